@@ -1,3 +1,4 @@
+import { invoke } from "@tauri-apps/api/core";
 import Database from "@tauri-apps/plugin-sql";
 import type { IHighlight, Content, ScaledPosition } from "../react-pdf-highlighter";
 
@@ -7,6 +8,7 @@ export interface PdfRecord {
   path: string;
   date_added: string;
   last_opened: string;
+  sha256: string | null;
 }
 
 export interface HighlightRecord {
@@ -15,11 +17,15 @@ export interface HighlightRecord {
   highlight_id: string;
   content_text: string | null;
   content_image: string | null;
+  // Set instead of content_image once the image is moved to a file
+  content_image_path: string | null;
   comment_text: string | null;
   comment_emoji: string | null;
   position_data: string;
   page_number: number;
   created_at: string;
+  color: string | null;
+  deleted_at: string | null;
 }
 
 export interface Tag {
@@ -111,9 +117,9 @@ class DatabaseService {
     return result.length > 0 ? result[0] : null;
   }
 
+  // The backend also removes the files of externalized images
   async deletePdf(id: number): Promise<void> {
-    await this.ensureInitialized();
-    await this.db!.execute("DELETE FROM pdfs WHERE id = ?", [id]);
+    await invoke("delete_pdf", { pdfId: id, deleteFile: false });
   }
 
   // Highlight operations
@@ -139,11 +145,11 @@ class DatabaseService {
   async getHighlightsForPdf(pdfId: number): Promise<IHighlight[]> {
     await this.ensureInitialized();
     const result = await this.db!.select<HighlightRecord[]>(
-      "SELECT * FROM highlights WHERE pdf_id = ? ORDER BY created_at DESC",
+      "SELECT * FROM highlights WHERE pdf_id = ? AND deleted_at IS NULL ORDER BY created_at DESC",
       [pdfId]
     );
 
-    return result.map(this.highlightRecordToIHighlight);
+    return this.toHighlights(result);
   }
 
   async updateHighlight(
@@ -155,7 +161,7 @@ class DatabaseService {
     
     // Get current highlight to merge changes
     const current = await this.db!.select<HighlightRecord[]>(
-      "SELECT * FROM highlights WHERE highlight_id = ? LIMIT 1",
+      "SELECT * FROM highlights WHERE highlight_id = ? AND deleted_at IS NULL LIMIT 1",
       [highlightId]
     );
 
@@ -185,31 +191,42 @@ class DatabaseService {
   ): Promise<void> {
     await this.ensureInitialized();
     await this.db!.execute(
-      "UPDATE highlights SET comment_text = ?, comment_emoji = ? WHERE highlight_id = ?",
+      "UPDATE highlights SET comment_text = ?, comment_emoji = ? WHERE highlight_id = ? AND deleted_at IS NULL",
       [commentText, commentEmoji, highlightId]
     );
   }
 
+  // Moves the highlight to the trash, from which it can be restored
   async deleteHighlight(highlightId: string): Promise<void> {
-    await this.ensureInitialized();
-    await this.db!.execute("DELETE FROM highlights WHERE highlight_id = ?", [highlightId]);
+    await invoke("soft_delete_highlight", { highlightId });
   }
 
   async getHighlightCountForPdf(pdfId: number): Promise<number> {
     await this.ensureInitialized();
     const result = await this.db!.select<{ count: number }[]>(
-      "SELECT COUNT(*) as count FROM highlights WHERE pdf_id = ?",
+      "SELECT COUNT(*) as count FROM highlights WHERE pdf_id = ? AND deleted_at IS NULL",
       [pdfId]
     );
     return result[0]?.count || 0;
   }
 
-  private highlightRecordToIHighlight(record: HighlightRecord): IHighlight {
+  // Images moved out of the database are read back from their files by the backend
+  private async toHighlights(records: HighlightRecord[]): Promise<IHighlight[]> {
+    const external = records
+      .filter(record => !record.content_image && record.content_image_path)
+      .map(record => record.highlight_id);
+    const images: Record<string, string> = external.length > 0
+      ? await invoke<Record<string, string>>("resolve_highlight_images", { highlightIds: external })
+      : {};
+    return records.map(record => this.highlightRecordToIHighlight(record, images[record.highlight_id]));
+  }
+
+  private highlightRecordToIHighlight(record: HighlightRecord, externalImage?: string): IHighlight {
     return {
       id: record.highlight_id,
       content: {
         text: record.content_text || undefined,
-        image: record.content_image || undefined,
+        image: record.content_image || externalImage || undefined,
       },
       comment: {
         text: record.comment_text || "",
@@ -292,7 +309,7 @@ class DatabaseService {
       `SELECT DISTINCT t.* FROM tags t
        INNER JOIN highlight_tags ht ON t.id = ht.tag_id
        INNER JOIN highlights h ON ht.highlight_id = h.highlight_id
-       WHERE h.pdf_id = ?
+       WHERE h.pdf_id = ? AND h.deleted_at IS NULL
        ORDER BY t.name ASC`,
       [pdfId]
     );
@@ -356,6 +373,7 @@ class DatabaseService {
       `SELECT t.*, COUNT(ht.highlight_id) as usage_count
        FROM tags t
        LEFT JOIN highlight_tags ht ON t.id = ht.tag_id
+         AND ht.highlight_id IN (SELECT highlight_id FROM highlights WHERE deleted_at IS NULL)
        GROUP BY t.id, t.name, t.created_at
        ORDER BY t.name ASC`
     );
@@ -371,7 +389,7 @@ class DatabaseService {
     await this.ensureInitialized();
     try {
       const result = await this.db!.select<{ count: number }[]>(
-        "SELECT COUNT(*) as count FROM highlights WHERE highlight_id = ?",
+        "SELECT COUNT(*) as count FROM highlights WHERE highlight_id = ? AND deleted_at IS NULL",
         [highlightId]
       );
       const exists = result[0]?.count > 0;
@@ -470,7 +488,7 @@ class DatabaseService {
     await this.ensureInitialized();
     try {
       const result = await this.db!.select<{ pdf_id: number }[]>(
-        `SELECT pdf_id FROM highlights WHERE highlight_id = ?`,
+        `SELECT pdf_id FROM highlights WHERE highlight_id = ? AND deleted_at IS NULL`,
         [highlightId]
       );
       return result.length > 0 ? result[0].pdf_id : null;
@@ -489,7 +507,7 @@ class DatabaseService {
          FROM tags t
          INNER JOIN highlight_tags ht ON t.id = ht.tag_id
          INNER JOIN highlights h ON ht.highlight_id = h.highlight_id
-         WHERE h.pdf_id = ?
+         WHERE h.pdf_id = ? AND h.deleted_at IS NULL
          GROUP BY t.id, t.name, t.created_at
          ORDER BY usage_count DESC, t.name ASC`,
         [pdfId]
@@ -509,7 +527,7 @@ class DatabaseService {
     let query = `
       SELECT h.* FROM highlights h
       INNER JOIN highlight_tags ht ON h.highlight_id = ht.highlight_id
-      WHERE ht.tag_id = ?
+      WHERE ht.tag_id = ? AND h.deleted_at IS NULL
     `;
     const params: any[] = [tagId];
     
@@ -521,7 +539,7 @@ class DatabaseService {
     query += " ORDER BY h.created_at DESC";
     
     const result = await this.db!.select<HighlightRecord[]>(query, params);
-    return result.map(this.highlightRecordToIHighlight);
+    return this.toHighlights(result);
   }
 
   async searchHighlightsByTags(tagNames: string[], pdfId?: number): Promise<IHighlight[]> {
@@ -536,7 +554,7 @@ class DatabaseService {
       SELECT DISTINCT h.* FROM highlights h
       INNER JOIN highlight_tags ht ON h.highlight_id = ht.highlight_id
       INNER JOIN tags t ON ht.tag_id = t.id
-      WHERE t.name IN (${placeholders})
+      WHERE t.name IN (${placeholders}) AND h.deleted_at IS NULL
     `;
     const params: any[] = [...tagNames];
     
@@ -548,7 +566,7 @@ class DatabaseService {
     query += " ORDER BY h.created_at DESC";
     
     const result = await this.db!.select<HighlightRecord[]>(query, params);
-    return result.map(this.highlightRecordToIHighlight);
+    return this.toHighlights(result);
   }
 
   async getTagUsageStats(): Promise<{ tag: Tag; count: number }[]> {
@@ -557,6 +575,7 @@ class DatabaseService {
       `SELECT t.*, COUNT(ht.highlight_id) as count
        FROM tags t
        LEFT JOIN highlight_tags ht ON t.id = ht.tag_id
+         AND ht.highlight_id IN (SELECT highlight_id FROM highlights WHERE deleted_at IS NULL)
        GROUP BY t.id, t.name, t.created_at
        ORDER BY count DESC, t.name ASC`
     );
@@ -588,6 +607,8 @@ class DatabaseService {
         `SELECT t.*, COUNT(DISTINCT ht.highlight_id) as usage_count
          FROM tags t
          INNER JOIN highlight_tags ht ON t.id = ht.tag_id
+         INNER JOIN highlights h ON ht.highlight_id = h.highlight_id
+         WHERE h.deleted_at IS NULL
          GROUP BY t.id, t.name, t.created_at
          ORDER BY usage_count DESC, t.name ASC
          LIMIT ?`,
@@ -611,6 +632,8 @@ class DatabaseService {
         `SELECT DISTINCT t.*, MAX(tuh.used_at) as last_used_at
          FROM tags t
          INNER JOIN tag_usage_history tuh ON t.id = tuh.tag_id
+         INNER JOIN highlights h ON tuh.highlight_id = h.highlight_id
+         WHERE h.deleted_at IS NULL
          GROUP BY t.id, t.name, t.created_at
          ORDER BY last_used_at DESC
          LIMIT ?`,
@@ -629,6 +652,7 @@ class DatabaseService {
            FROM tags t
            INNER JOIN highlight_tags ht ON t.id = ht.tag_id
            INNER JOIN highlights h ON ht.highlight_id = h.highlight_id
+           WHERE h.deleted_at IS NULL
            GROUP BY t.id, t.name, t.created_at
            ORDER BY last_used_at DESC
            LIMIT ?`,
@@ -655,7 +679,7 @@ class DatabaseService {
          FROM tags t
          INNER JOIN highlight_tags ht ON t.id = ht.tag_id
          INNER JOIN highlights h ON ht.highlight_id = h.highlight_id
-         WHERE h.pdf_id = ?
+         WHERE h.pdf_id = ? AND h.deleted_at IS NULL
          GROUP BY t.id, t.name, t.created_at
          ORDER BY usage_count DESC, t.name ASC
          LIMIT ?`,
@@ -680,7 +704,7 @@ class DatabaseService {
          FROM tags t
          INNER JOIN tag_usage_history tuh ON t.id = tuh.tag_id
          INNER JOIN highlights h ON tuh.highlight_id = h.highlight_id
-         WHERE h.pdf_id = ?
+         WHERE h.pdf_id = ? AND h.deleted_at IS NULL
          GROUP BY t.id, t.name, t.created_at
          ORDER BY last_used_at DESC
          LIMIT ?`,
@@ -699,7 +723,7 @@ class DatabaseService {
            FROM tags t
            INNER JOIN highlight_tags ht ON t.id = ht.tag_id
            INNER JOIN highlights h ON ht.highlight_id = h.highlight_id
-           WHERE h.pdf_id = ?
+           WHERE h.pdf_id = ? AND h.deleted_at IS NULL
            GROUP BY t.id, t.name, t.created_at
            ORDER BY last_used_at DESC
            LIMIT ?`,
@@ -739,6 +763,7 @@ class DatabaseService {
        LEFT JOIN (
          SELECT pdf_id, COUNT(*) as highlight_count
          FROM highlights
+         WHERE deleted_at IS NULL
          GROUP BY pdf_id
        ) h ON p.id = h.pdf_id
        ${orderByClause}`
@@ -767,7 +792,7 @@ class DatabaseService {
       
       // Check data integrity
       const pdfCount = await this.db!.select<{ count: number }[]>("SELECT COUNT(*) as count FROM pdfs");
-      const highlightCount = await this.db!.select<{ count: number }[]>("SELECT COUNT(*) as count FROM highlights");
+      const highlightCount = await this.db!.select<{ count: number }[]>("SELECT COUNT(*) as count FROM highlights WHERE deleted_at IS NULL");
       const tagCount = await this.db!.select<{ count: number }[]>("SELECT COUNT(*) as count FROM tags");
       const relationshipCount = await this.db!.select<{ count: number }[]>("SELECT COUNT(*) as count FROM highlight_tags");
       
@@ -0,0 +1,350 @@
+//! Log of destructive operations, so the most recent one can be undone.
+//!
+//! Commands record an `Action` in the same transaction as the change. Deleted
+//! rows are kept as column name → value snapshots, so undoing restores every
+//! column, ids included, without this module knowing the schema.
+
+use rusqlite::types::{Value as SqlValue, ValueRef};
+use rusqlite::{params, params_from_iter, Connection, OptionalExtension, Params};
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+use tauri::AppHandle;
+
+use crate::error::AppError;
+use crate::{db, images};
+
+/// Entries kept in `action_log`. Snapshots can hold whole PDFs' worth of
+/// highlights, images included, so older entries are dropped.
+const LOG_SIZE: i64 = 50;
+
+/// A row as column name → value.
+pub type RowSnapshot = Map<String, Value>;
+
+/// Highlights of a PDF along with their tag links.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct HighlightsSnapshot {
+  pub highlights: Vec<RowSnapshot>,
+  pub highlight_tags: Vec<RowSnapshot>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum Action {
+  /// A highlight moved to the trash.
+  TrashHighlight {
+    highlight_id: String,
+  },
+  /// Every highlight of a PDF deleted.
+  DeleteHighlights {
+    pdf_id: i64,
+    snapshot: HighlightsSnapshot,
+  },
+  /// A PDF removed from the library, its file kept.
+  DeletePdf {
+    pdf: RowSnapshot,
+    open_events: Vec<RowSnapshot>,
+    snapshot: HighlightsSnapshot,
+  },
+  /// A PDF removed from the library along with its file, which can't be
+  /// brought back.
+  DeletePdfAndFile {
+    pdf_id: i64,
+    name: String,
+  },
+  PurgeTrash {
+    purged: usize,
+  },
+  MergePdfs {
+    source_pdf_id: i64,
+    target_pdf_id: i64,
+    moved: usize,
+  },
+  MergeTags {
+    source_tag_ids: Vec<i64>,
+    target_tag_id: i64,
+    moved: usize,
+  },
+}
+
+impl Action {
+  fn kind(&self) -> &'static str {
+    match self {
+      Action::TrashHighlight { .. } => "trash_highlight",
+      Action::DeleteHighlights { .. } => "delete_highlights",
+      Action::DeletePdf { .. } => "delete_pdf",
+      Action::DeletePdfAndFile { .. } => "delete_pdf_and_file",
+      Action::PurgeTrash { .. } => "purge_trash",
+      Action::MergePdfs { .. } => "merge_pdfs",
+      Action::MergeTags { .. } => "merge_tags",
+    }
+  }
+
+  fn reversible(&self) -> bool {
+    matches!(
+      self,
+      Action::TrashHighlight { .. } | Action::DeleteHighlights { .. } | Action::DeletePdf { .. }
+    )
+  }
+}
+
+/// Logs `action`. Call it inside the transaction making the change, so the
+/// entry exists exactly when the change does.
+pub fn record(conn: &Connection, action: &Action) -> rusqlite::Result<()> {
+  let payload =
+    serde_json::to_string(action).map_err(|e| rusqlite::Error::ToSqlConversionFailure(e.into()))?;
+  conn.execute(
+    "INSERT INTO action_log (kind, payload, reversible) VALUES (?1, ?2, ?3)",
+    params![action.kind(), payload, action.reversible()],
+  )?;
+  conn.execute(
+    "DELETE FROM action_log WHERE id <= last_insert_rowid() - ?1",
+    params![LOG_SIZE],
+  )?;
+  Ok(())
+}
+
+fn json_value(value: ValueRef) -> Value {
+  match value {
+    ValueRef::Null => Value::Null,
+    ValueRef::Integer(i) => i.into(),
+    ValueRef::Real(f) => f.into(),
+    ValueRef::Text(text) => String::from_utf8_lossy(text).into_owned().into(),
+    ValueRef::Blob(bytes) => bytes.to_vec().into(),
+  }
+}
+
+fn sql_value(value: &Value) -> SqlValue {
+  match value {
+    Value::Null => SqlValue::Null,
+    Value::Bool(b) => SqlValue::Integer(i64::from(*b)),
+    Value::Number(n) => match n.as_i64() {
+      Some(i) => SqlValue::Integer(i),
+      None => SqlValue::Real(n.as_f64().unwrap_or_default()),
+    },
+    Value::String(text) => SqlValue::Text(text.clone()),
+    // Blobs, see `json_value`
+    Value::Array(bytes) => SqlValue::Blob(
+      bytes
+        .iter()
+        .filter_map(Value::as_u64)
+        .map(|b| b as u8)
+        .collect(),
+    ),
+    Value::Object(_) => SqlValue::Text(value.to_string()),
+  }
+}
+
+fn snapshot<P: Params>(
+  conn: &Connection,
+  sql: &str,
+  params: P,
+) -> rusqlite::Result<Vec<RowSnapshot>> {
+  let mut stmt = conn.prepare(sql)?;
+  let columns: Vec<String> = stmt.column_names().into_iter().map(String::from).collect();
+  let mut rows = stmt.query(params)?;
+  let mut snapshots = Vec::new();
+  while let Some(row) = rows.next()? {
+    let mut snapshot = RowSnapshot::new();
+    for (i, column) in columns.iter().enumerate() {
+      snapshot.insert(column.clone(), json_value(row.get_ref(i)?));
+    }
+    snapshots.push(snapshot);
+  }
+  Ok(snapshots)
+}
+
+fn reinsert(conn: &Connection, table: &str, rows: &[RowSnapshot]) -> rusqlite::Result<()> {
+  for row in rows {
+    let columns = row
+      .keys()
+      .map(|column| format!("\"{}\"", column.replace('"', "\"\"")))
+      .collect::<Vec<_>>()
+      .join(", ");
+    let placeholders = vec!["?"; row.len()].join(", ");
+    conn.execute(
+      &format!(
+        "INSERT INTO {} ({}) VALUES ({})",
+        table, columns, placeholders
+      ),
+      params_from_iter(row.values().map(sql_value)),
+    )?;
+  }
+  Ok(())
+}
+
+/// The highlights of a PDF, trashed ones included, and their tag links, taken
+/// before they are deleted. Externalized images are taken inline, since their
+/// files are deleted along with the highlights.
+pub fn snapshot_highlights(conn: &Connection, pdf_id: i64) -> rusqlite::Result<HighlightsSnapshot> {
+  let mut highlights = snapshot(
+    conn,
+    "SELECT * FROM highlights WHERE pdf_id = ?1",
+    params![pdf_id],
+  )?;
+  let images_dir = images::images_dir(conn)?;
+  for row in &mut highlights {
+    let Some(file) = row
+      .get("content_image_path")
+      .and_then(Value::as_str)
+      .map(str::to_owned)
+    else {
+      continue;
+    };
+    match images::inline(images_dir.as_deref(), &file) {
+      Ok(image) => {
+        row.insert("content_image".to_string(), image.into());
+        row.insert("content_image_path".to_string(), Value::Null);
+      }
+      Err(e) => log::warn!("Undo of the deletion will lack an image: {}", e),
+    }
+  }
+  Ok(HighlightsSnapshot {
+    highlights,
+    highlight_tags: snapshot(
+      conn,
+      "SELECT * FROM highlight_tags
+       WHERE highlight_id IN (SELECT highlight_id FROM highlights WHERE pdf_id = ?1)",
+      params![pdf_id],
+    )?,
+  })
+}
+
+/// A PDF's row and open history, taken before it is deleted.
+pub fn snapshot_pdf(
+  conn: &Connection,
+  pdf_id: i64,
+) -> rusqlite::Result<(RowSnapshot, Vec<RowSnapshot>)> {
+  let pdf = snapshot(conn, "SELECT * FROM pdfs WHERE id = ?1", params![pdf_id])?
+    .pop()
+    .ok_or(rusqlite::Error::QueryReturnedNoRows)?;
+  let open_events = snapshot(
+    conn,
+    "SELECT * FROM pdf_open_events WHERE pdf_id = ?1",
+    params![pdf_id],
+  )?;
+  Ok((pdf, open_events))
+}
+
+fn pdf_name(conn: &Connection, pdf_id: i64) -> rusqlite::Result<Option<String>> {
+  conn
+    .query_row(
+      "SELECT name FROM pdfs WHERE id = ?1",
+      params![pdf_id],
+      |row| row.get(0),
+    )
+    .optional()
+}
+
+/// Puts the highlights back. Links to tags deleted since are left out.
+fn restore_highlights(conn: &Connection, snapshot: &HighlightsSnapshot) -> Result<(), AppError> {
+  reinsert(conn, "highlights", &snapshot.highlights)?;
+  let mut tag_exists = conn.prepare("SELECT 1 FROM tags WHERE id = ?1")?;
+  for link in &snapshot.highlight_tags {
+    let tag_id = link.get("tag_id").map(sql_value).unwrap_or(SqlValue::Null);
+    if tag_exists.exists(params![tag_id])? {
+      reinsert(conn, "highlight_tags", std::slice::from_ref(link))?;
+    }
+  }
+  Ok(())
+}
+
+/// Reverses `action` and describes what was undone. `AppError::Conflict`
+/// means the library has changed in a way that rules the undo out.
+fn undo(conn: &Connection, action: &Action) -> Result<String, AppError> {
+  match action {
+    Action::TrashHighlight { highlight_id } => {
+      let restored = conn.execute(
+        "UPDATE highlights SET deleted_at = NULL
+         WHERE highlight_id = ?1 AND deleted_at IS NOT NULL",
+        params![highlight_id],
+      )?;
+      if restored == 0 {
+        return Err(AppError::Conflict(format!(
+          "Highlight {} is no longer in the trash",
+          highlight_id
+        )));
+      }
+      Ok(format!(
+        "Restored highlight {} from the trash",
+        highlight_id
+      ))
+    }
+    Action::DeleteHighlights { pdf_id, snapshot } => {
+      let name = pdf_name(conn, *pdf_id)?
+        .ok_or_else(|| AppError::Conflict(format!("PDF {} no longer exists", pdf_id)))?;
+      restore_highlights(conn, snapshot)?;
+      Ok(format!(
+        "Restored {} deleted highlights of {}",
+        snapshot.highlights.len(),
+        name
+      ))
+    }
+    Action::DeletePdf {
+      pdf,
+      open_events,
+      snapshot,
+    } => {
+      reinsert(conn, "pdfs", std::slice::from_ref(pdf))?;
+      reinsert(conn, "pdf_open_events", open_events)?;
+      restore_highlights(conn, snapshot)?;
+      Ok(format!(
+        "Restored {} with {} highlights",
+        pdf.get("name").and_then(Value::as_str).unwrap_or("the PDF"),
+        snapshot.highlights.len()
+      ))
+    }
+    _ => Err(AppError::Unsupported(format!(
+      "{} can't be undone",
+      action.kind()
+    ))),
+  }
+}
+
+/// Reverses the most recent action that can be undone and hasn't been yet,
+/// and returns a description of what was undone. Merges, purging the trash
+/// and deleting a PDF's file are logged but can't be undone. An action the
+/// library has since moved past, e.g. a trashed highlight restored by hand,
+/// fails with `AppError::Conflict` and is marked as no longer reversible, so
+/// the next undo goes further back.
+#[tauri::command]
+pub async fn undo_last_action(app: AppHandle) -> Result<String, AppError> {
+  let mut conn = db::open(&app)?;
+  let tx = conn.transaction()?;
+  let (id, payload): (i64, String) = tx
+    .query_row(
+      "SELECT id, payload FROM action_log
+       WHERE reversible = 1 AND undone_at IS NULL
+       ORDER BY id DESC LIMIT 1",
+      [],
+      |row| Ok((row.get(0)?, row.get(1)?)),
+    )
+    .optional()?
+    .ok_or_else(|| AppError::NotFound("Nothing to undo".to_string()))?;
+  let action: Action = serde_json::from_str(&payload)
+    .map_err(|e| AppError::Corrupt(format!("Action {} has an unreadable payload: {}", id, e)))?;
+
+  match undo(&tx, &action) {
+    Ok(description) => {
+      tx.execute(
+        "UPDATE action_log SET undone_at = CURRENT_TIMESTAMP WHERE id = ?1",
+        params![id],
+      )?;
+      tx.commit()?;
+      log::info!("Undid action {}: {}", id, description);
+      Ok(description)
+    }
+    Err(AppError::Conflict(reason)) => {
+      tx.rollback()?;
+      conn.execute(
+        "UPDATE action_log SET reversible = 0 WHERE id = ?1",
+        params![id],
+      )?;
+      log::warn!("Action {} can no longer be undone: {}", id, reason);
+      Err(AppError::Conflict(format!(
+        "The last action can no longer be undone: {}",
+        reason
+      )))
+    }
+    Err(e) => Err(e),
+  }
+}
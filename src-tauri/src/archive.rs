@@ -0,0 +1,326 @@
+//! ZIP archives of the library: the bundle of `export_library` together with
+//! the PDF files it refers to, so a library can move to another machine.
+
+use rusqlite::Connection;
+use serde::Serialize;
+use std::collections::HashMap;
+use std::fs::{self, File};
+use std::io::{self, BufReader, BufWriter, Read, Seek, Write};
+use std::path::{Path, PathBuf};
+use tauri::AppHandle;
+use zip::write::SimpleFileOptions;
+use zip::{CompressionMethod, ZipArchive, ZipWriter};
+
+use crate::error::AppError;
+use crate::library::{self, ImportReport, ImportStrategy, LibraryBundle, PdfRow, Progress};
+use crate::{db, pdfs};
+
+/// Entry holding the library bundle.
+pub const LIBRARY_ENTRY: &str = "library.json";
+
+/// Folder of the archive holding the PDFs. Bundle rows of the PDFs in it have
+/// a path relative to the archive root, e.g. `pdfs/3-paper.pdf`.
+pub const PDF_DIR: &str = "pdfs/";
+
+#[derive(Debug, Default, Serialize)]
+pub struct BundleExportReport {
+  /// PDFs copied into the archive.
+  pub copied: usize,
+  /// PDFs that couldn't be read and were left out. Their bundle rows keep
+  /// the original path.
+  pub missing: Vec<String>,
+}
+
+/// Entry name for a PDF, prefixed with its id since file names needn't be
+/// unique across the library.
+fn entry_name(pdf_id: i64, path: &str) -> String {
+  let file_name = Path::new(path)
+    .file_name()
+    .map(|name| name.to_string_lossy().into_owned())
+    .unwrap_or_else(|| "document.pdf".to_string());
+  format!("{}{}-{}", PDF_DIR, pdf_id, file_name)
+}
+
+/// Copies every readable PDF of the library into the archive and returns the
+/// entry name of each, by PDF id.
+fn write_pdfs<W: Write + Seek>(
+  zip: &mut ZipWriter<W>,
+  conn: &Connection,
+  report: &mut BundleExportReport,
+) -> Result<HashMap<i64, String>, AppError> {
+  let pdfs = conn
+    .prepare("SELECT id, path FROM pdfs ORDER BY id")?
+    .query_map([], |row| {
+      Ok((row.get::<_, i64>(0)?, row.get::<_, String>(1)?))
+    })?
+    .collect::<rusqlite::Result<Vec<_>>>()?;
+
+  // PDFs are compressed already; deflating them again only costs time
+  let options = SimpleFileOptions::default().compression_method(CompressionMethod::Stored);
+  let mut entries = HashMap::new();
+  for (id, path) in pdfs {
+    let mut file = match File::open(&path) {
+      Ok(file) => file,
+      Err(e) => {
+        log::warn!("Leaving {} out of the archive: {}", path, e);
+        report.missing.push(path);
+        continue;
+      }
+    };
+    let name = entry_name(id, &path);
+    zip.start_file(name.as_str(), options)?;
+    io::copy(&mut file, zip)
+      .map_err(|e| AppError::Io(format!("Cannot copy {} into the archive: {}", path, e)))?;
+    entries.insert(id, name);
+    report.copied += 1;
+  }
+  Ok(entries)
+}
+
+fn write_archive(
+  conn: &Connection,
+  dest: &Path,
+  include_pdfs: bool,
+) -> Result<BundleExportReport, AppError> {
+  let file = File::create(dest)
+    .map_err(|e| AppError::Io(format!("Cannot create {}: {}", dest.display(), e)))?;
+  let mut zip = ZipWriter::new(BufWriter::new(file));
+  let mut report = BundleExportReport::default();
+
+  let entries = if include_pdfs {
+    write_pdfs(&mut zip, conn, &mut report)?
+  } else {
+    HashMap::new()
+  };
+  zip.start_file(
+    LIBRARY_ENTRY,
+    SimpleFileOptions::default().compression_method(CompressionMethod::Deflated),
+  )?;
+  library::write_bundle_with(conn, &mut zip, |pdf| {
+    if let Some(entry) = entries.get(&pdf.id) {
+      pdf.path = entry.clone();
+    }
+  })?;
+  zip.finish()?.flush()?;
+  Ok(report)
+}
+
+fn export_file(
+  app: &AppHandle,
+  dest: &Path,
+  include_pdfs: bool,
+) -> Result<BundleExportReport, AppError> {
+  let mut conn = db::open(app)?;
+  // One read transaction, so the PDFs copied are the ones the bundle lists
+  let tx = conn.transaction()?;
+  let report = match write_archive(&tx, dest, include_pdfs) {
+    Ok(report) => report,
+    Err(e) => {
+      let _ = fs::remove_file(dest);
+      return Err(e);
+    }
+  };
+  tx.commit()?;
+  Ok(report)
+}
+
+/// Writes the library to a ZIP archive at `dest_path`: the bundle of
+/// `export_library` and, if `include_pdfs` is set, a copy of every PDF with
+/// the bundle's paths pointing into the archive. PDFs that can't be read are
+/// left out and listed in the report rather than failing the export.
+#[tauri::command]
+pub async fn export_bundle(
+  app: AppHandle,
+  dest_path: String,
+  include_pdfs: bool,
+) -> Result<BundleExportReport, AppError> {
+  let dest = PathBuf::from(&dest_path);
+  // Copying every PDF of a large library takes a while
+  let report = tauri::async_runtime::spawn_blocking(move || export_file(&app, &dest, include_pdfs))
+    .await
+    .map_err(|e| format!("Export failed: {}", e))??;
+  log::info!("Library archived to {}: {:?}", dest_path, report);
+  Ok(report)
+}
+
+#[derive(Debug, Serialize)]
+pub struct BundleImportReport {
+  /// PDFs written to the destination folder.
+  pub extracted: usize,
+  pub imported: ImportReport,
+}
+
+/// A path in `dir` for `file_name` that isn't taken, numbering the name like
+/// "paper (1).pdf" if needed.
+fn free_path(dir: &Path, file_name: &str) -> PathBuf {
+  let path = dir.join(file_name);
+  if !path.exists() {
+    return path;
+  }
+  let name = Path::new(file_name);
+  let stem = name
+    .file_stem()
+    .map(|stem| stem.to_string_lossy().into_owned())
+    .unwrap_or_default();
+  let extension = name
+    .extension()
+    .map(|extension| format!(".{}", extension.to_string_lossy()))
+    .unwrap_or_default();
+  (1..)
+    .map(|n| dir.join(format!("{} ({}){}", stem, n, extension)))
+    .find(|path| !path.exists())
+    .expect("some numbered name is free")
+}
+
+/// Extracts the archived PDF of `pdf` into `dest_dir` and points its path
+/// there. Returns the file written, or `None` if an identical file was
+/// already there and is reused.
+fn extract_pdf<R: Read + Seek>(
+  zip: &mut ZipArchive<R>,
+  pdf: &mut PdfRow,
+  dest_dir: &Path,
+) -> Result<Option<PathBuf>, AppError> {
+  // Only the file name of the entry is used, so entries can't be written
+  // outside `dest_dir`.
+  let file_name = Path::new(&pdf.path)
+    .file_name()
+    .map(|name| name.to_string_lossy().into_owned())
+    .ok_or_else(|| AppError::Corrupt(format!("Bad PDF entry name {}", pdf.path)))?;
+  let file_name = file_name
+    .strip_prefix(&format!("{}-", pdf.id))
+    .unwrap_or(&file_name)
+    .to_string();
+
+  let existing = dest_dir.join(&file_name);
+  if let Some(sha256) = &pdf.sha256 {
+    if existing.is_file() && pdfs::sha256_file(&existing).ok().as_ref() == Some(sha256) {
+      pdf.path = existing.to_string_lossy().into_owned();
+      return Ok(None);
+    }
+  }
+
+  let mut entry = zip
+    .by_name(&pdf.path)
+    .map_err(|e| AppError::Corrupt(format!("Archive is missing {}: {}", pdf.path, e)))?;
+  let dest = free_path(dest_dir, &file_name);
+  let mut file = File::create(&dest)
+    .map_err(|e| AppError::Io(format!("Cannot create {}: {}", dest.display(), e)))?;
+  let copied = io::copy(&mut entry, &mut file);
+  drop(file);
+  if let Err(e) = copied {
+    let _ = fs::remove_file(&dest);
+    return Err(AppError::Io(format!("Cannot extract {}: {}", pdf.path, e)));
+  }
+
+  if let Some(sha256) = &pdf.sha256 {
+    if pdfs::sha256_file(&dest)? != *sha256 {
+      let _ = fs::remove_file(&dest);
+      return Err(AppError::Corrupt(format!(
+        "{} in the archive doesn't match its checksum",
+        pdf.path
+      )));
+    }
+  }
+  pdf.path = dest.to_string_lossy().into_owned();
+  Ok(Some(dest))
+}
+
+/// Extracts every archived PDF of the bundle, rewriting the paths of their
+/// rows, and returns the files written.
+fn extract_pdfs<R: Read + Seek>(
+  zip: &mut ZipArchive<R>,
+  bundle: &mut LibraryBundle,
+  dest_dir: &Path,
+) -> Result<Vec<PathBuf>, AppError> {
+  fs::create_dir_all(dest_dir)
+    .map_err(|e| AppError::Io(format!("Cannot create {}: {}", dest_dir.display(), e)))?;
+  let mut extracted = Vec::new();
+  for pdf in &mut bundle.pdfs {
+    // Rows of PDFs left out of the archive keep their original path
+    if !pdf.path.starts_with(PDF_DIR) {
+      continue;
+    }
+    match extract_pdf(zip, pdf, dest_dir) {
+      Ok(Some(path)) => extracted.push(path),
+      Ok(None) => {}
+      Err(e) => {
+        remove_all(&extracted);
+        return Err(e);
+      }
+    }
+  }
+  Ok(extracted)
+}
+
+fn remove_all(paths: &[PathBuf]) {
+  for path in paths {
+    let _ = fs::remove_file(path);
+  }
+}
+
+fn import_rows(
+  app: &AppHandle,
+  bundle: &LibraryBundle,
+  strategy: ImportStrategy,
+) -> Result<ImportReport, AppError> {
+  let mut progress = Progress::new(app, bundle);
+  let mut conn = db::open(app)?;
+  let tx = conn.transaction()?;
+  let report = library::import(&tx, bundle, strategy, &mut progress)?;
+  tx.commit()?;
+  Ok(report)
+}
+
+fn import_file(
+  app: &AppHandle,
+  zip_path: &Path,
+  dest_dir: &Path,
+  strategy: ImportStrategy,
+) -> Result<BundleImportReport, AppError> {
+  let file = File::open(zip_path)
+    .map_err(|e| AppError::Io(format!("Cannot open {}: {}", zip_path.display(), e)))?;
+  let mut zip = ZipArchive::new(BufReader::new(file))?;
+  let mut bundle: LibraryBundle =
+    serde_json::from_reader(BufReader::new(zip.by_name(LIBRARY_ENTRY)?)).map_err(|e| {
+      AppError::InvalidInput(format!(
+        "{} has no valid library bundle: {}",
+        zip_path.display(),
+        e
+      ))
+    })?;
+  library::check_bundle(&bundle).map_err(AppError::InvalidInput)?;
+
+  let extracted = extract_pdfs(&mut zip, &mut bundle, dest_dir)?;
+  match import_rows(app, &bundle, strategy) {
+    Ok(imported) => Ok(BundleImportReport {
+      extracted: extracted.len(),
+      imported,
+    }),
+    // The library is untouched, so the extracted files would be strays
+    Err(e) => {
+      remove_all(&extracted);
+      Err(e)
+    }
+  }
+}
+
+/// Imports an archive written by `export_bundle`. Its PDFs are extracted into
+/// `pdf_dest_dir`, checked against their stored hash and imported with their
+/// new paths; the rows are imported as by `import_library`. If anything
+/// fails, the library is left untouched and the extracted files are removed.
+#[tauri::command]
+pub async fn import_bundle(
+  app: AppHandle,
+  zip_path: String,
+  pdf_dest_dir: String,
+  strategy: ImportStrategy,
+) -> Result<BundleImportReport, AppError> {
+  let path = PathBuf::from(&zip_path);
+  let dest_dir = PathBuf::from(pdf_dest_dir);
+  let report =
+    tauri::async_runtime::spawn_blocking(move || import_file(&app, &path, &dest_dir, strategy))
+      .await
+      .map_err(|e| format!("Import failed: {}", e))??;
+  log::info!("Library imported from {}: {:?}", zip_path, report);
+  Ok(report)
+}
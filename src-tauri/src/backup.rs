@@ -0,0 +1,256 @@
+use rusqlite::backup::Backup;
+use rusqlite::{Connection, OpenFlags};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use tauri::AppHandle;
+
+use crate::error::AppError;
+use crate::{db, migrations};
+
+/// Tables a database must contain to be accepted as a backup of this app.
+const REQUIRED_TABLES: [&str; 4] = ["pdfs", "highlights", "tags", "highlight_tags"];
+
+/// Number of pages copied per backup step. Between steps the source database
+/// is unlocked, so the app keeps working while a large backup runs.
+const PAGES_PER_STEP: std::os::raw::c_int = 256;
+
+fn backup_file_name() -> String {
+  let stem = db::DB_FILE.trim_end_matches(".db");
+  format!(
+    "{}-{}.db",
+    stem,
+    chrono::Local::now().format("%Y%m%d-%H%M%S")
+  )
+}
+
+fn check_dest_dir(dest_dir: &Path) -> Result<(), AppError> {
+  let metadata = fs::metadata(dest_dir).map_err(|e| {
+    let message = format!(
+      "Backup directory {} is not accessible: {}",
+      dest_dir.display(),
+      e
+    );
+    match e.kind() {
+      std::io::ErrorKind::NotFound => AppError::NotFound(message),
+      _ => AppError::Io(message),
+    }
+  })?;
+  if !metadata.is_dir() {
+    return Err(AppError::InvalidInput(format!(
+      "Backup destination {} is not a directory",
+      dest_dir.display()
+    )));
+  }
+  if metadata.permissions().readonly() {
+    return Err(AppError::Io(format!(
+      "Backup directory {} is not writable",
+      dest_dir.display()
+    )));
+  }
+  Ok(())
+}
+
+/// Copies the live database into `dest` using the SQLite Online Backup API,
+/// which yields a consistent snapshot even while other connections write.
+///
+/// The backup reads through `src`, so commits still sitting in the live WAL
+/// are part of the snapshot. The copy is switched out of WAL mode so it is a
+/// single self-contained file.
+fn backup_to(src: &Connection, dest: &Path) -> Result<(), AppError> {
+  let mut dst = Connection::open(dest).map_err(|e| {
+    AppError::Io(format!(
+      "Cannot create backup file {}: {}",
+      dest.display(),
+      e
+    ))
+  })?;
+  {
+    let backup = Backup::new(src, &mut dst)?;
+    backup
+      .run_to_completion(PAGES_PER_STEP, Duration::from_millis(10), None)
+      .map_err(|e| AppError::Io(format!("Backup to {} failed: {}", dest.display(), e)))?;
+  }
+  dst
+    .query_row("PRAGMA journal_mode=DELETE", [], |_| Ok(()))
+    .map_err(|e| AppError::Io(format!("Cannot finalize backup {}: {}", dest.display(), e)))
+}
+
+#[tauri::command]
+pub async fn backup_database(app: AppHandle, dest_dir: String) -> Result<String, AppError> {
+  let dest_dir = PathBuf::from(dest_dir);
+  check_dest_dir(&dest_dir)?;
+
+  let dest = dest_dir.join(backup_file_name());
+  if dest.exists() {
+    return Err(AppError::Conflict(format!(
+      "Backup file {} already exists",
+      dest.display()
+    )));
+  }
+
+  let conn = db::open(&app)?;
+  if let Err(e) = backup_to(&conn, &dest) {
+    // Don't leave a half-written copy behind that looks like a valid backup
+    let _ = fs::remove_file(&dest);
+    let _ = fs::remove_file(sidecar(&dest, "-wal"));
+    let _ = fs::remove_file(sidecar(&dest, "-shm"));
+    return Err(e);
+  }
+
+  log::info!("Database backed up to {}", dest.display());
+  Ok(dest.to_string_lossy().into_owned())
+}
+
+/// Checks that `path` is a SQLite database with our tables and a migration
+/// history this build of the app can continue from.
+fn validate_backup(path: &Path) -> Result<(), AppError> {
+  if !path.is_file() {
+    return Err(AppError::NotFound(format!(
+      "Backup {} not found",
+      path.display()
+    )));
+  }
+  let conn = Connection::open_with_flags(
+    path,
+    OpenFlags::SQLITE_OPEN_READ_ONLY | OpenFlags::SQLITE_OPEN_NO_MUTEX,
+  )
+  .map_err(|e| AppError::Io(format!("Cannot open backup {}: {}", path.display(), e)))?;
+
+  let tables = conn
+    .prepare("SELECT name FROM sqlite_master WHERE type = 'table'")
+    .and_then(|mut stmt| {
+      stmt
+        .query_map([], |row| row.get::<_, String>(0))?
+        .collect::<rusqlite::Result<Vec<_>>>()
+    })
+    .map_err(|e| {
+      AppError::InvalidInput(format!(
+        "{} is not a valid SQLite database: {}",
+        path.display(),
+        e
+      ))
+    })?;
+
+  let missing: Vec<_> = REQUIRED_TABLES
+    .iter()
+    .filter(|t| !tables.iter().any(|name| name == *t))
+    .copied()
+    .collect();
+  if !missing.is_empty() {
+    return Err(AppError::InvalidInput(format!(
+      "Backup is missing required tables: {}",
+      missing.join(", ")
+    )));
+  }
+  if !tables.iter().any(|name| name == "_sqlx_migrations") {
+    return Err(AppError::InvalidInput(
+      "Backup has no migration history".to_string(),
+    ));
+  }
+
+  let applied = conn
+    .prepare("SELECT version, description, success FROM _sqlx_migrations ORDER BY version")
+    .and_then(|mut stmt| {
+      stmt
+        .query_map([], |row| {
+          Ok((
+            row.get::<_, i64>(0)?,
+            row.get::<_, String>(1)?,
+            row.get::<_, bool>(2)?,
+          ))
+        })?
+        .collect::<rusqlite::Result<Vec<_>>>()
+    })
+    .map_err(|e| AppError::InvalidInput(format!("Cannot read backup migration history: {}", e)))?;
+
+  let known = migrations::all();
+  let latest = migrations::latest_version();
+  for (version, description, success) in applied {
+    if version > latest {
+      return Err(AppError::Unsupported(format!(
+        "Backup was created by a newer version of the app (schema version {}, this app supports up to {})",
+        version, latest
+      )));
+    }
+    if !known
+      .iter()
+      .any(|m| m.version == version && m.description == description)
+    {
+      return Err(AppError::InvalidInput(format!(
+        "Backup contains unknown migration {} ({})",
+        version, description
+      )));
+    }
+    if !success {
+      return Err(AppError::InvalidInput(format!(
+        "Backup contains a partially applied migration {} ({})",
+        version, description
+      )));
+    }
+  }
+  Ok(())
+}
+
+pub fn remove_if_exists(path: &Path) -> Result<(), AppError> {
+  match fs::remove_file(path) {
+    Ok(()) => Ok(()),
+    Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(()),
+    Err(e) => Err(AppError::Io(format!(
+      "Cannot remove {}: {}",
+      path.display(),
+      e
+    ))),
+  }
+}
+
+pub fn sidecar(path: &Path, suffix: &str) -> PathBuf {
+  let mut name = path.as_os_str().to_owned();
+  name.push(suffix);
+  PathBuf::from(name)
+}
+
+/// Replaces the live database with `backup_path`.
+///
+/// The backup is copied next to the live file and migrated up to the current
+/// schema first; only once that succeeds is the plugin's pool closed and the
+/// copy renamed over the live database. The frontend must call
+/// `Database.load` again afterwards.
+#[tauri::command]
+pub async fn restore_database(app: AppHandle, backup_path: String) -> Result<(), AppError> {
+  let backup_path = PathBuf::from(backup_path);
+  validate_backup(&backup_path)?;
+
+  let live = db::db_path(&app)?;
+  let tmp = sidecar(&live, ".restore");
+  remove_if_exists(&tmp)?;
+  fs::copy(&backup_path, &tmp)
+    .map_err(|e| AppError::Io(format!("Cannot copy backup to {}: {}", tmp.display(), e)))?;
+
+  if let Err(e) = migrations::run_on_file(&tmp).await {
+    let _ = fs::remove_file(&tmp);
+    return Err(AppError::InvalidInput(format!(
+      "Backup is not compatible with this version of the app: {}",
+      e
+    )));
+  }
+
+  db::close_plugin_pool(&app).await;
+  db::close_pool(&app);
+
+  // Leftover WAL files belong to the old database and would be replayed on
+  // top of the restored one.
+  let swap = remove_if_exists(&sidecar(&live, "-wal"))
+    .and_then(|_| remove_if_exists(&sidecar(&live, "-shm")))
+    .and_then(|_| {
+      fs::rename(&tmp, &live)
+        .map_err(|e| AppError::Io(format!("Cannot replace {}: {}", live.display(), e)))
+    });
+  if let Err(e) = swap {
+    let _ = fs::remove_file(&tmp);
+    return Err(e);
+  }
+
+  log::info!("Database restored from {}", backup_path.display());
+  Ok(())
+}
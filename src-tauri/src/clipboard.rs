@@ -0,0 +1,35 @@
+use tauri::image::Image;
+use tauri::AppHandle;
+use tauri_plugin_clipboard_manager::ClipboardExt;
+
+use crate::error::AppError;
+use crate::{db, highlights, images};
+
+/// Decodes an image file into RGBA pixels.
+fn decode_image(png: &[u8]) -> Result<Image<'static>, String> {
+  let rgba = image::load_from_memory(png)
+    .map_err(|e| format!("Cannot decode image: {}", e))?
+    .into_rgba8();
+  let (width, height) = rgba.dimensions();
+  Ok(Image::new_owned(rgba.into_raw(), width, height))
+}
+
+/// Puts the image of an area highlight on the system clipboard.
+#[tauri::command]
+pub async fn copy_highlight_image(app: AppHandle, highlight_id: String) -> Result<(), AppError> {
+  let conn = db::open(&app)?;
+  let highlight = highlights::get(&conn, &highlight_id)?
+    .ok_or_else(|| AppError::NotFound(format!("Highlight {} not found", highlight_id)))?;
+  let images_dir = images::images_dir(&conn)?;
+  drop(conn);
+  let png = images::load(images_dir.as_deref(), &highlight)
+    .map_err(|e| AppError::Corrupt(format!("Highlight {}: {}", highlight_id, e)))?
+    .ok_or_else(|| AppError::InvalidInput(format!("Highlight {} has no image", highlight_id)))?;
+
+  let image = decode_image(&png)
+    .map_err(|e| AppError::Corrupt(format!("Highlight {}: {}", highlight_id, e)))?;
+  app
+    .clipboard()
+    .write_image(&image)
+    .map_err(|e| AppError::Failed(format!("Cannot write to the clipboard: {}", e)))
+}
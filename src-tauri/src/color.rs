@@ -0,0 +1,25 @@
+/// Accepts `#RRGGBB` and `#RRGGBBAA` hex colors.
+pub fn validate_hex(color: &str) -> Result<(), String> {
+  let valid = match color.strip_prefix('#') {
+    Some(hex) => (hex.len() == 6 || hex.len() == 8) && hex.chars().all(|c| c.is_ascii_hexdigit()),
+    None => false,
+  };
+  if valid {
+    Ok(())
+  } else {
+    Err(format!(
+      "Invalid color {:?}: expected #RRGGBB or #RRGGBBAA",
+      color
+    ))
+  }
+}
+
+/// Splits a color `validate_hex` accepts into its RGB components and, for
+/// `#RRGGBBAA`, its alpha.
+pub fn parse_hex(color: &str) -> Option<([u8; 3], Option<u8>)> {
+  validate_hex(color).ok()?;
+  let hex = &color[1..];
+  let byte = |i: usize| u8::from_str_radix(&hex[i..i + 2], 16).ok();
+  let alpha = if hex.len() == 8 { Some(byte(6)?) } else { None };
+  Some(([byte(0)?, byte(2)?, byte(4)?], alpha))
+}
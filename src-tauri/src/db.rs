@@ -0,0 +1,160 @@
+use r2d2::Pool;
+use r2d2_sqlite::SqliteConnectionManager;
+use rusqlite::{Connection, OpenFlags};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::Duration;
+use tauri::{AppHandle, Manager, Runtime};
+use tauri_plugin_sql::{DbInstances, DbPool};
+
+// Use different database names for dev and prod to maintain separation
+pub const DB_FILE: &str = if cfg!(debug_assertions) {
+  "pdf_highlighter_dev.db"
+} else {
+  "pdf_highlighter.db"
+};
+
+/// Connection string the frontend passes to `Database.load`, which is also
+/// the key tauri-plugin-sql stores the pool under.
+pub fn db_url() -> String {
+  format!("sqlite:{}", DB_FILE)
+}
+
+/// Location of the live database. tauri-plugin-sql resolves `sqlite:` URLs
+/// relative to the app config directory, so we do the same.
+pub fn db_path<R: Runtime>(app: &AppHandle<R>) -> Result<PathBuf, String> {
+  let dir = app
+    .path()
+    .app_config_dir()
+    .map_err(|e| format!("Could not resolve app config directory: {}", e))?;
+  Ok(dir.join(DB_FILE))
+}
+
+/// Most connections the pool keeps open at once. Commands hold theirs only
+/// briefly, and SQLite serializes writers anyway.
+const MAX_CONNECTIONS: u32 = 4;
+
+/// How long a statement waits for another connection's write lock, and a
+/// command for a free connection, before failing.
+const BUSY_TIMEOUT: Duration = Duration::from_secs(5);
+
+pub type PooledConnection = r2d2::PooledConnection<SqliteConnectionManager>;
+
+/// Pool of connections to the live database, kept in managed state.
+///
+/// It is built on first use rather than at startup, since the file only
+/// exists once the frontend has loaded it, and dropped by `close_pool`
+/// whenever the file or its key changes.
+#[derive(Default)]
+pub struct ConnectionPool(Mutex<Option<Pool<SqliteConnectionManager>>>);
+
+/// Sets up every new connection of the pool.
+fn init_connection(conn: &mut Connection, passphrase: Option<&str>) -> rusqlite::Result<()> {
+  if let Some(passphrase) = passphrase {
+    conn.pragma_update(None, "key", passphrase)?;
+  }
+  conn.busy_timeout(BUSY_TIMEOUT)?;
+  // SQLite leaves foreign keys off per connection, which would make the
+  // migrations' ON DELETE CASCADE clauses dead letters. The plugin's sqlx
+  // pool, which the frontend deletes PDFs through, enables them itself.
+  conn.pragma_update(None, "foreign_keys", true)?;
+  // Safe with WAL: a power loss can lose the last commits but never corrupts
+  // the database. Not persisted, so every connection sets it.
+  conn.pragma_update(None, "synchronous", "NORMAL")
+}
+
+fn build_pool(path: &Path) -> Result<Pool<SqliteConnectionManager>, String> {
+  let passphrase = passphrase();
+  let manager = SqliteConnectionManager::file(path)
+    .with_flags(OpenFlags::SQLITE_OPEN_READ_WRITE | OpenFlags::SQLITE_OPEN_NO_MUTEX)
+    .with_init(move |conn| init_connection(conn, passphrase.as_deref()));
+  Pool::builder()
+    .max_size(MAX_CONNECTIONS)
+    .min_idle(Some(0))
+    .connection_timeout(BUSY_TIMEOUT)
+    .build(manager)
+    .map_err(|e| format!("Failed to open database {}: {}", path.display(), e))
+}
+
+/// Checks out a connection to the live database for use inside a command.
+/// It goes back to the pool when dropped.
+///
+/// The file is never created here: the frontend owns the first
+/// `Database.load`, which is also what runs the migrations.
+pub fn open<R: Runtime>(app: &AppHandle<R>) -> Result<PooledConnection, String> {
+  let path = db_path(app)?;
+  if !path.exists() {
+    return Err(format!("Database {} does not exist yet", path.display()));
+  }
+  let pool = {
+    let mut slot = app.state::<ConnectionPool>().inner().0.lock().unwrap();
+    match slot.as_ref() {
+      Some(pool) => pool.clone(),
+      None => slot.insert(build_pool(&path)?).clone(),
+    }
+  };
+  pool
+    .get()
+    .map_err(|e| format!("Failed to open database {}: {}", path.display(), e))
+}
+
+/// Drops the pool, so the next `open` connects to the file anew. Has to be
+/// called after the file is replaced or rekeyed; connections still checked
+/// out keep working on the old one until they are dropped.
+pub fn close_pool<R: Runtime>(app: &AppHandle<R>) {
+  if let Some(state) = app.try_state::<ConnectionPool>() {
+    state.0.lock().unwrap().take();
+  }
+}
+
+/// Switches an existing database to WAL journaling. The mode is stored in
+/// the file, so this only has to run once, before the plugin opens its pool:
+/// changing it needs the database to itself.
+pub fn enable_wal<R: Runtime>(app: &AppHandle<R>) -> Result<(), String> {
+  if !db_path(app)?.exists() {
+    // The frontend creates the file, and sqlx creates it in WAL mode.
+    return Ok(());
+  }
+  let conn = open(app)?;
+  let mode: String = conn
+    .query_row("PRAGMA journal_mode=WAL", [], |row| row.get(0))
+    .map_err(|e| e.to_string())?;
+  if !mode.eq_ignore_ascii_case("wal") {
+    return Err(format!("Database stayed in {} journal mode", mode));
+  }
+  Ok(())
+}
+
+pub fn wal_enabled(conn: &Connection) -> rusqlite::Result<bool> {
+  let mode: String = conn.query_row("PRAGMA journal_mode", [], |row| row.get(0))?;
+  Ok(mode.eq_ignore_ascii_case("wal"))
+}
+
+/// SQLCipher passphrase of the live database, set by `unlock_database`.
+/// Always empty in builds without the `encrypted` feature.
+static PASSPHRASE: Mutex<Option<String>> = Mutex::new(None);
+
+pub fn passphrase() -> Option<String> {
+  PASSPHRASE.lock().unwrap().clone()
+}
+
+/// Pooled connections are keyed when they are opened, so this closes the
+/// pool.
+pub fn set_passphrase<R: Runtime>(app: &AppHandle<R>, passphrase: Option<String>) {
+  *PASSPHRASE.lock().unwrap() = passphrase;
+  close_pool(app);
+}
+
+/// Closes the pool tauri-plugin-sql holds for the live database so its file
+/// can be replaced. The frontend has to call `Database.load` again afterwards.
+pub async fn close_plugin_pool<R: Runtime>(app: &AppHandle<R>) {
+  let Some(instances) = app.try_state::<DbInstances>() else {
+    return;
+  };
+  let pool = instances.0.write().await.remove(&db_url());
+  if let Some(pool) = pool {
+    match pool {
+      DbPool::Sqlite(pool) => pool.close().await,
+    }
+  }
+}
@@ -0,0 +1,115 @@
+//! `pdfhighlighter://` links to a highlight, so one can be shared and opened
+//! straight from a chat or a note.
+
+use serde::Serialize;
+use std::sync::Mutex;
+use tauri::{AppHandle, Emitter, Manager, Runtime, Url};
+use tauri_plugin_deep_link::DeepLinkExt;
+
+use crate::error::AppError;
+use crate::{db, highlights};
+
+/// URL scheme of the links, also declared under `plugins.deep-link` in
+/// tauri.conf.json.
+pub const SCHEME: &str = "pdfhighlighter";
+
+/// Event emitted with a `HighlightLink` when a link is opened while the app
+/// is running.
+pub const OPEN_HIGHLIGHT_EVENT: &str = "open-highlight";
+
+#[derive(Debug, Clone, Serialize)]
+pub struct HighlightLink {
+  pub pdf_id: i64,
+  pub highlight_id: String,
+  pub page: Option<i64>,
+}
+
+/// Link the app was launched with, kept until the frontend asks for it since
+/// it is parsed before any window listens for events.
+pub struct PendingLink(Mutex<Option<HighlightLink>>);
+
+fn parse(url: &Url) -> Option<HighlightLink> {
+  if url.scheme() != SCHEME || url.host_str() != Some("open") {
+    return None;
+  }
+  let mut pdf_id = None;
+  let mut highlight_id = None;
+  let mut page = None;
+  for (key, value) in url.query_pairs() {
+    match key.as_ref() {
+      "pdf" => pdf_id = value.parse().ok(),
+      "highlight" => highlight_id = Some(value.into_owned()),
+      "page" => page = value.parse().ok(),
+      _ => {}
+    }
+  }
+  Some(HighlightLink {
+    pdf_id: pdf_id?,
+    highlight_id: highlight_id.filter(|id| !id.is_empty())?,
+    page,
+  })
+}
+
+fn open_links<R: Runtime>(app: &AppHandle<R>, urls: Vec<Url>) {
+  for url in urls {
+    let Some(link) = parse(&url) else {
+      log::warn!("Ignoring deep link {}", url);
+      continue;
+    };
+    log::info!("Opening deep link {}", url);
+    if let Err(e) = app.emit(OPEN_HIGHLIGHT_EVENT, &link) {
+      log::warn!("Cannot emit {}: {}", OPEN_HIGHLIGHT_EVENT, e);
+    }
+  }
+}
+
+/// Hooks links up in `setup()`: the one the app was launched with becomes the
+/// pending link, later ones are emitted as `OPEN_HIGHLIGHT_EVENT`.
+pub fn init<R: Runtime>(app: &AppHandle<R>) {
+  // Installers register the scheme on Windows and Linux; this covers
+  // development builds, which aren't installed.
+  #[cfg(any(windows, target_os = "linux"))]
+  if let Err(e) = app.deep_link().register_all() {
+    log::warn!("Cannot register the {} scheme: {}", SCHEME, e);
+  }
+
+  let launched_with = match app.deep_link().get_current() {
+    Ok(urls) => urls.unwrap_or_default().iter().find_map(parse),
+    Err(e) => {
+      log::warn!("Cannot read the launch deep link: {}", e);
+      None
+    }
+  };
+  app.manage(PendingLink(Mutex::new(launched_with)));
+
+  let handle = app.clone();
+  app
+    .deep_link()
+    .on_open_url(move |event| open_links(&handle, event.urls()));
+}
+
+/// Builds a link that opens the highlight, such as
+/// `pdfhighlighter://open?pdf=3&highlight=f2c1...&page=12`.
+#[tauri::command]
+pub async fn highlight_deep_link(app: AppHandle, highlight_id: String) -> Result<String, AppError> {
+  let conn = db::open(&app)?;
+  let highlight = highlights::get(&conn, &highlight_id)?
+    .ok_or_else(|| AppError::NotFound(format!("Highlight {} not found", highlight_id)))?;
+
+  let mut url = Url::parse(&format!("{}://open", SCHEME)).expect("scheme URL is valid");
+  url
+    .query_pairs_mut()
+    .append_pair("pdf", &highlight.pdf_id.to_string())
+    .append_pair("highlight", &highlight.highlight_id)
+    .append_pair("page", &highlight.page_number.to_string());
+  Ok(url.into())
+}
+
+/// Takes the link the app was launched with, if any, so the frontend can
+/// open it once it is ready.
+#[tauri::command]
+pub async fn take_pending_deep_link(app: AppHandle) -> Result<Option<HighlightLink>, AppError> {
+  let pending = app.state::<PendingLink>();
+  let link = pending.0.lock().unwrap().take();
+  Ok(link)
+}
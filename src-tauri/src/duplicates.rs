@@ -0,0 +1,144 @@
+use serde::Serialize;
+use std::collections::HashMap;
+use tauri::AppHandle;
+
+use crate::error::AppError;
+use crate::highlights::{self, HighlightRecord};
+use crate::{db, position};
+
+/// Bounding boxes overlapping at least this much are duplicates unless the
+/// caller asks for another threshold.
+const DEFAULT_IOU_THRESHOLD: f64 = 0.8;
+
+#[derive(Debug, Serialize)]
+pub struct DuplicateGroup {
+  pub page_number: i64,
+  /// Oldest highlight first.
+  pub highlight_ids: Vec<String>,
+}
+
+/// Minimal union-find over highlight indices.
+struct Groups(Vec<usize>);
+
+impl Groups {
+  fn find(&mut self, i: usize) -> usize {
+    let mut root = i;
+    while self.0[root] != root {
+      root = self.0[root];
+    }
+    // Path compression
+    let mut i = i;
+    while self.0[i] != root {
+      i = std::mem::replace(&mut self.0[i], root);
+    }
+    root
+  }
+
+  fn union(&mut self, a: usize, b: usize) {
+    let (a, b) = (self.find(a), self.find(b));
+    if a != b {
+      self.0[a.max(b)] = a.min(b);
+    }
+  }
+}
+
+fn is_duplicate(a: &Candidate, b: &Candidate, iou_threshold: f64) -> bool {
+  if a.text.is_some() && a.text == b.text {
+    return true;
+  }
+  match (a.rect, b.rect) {
+    (Some(ra), Some(rb)) => position::iou(ra, rb) >= iou_threshold,
+    _ => false,
+  }
+}
+
+struct Candidate<'a> {
+  record: &'a HighlightRecord,
+  text: Option<&'a str>,
+  rect: Option<[f64; 4]>,
+}
+
+/// Groups highlights on the same page whose bounding boxes overlap by at
+/// least `iou_threshold` or whose text is identical. Highlights with
+/// unparseable positions can still match on text.
+fn duplicate_groups(rows: &[HighlightRecord], iou_threshold: f64) -> Vec<DuplicateGroup> {
+  let candidates: Vec<Candidate> = rows
+    .iter()
+    .map(|record| Candidate {
+      record,
+      text: record
+        .content_text
+        .as_deref()
+        .map(str::trim)
+        .filter(|text| !text.is_empty()),
+      rect: record
+        .position()
+        .ok()
+        .and_then(|p| p.bounding_rect.normalized()),
+    })
+    .collect();
+
+  let mut by_page: HashMap<i64, Vec<usize>> = HashMap::new();
+  for (i, candidate) in candidates.iter().enumerate() {
+    by_page
+      .entry(candidate.record.page_number)
+      .or_default()
+      .push(i);
+  }
+
+  let mut groups = Groups((0..candidates.len()).collect());
+  for indices in by_page.values() {
+    for (n, &a) in indices.iter().enumerate() {
+      for &b in &indices[n + 1..] {
+        if is_duplicate(&candidates[a], &candidates[b], iou_threshold) {
+          groups.union(a, b);
+        }
+      }
+    }
+  }
+
+  let mut members: HashMap<usize, Vec<usize>> = HashMap::new();
+  for i in 0..candidates.len() {
+    members.entry(groups.find(i)).or_default().push(i);
+  }
+  let mut result: Vec<DuplicateGroup> = members
+    .into_values()
+    .filter(|indices| indices.len() > 1)
+    .map(|mut indices| {
+      indices.sort_by_key(|&i| candidates[i].record.id);
+      DuplicateGroup {
+        page_number: candidates[indices[0]].record.page_number,
+        highlight_ids: indices
+          .iter()
+          .map(|&i| candidates[i].record.highlight_id.clone())
+          .collect(),
+      }
+    })
+    .collect();
+  result.sort_by(|a, b| {
+    a.page_number
+      .cmp(&b.page_number)
+      .then_with(|| a.highlight_ids.cmp(&b.highlight_ids))
+  });
+  result
+}
+
+/// Finds highlights of a PDF that are likely duplicates of each other, so the
+/// UI can offer to clean them up. `iou_threshold` defaults to 0.8.
+#[tauri::command]
+pub async fn find_duplicate_highlights(
+  app: AppHandle,
+  pdf_id: i64,
+  iou_threshold: Option<f64>,
+) -> Result<Vec<DuplicateGroup>, AppError> {
+  let iou_threshold = iou_threshold.unwrap_or(DEFAULT_IOU_THRESHOLD);
+  if !(iou_threshold > 0.0 && iou_threshold <= 1.0) {
+    return Err(AppError::InvalidInput(
+      "iou_threshold must be greater than 0 and at most 1".to_string(),
+    ));
+  }
+  let conn = db::open(&app)?;
+  let rows = highlights::list_for_pdf(&conn, pdf_id)?;
+
+  Ok(duplicate_groups(&rows, iou_threshold))
+}
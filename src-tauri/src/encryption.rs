@@ -0,0 +1,117 @@
+//! At-rest encryption of the live database through SQLCipher, available in
+//! builds with the `encrypted` Cargo feature.
+//!
+//! The passphrase is only known to the backend: every connection of the pool
+//! behind `db::open` is keyed with it. tauri-plugin-sql cannot key its own
+//! connections, so an encrypted library is only reachable through commands.
+
+use rusqlite::{params, Connection, ErrorCode, OpenFlags};
+use std::fs;
+use std::path::Path;
+use tauri::AppHandle;
+
+use crate::error::AppError;
+use crate::{backup, db, migrations};
+
+fn ensure_supported() -> Result<(), AppError> {
+  if cfg!(feature = "encrypted") {
+    Ok(())
+  } else {
+    Err(AppError::Unsupported(
+      "This build does not support database encryption".to_string(),
+    ))
+  }
+}
+
+/// Opens `path` with `passphrase` (or as plaintext with `None`) and reads the
+/// schema, which is what fails when the key is wrong. A wrong key yields
+/// `AppError::WrongPassphrase`, so the UI knows to prompt again.
+fn open_keyed(path: &Path, passphrase: Option<&str>) -> Result<Connection, AppError> {
+  let conn = Connection::open_with_flags(
+    path,
+    OpenFlags::SQLITE_OPEN_READ_WRITE | OpenFlags::SQLITE_OPEN_NO_MUTEX,
+  )
+  .map_err(|e| AppError::Io(format!("Failed to open database {}: {}", path.display(), e)))?;
+  if let Some(passphrase) = passphrase {
+    conn.pragma_update(None, "key", passphrase)?;
+  }
+
+  match conn.query_row("SELECT COUNT(*) FROM sqlite_master", [], |row| {
+    row.get::<_, i64>(0)
+  }) {
+    Ok(_) => Ok(conn),
+    Err(rusqlite::Error::SqliteFailure(e, _)) if e.code == ErrorCode::NotADatabase => Err(
+      AppError::WrongPassphrase("The passphrase is wrong".to_string()),
+    ),
+    Err(e) => Err(e.into()),
+  }
+}
+
+/// Writes an encrypted copy of the plaintext database `conn` to `dest`.
+fn export_encrypted(conn: &Connection, dest: &Path, passphrase: &str) -> Result<(), String> {
+  // Attached databases inherit the connection's flags, which don't include
+  // SQLITE_OPEN_CREATE; an empty file is a valid empty database.
+  fs::File::create(dest).map_err(|e| format!("Cannot create {}: {}", dest.display(), e))?;
+  export_into(conn, dest, passphrase).map_err(|e| e.to_string())
+}
+
+fn export_into(conn: &Connection, dest: &Path, passphrase: &str) -> rusqlite::Result<()> {
+  conn.execute(
+    "ATTACH DATABASE ?1 AS encrypted KEY ?2",
+    params![dest.to_string_lossy(), passphrase],
+  )?;
+  conn.query_row("SELECT sqlcipher_export('encrypted')", [], |_| Ok(()))?;
+  conn.execute("DETACH DATABASE encrypted", [])?;
+  Ok(())
+}
+
+/// Unlocks an encrypted database for this session and brings its schema up to
+/// date. A wrong passphrase yields `AppError::WrongPassphrase`.
+#[tauri::command]
+pub async fn unlock_database(app: AppHandle, passphrase: String) -> Result<(), AppError> {
+  ensure_supported()?;
+  let path = db::db_path(&app)?;
+  drop(open_keyed(&path, Some(&passphrase))?);
+
+  migrations::run_on_file_with_key(&path, Some(&passphrase)).await?;
+  db::set_passphrase(&app, Some(passphrase));
+  Ok(())
+}
+
+/// Changes the passphrase with `PRAGMA rekey`. With an empty `old` the
+/// database is taken to be plaintext and is encrypted with `new`.
+#[tauri::command]
+pub async fn change_passphrase(app: AppHandle, old: String, new: String) -> Result<(), AppError> {
+  ensure_supported()?;
+  if new.is_empty() {
+    return Err(AppError::InvalidInput(
+      "The new passphrase must not be empty".to_string(),
+    ));
+  }
+  let path = db::db_path(&app)?;
+
+  if old.is_empty() {
+    // `rekey` cannot encrypt a plaintext file in place, so export an
+    // encrypted copy and swap it in.
+    let conn = open_keyed(&path, None)?;
+    let tmp = backup::sidecar(&path, ".encrypting");
+    backup::remove_if_exists(&tmp)?;
+    if let Err(e) = export_encrypted(&conn, &tmp, &new) {
+      let _ = fs::remove_file(&tmp);
+      return Err(AppError::Failed(e));
+    }
+    drop(conn);
+
+    db::close_plugin_pool(&app).await;
+    db::close_pool(&app);
+    fs::rename(&tmp, &path)
+      .map_err(|e| AppError::Io(format!("Cannot replace {}: {}", path.display(), e)))?;
+  } else {
+    let conn = open_keyed(&path, Some(&old))?;
+    conn.pragma_update(None, "rekey", &new)?;
+  }
+
+  db::set_passphrase(&app, Some(new));
+  log::info!("Database passphrase changed");
+  Ok(())
+}
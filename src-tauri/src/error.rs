@@ -0,0 +1,124 @@
+use serde::Serialize;
+use std::fmt;
+use std::io;
+
+/// Error returned by every command. Serialized as
+/// `{ "code": "NotFound", "message": "PDF 3 not found" }`, so the UI can
+/// switch on `code` and show `message`.
+///
+/// Helpers that can fail on bad input or missing data return `AppError`
+/// themselves so the code survives the trip up. A plain `String` error still
+/// converts, as `Failed`, for failures nothing can be done about.
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "code", content = "message")]
+pub enum AppError {
+  /// A PDF, highlight, tag, file or setting that doesn't exist.
+  NotFound(String),
+  /// The change clashes with existing data, e.g. a duplicate name or a
+  /// newer copy on the sync server.
+  Conflict(String),
+  /// An argument the command can't accept.
+  InvalidInput(String),
+  /// Reading or writing a file failed.
+  Io(String),
+  /// The database or a PDF is encrypted and needs a passphrase first.
+  Encrypted(String),
+  /// The passphrase given to unlock the database is wrong.
+  WrongPassphrase(String),
+  /// A PDF that can't be parsed.
+  Corrupt(String),
+  /// The sync server can't be reached or answered with an error.
+  Network(String),
+  /// The sync server rejected the credentials.
+  Unauthorized(String),
+  /// The operation isn't available in this build.
+  Unsupported(String),
+  /// Any other database error.
+  Database(String),
+  /// Anything else.
+  Failed(String),
+}
+
+impl AppError {
+  pub fn message(&self) -> &str {
+    match self {
+      AppError::NotFound(message)
+      | AppError::Conflict(message)
+      | AppError::InvalidInput(message)
+      | AppError::Io(message)
+      | AppError::Encrypted(message)
+      | AppError::WrongPassphrase(message)
+      | AppError::Corrupt(message)
+      | AppError::Network(message)
+      | AppError::Unauthorized(message)
+      | AppError::Unsupported(message)
+      | AppError::Database(message)
+      | AppError::Failed(message) => message,
+    }
+  }
+}
+
+impl fmt::Display for AppError {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    f.write_str(self.message())
+  }
+}
+
+impl std::error::Error for AppError {}
+
+impl From<String> for AppError {
+  fn from(message: String) -> Self {
+    AppError::Failed(message)
+  }
+}
+
+impl From<rusqlite::Error> for AppError {
+  fn from(e: rusqlite::Error) -> Self {
+    match &e {
+      rusqlite::Error::SqliteFailure(failure, _) => match failure.code {
+        rusqlite::ErrorCode::ConstraintViolation => AppError::Conflict(e.to_string()),
+        // What SQLCipher reports for a database read without its key
+        rusqlite::ErrorCode::NotADatabase => AppError::Encrypted(e.to_string()),
+        _ => AppError::Database(e.to_string()),
+      },
+      rusqlite::Error::QueryReturnedNoRows => AppError::NotFound(e.to_string()),
+      _ => AppError::Database(e.to_string()),
+    }
+  }
+}
+
+impl From<io::Error> for AppError {
+  fn from(e: io::Error) -> Self {
+    match e.kind() {
+      io::ErrorKind::NotFound => AppError::NotFound(e.to_string()),
+      _ => AppError::Io(e.to_string()),
+    }
+  }
+}
+
+impl From<serde_json::Error> for AppError {
+  fn from(e: serde_json::Error) -> Self {
+    if e.is_io() {
+      AppError::Io(e.to_string())
+    } else {
+      AppError::InvalidInput(e.to_string())
+    }
+  }
+}
+
+impl From<reqwest::Error> for AppError {
+  fn from(e: reqwest::Error) -> Self {
+    AppError::Network(e.to_string())
+  }
+}
+
+impl From<zip::result::ZipError> for AppError {
+  fn from(e: zip::result::ZipError) -> Self {
+    match e {
+      zip::result::ZipError::Io(e) => e.into(),
+      zip::result::ZipError::FileNotFound => AppError::NotFound(e.to_string()),
+      zip::result::ZipError::UnsupportedArchive(_) => AppError::Unsupported(e.to_string()),
+      _ => AppError::Corrupt(e.to_string()),
+    }
+  }
+}
@@ -0,0 +1,947 @@
+use image::ImageFormat;
+use lopdf::{Document, Object};
+use rusqlite::{params, Connection, OptionalExtension};
+use serde::Deserialize;
+use std::collections::{HashMap, HashSet};
+use std::fmt::Write;
+use std::fs;
+use std::path::{Path, PathBuf};
+use tauri::AppHandle;
+
+use crate::error::AppError;
+use crate::geometry::{self, PdfBox};
+use crate::highlights::{self, HighlightRecord};
+use crate::{color, db, images, optimize, pdfs, position, render};
+
+fn pdf_name(conn: &Connection, pdf_id: i64) -> Result<String, AppError> {
+  conn
+    .query_row(
+      "SELECT name FROM pdfs WHERE id = ?1",
+      params![pdf_id],
+      |row| row.get(0),
+    )
+    .optional()?
+    .ok_or_else(|| AppError::NotFound(format!("PDF {} not found", pdf_id)))
+}
+
+/// Tag names for every highlight of a PDF, or of the whole library with
+/// `None`, keyed by `highlight_id`.
+fn tag_names_for_pdf(
+  conn: &Connection,
+  pdf_id: Option<i64>,
+) -> rusqlite::Result<HashMap<String, Vec<String>>> {
+  let mut stmt = conn.prepare(
+    "SELECT ht.highlight_id, t.name
+     FROM highlight_tags ht
+     JOIN tags t ON t.id = ht.tag_id
+     JOIN highlights h ON h.highlight_id = ht.highlight_id
+     WHERE (?1 IS NULL OR h.pdf_id = ?1) AND h.deleted_at IS NULL
+     ORDER BY t.name ASC",
+  )?;
+  let mut tags: HashMap<String, Vec<String>> = HashMap::new();
+  let rows = stmt.query_map(params![pdf_id], |row| {
+    Ok((row.get::<_, String>(0)?, row.get::<_, String>(1)?))
+  })?;
+  for row in rows {
+    let (highlight_id, name) = row?;
+    tags.entry(highlight_id).or_default().push(name);
+  }
+  Ok(tags)
+}
+
+fn hashtag(name: &str) -> String {
+  format!("#{}", name.split_whitespace().collect::<Vec<_>>().join("-"))
+}
+
+fn render_markdown(
+  name: &str,
+  rows: &[HighlightRecord],
+  tags: &HashMap<String, Vec<String>>,
+) -> String {
+  let mut out = String::new();
+  let _ = writeln!(out, "# {}", name);
+
+  let mut current_page = None;
+  for row in rows {
+    if current_page != Some(row.page_number) {
+      current_page = Some(row.page_number);
+      let _ = writeln!(out, "\n## Page {}", row.page_number);
+    }
+    write_highlight(&mut out, row);
+
+    if let Some(names) = tags.get(&row.highlight_id) {
+      let line = names
+        .iter()
+        .map(|n| hashtag(n))
+        .collect::<Vec<_>>()
+        .join(" ");
+      let _ = write!(out, "\n{}\n", line);
+    }
+  }
+  out
+}
+
+/// Writes the quote of a highlight and its comment, preceded by a blank line.
+fn write_highlight(out: &mut String, row: &HighlightRecord) {
+  out.push('\n');
+
+  match &row.content_text {
+    Some(text) if !text.is_empty() => {
+      for line in text.lines() {
+        if line.is_empty() {
+          out.push_str(">\n");
+        } else {
+          let _ = writeln!(out, "> {}", line);
+        }
+      }
+    }
+    _ if row.has_image() => out.push_str("> *[Image highlight]*\n"),
+    _ => out.push_str("> *[Empty highlight]*\n"),
+  }
+
+  let comment = row.comment_text.as_deref().unwrap_or("").trim();
+  let emoji = row.comment_emoji.as_deref().unwrap_or("").trim();
+  if !comment.is_empty() || !emoji.is_empty() {
+    out.push('\n');
+    let line = [emoji, comment]
+      .iter()
+      .filter(|s| !s.is_empty())
+      .copied()
+      .collect::<Vec<_>>()
+      .join(" ");
+    let _ = writeln!(out, "{}", line);
+  }
+}
+
+#[tauri::command]
+pub async fn export_highlights_markdown(app: AppHandle, pdf_id: i64) -> Result<String, AppError> {
+  let conn = db::open(&app)?;
+  let name = pdf_name(&conn, pdf_id)?;
+  let rows = highlights::in_reading_order(&conn, pdf_id)?;
+  let tags = tag_names_for_pdf(&conn, Some(pdf_id))?;
+  Ok(render_markdown(&name, &rows, &tags))
+}
+
+/// Highlights carrying one tag, grouped by PDF.
+struct TagExport {
+  name: String,
+  /// PDF names with their highlights, in the order of the names.
+  pdfs: Vec<(String, Vec<HighlightRecord>)>,
+}
+
+/// Every tag that has highlights outside the trash, ordered by name.
+fn tagged_highlights(conn: &Connection) -> rusqlite::Result<Vec<TagExport>> {
+  let mut stmt = conn.prepare(&format!(
+    "SELECT {}, t.id AS tag_id, t.name AS tag_name, p.name AS pdf_name
+     FROM tags t
+     JOIN highlight_tags ht ON ht.tag_id = t.id
+     JOIN highlights h ON h.highlight_id = ht.highlight_id AND h.deleted_at IS NULL
+     JOIN pdfs p ON p.id = h.pdf_id
+     ORDER BY t.name, t.id, p.name, p.id",
+    highlights::COLUMNS
+  ))?;
+  let rows = stmt.query_map([], |row| {
+    Ok((
+      row.get::<_, i64>("tag_id")?,
+      row.get::<_, String>("tag_name")?,
+      row.get::<_, String>("pdf_name")?,
+      HighlightRecord::from_row(row)?,
+    ))
+  })?;
+
+  let mut tags: Vec<TagExport> = Vec::new();
+  let mut current = None;
+  for row in rows {
+    let (tag_id, tag_name, pdf_name, record) = row?;
+    if current != Some(tag_id) {
+      current = Some(tag_id);
+      tags.push(TagExport {
+        name: tag_name,
+        pdfs: Vec::new(),
+      });
+    }
+    let pdfs = &mut tags.last_mut().expect("pushed above").pdfs;
+    match pdfs.last_mut() {
+      Some((_, rows)) if rows[0].pdf_id == record.pdf_id => rows.push(record),
+      _ => pdfs.push((pdf_name, vec![record])),
+    }
+  }
+  Ok(tags)
+}
+
+fn render_tag_markdown(tag: &mut TagExport) -> String {
+  let mut out = String::new();
+  let _ = writeln!(out, "# {}", tag.name);
+  for (pdf_name, rows) in &mut tag.pdfs {
+    let _ = writeln!(out, "\n## {}", pdf_name);
+    highlights::sort_in_reading_order(rows);
+    let mut current_page = None;
+    for row in rows.iter() {
+      if current_page != Some(row.page_number) {
+        current_page = Some(row.page_number);
+        let _ = writeln!(out, "\n### Page {}", row.page_number);
+      }
+      write_highlight(&mut out, row);
+    }
+  }
+  out
+}
+
+/// Device names Windows won't create a file under, whatever the extension.
+const RESERVED_FILE_NAMES: &[&str] = &[
+  "CON", "PRN", "AUX", "NUL", "COM1", "COM2", "COM3", "COM4", "COM5", "COM6", "COM7", "COM8",
+  "COM9", "LPT1", "LPT2", "LPT3", "LPT4", "LPT5", "LPT6", "LPT7", "LPT8", "LPT9",
+];
+
+/// File stem for a name that is valid on every platform: characters Windows
+/// forbids become `_`, and its reserved device names get a `_` appended.
+fn safe_file_stem(name: &str) -> String {
+  let stem: String = name
+    .trim()
+    .chars()
+    .map(|c| match c {
+      '<' | '>' | ':' | '"' | '/' | '\\' | '|' | '?' | '*' => '_',
+      c if c.is_control() => '_',
+      c => c,
+    })
+    .take(100)
+    .collect();
+  // Windows drops trailing dots and spaces, which could merge two names
+  let stem = stem.trim_end_matches(['.', ' ']);
+  if stem.is_empty() {
+    return "untitled".to_string();
+  }
+  let device = stem.split('.').next().unwrap_or("").to_ascii_uppercase();
+  if RESERVED_FILE_NAMES.contains(&device.as_str()) {
+    format!("{}_", stem)
+  } else {
+    stem.to_string()
+  }
+}
+
+/// `<stem>.md`, numbered like "ideas (1).md" if another tag already took the
+/// name. Names are compared case-insensitively, as most file systems do.
+fn unique_file_name(stem: &str, taken: &mut HashSet<String>) -> String {
+  let name = (0..)
+    .map(|n| match n {
+      0 => format!("{}.md", stem),
+      n => format!("{} ({}).md", stem, n),
+    })
+    .find(|name| !taken.contains(&name.to_lowercase()))
+    .expect("some numbered name is free");
+  taken.insert(name.to_lowercase());
+  name
+}
+
+/// Writes one Markdown file per tag into `dest_dir`, holding the highlights
+/// carrying the tag grouped by PDF, and returns the number of files written.
+/// Tags without highlights get no file; files of an earlier export with the
+/// same names are overwritten.
+#[tauri::command]
+pub async fn export_by_tag(app: AppHandle, dest_dir: String) -> Result<usize, AppError> {
+  let dest_dir = PathBuf::from(dest_dir);
+  let conn = db::open(&app)?;
+  let mut tags = tagged_highlights(&conn)?;
+  drop(conn);
+
+  fs::create_dir_all(&dest_dir)
+    .map_err(|e| AppError::Io(format!("Cannot create {}: {}", dest_dir.display(), e)))?;
+  let mut taken = HashSet::new();
+  for tag in &mut tags {
+    let dest = dest_dir.join(unique_file_name(&safe_file_stem(&tag.name), &mut taken));
+    fs::write(&dest, render_tag_markdown(tag))
+      .map_err(|e| AppError::Io(format!("Cannot write {}: {}", dest.display(), e)))?;
+  }
+  log::info!("Exported {} tags to {}", tags.len(), dest_dir.display());
+  Ok(tags.len())
+}
+
+/// Makes text safe for one field of an Anki TSV import. Fields are imported
+/// as HTML, so tabs and newlines are written as entities and `<br>` instead of
+/// splitting the field or the note.
+fn anki_field(text: &str) -> String {
+  let mut out = String::with_capacity(text.len());
+  let mut chars = text.trim().chars().peekable();
+  while let Some(c) = chars.next() {
+    match c {
+      '&' => out.push_str("&amp;"),
+      '<' => out.push_str("&lt;"),
+      '>' => out.push_str("&gt;"),
+      '"' => out.push_str("&quot;"),
+      '\t' => out.push_str("&#9;"),
+      '\r' => {
+        if chars.peek() == Some(&'\n') {
+          chars.next();
+        }
+        out.push_str("<br>");
+      }
+      '\n' => out.push_str("<br>"),
+      c => out.push(c),
+    }
+  }
+  out
+}
+
+/// One note per highlight that has both text and a comment: the highlighted
+/// text on the front, the comment on the back and, with `source`, the PDF
+/// name and page as a third field.
+fn render_anki(name: &str, rows: &[HighlightRecord], source: bool) -> (String, usize) {
+  let mut out = String::from("#separator:tab\n#html:true\n");
+  let mut cards = 0;
+  for row in rows {
+    let front = row.content_text.as_deref().unwrap_or("").trim();
+    let back = row.comment_text.as_deref().unwrap_or("").trim();
+    if front.is_empty() || back.is_empty() {
+      continue;
+    }
+    let _ = write!(out, "{}\t{}", anki_field(front), anki_field(back));
+    if source {
+      let _ = write!(
+        out,
+        "\t{}",
+        anki_field(&format!("{}, page {}", name, row.page_number))
+      );
+    }
+    out.push('\n');
+    cards += 1;
+  }
+  (out, cards)
+}
+
+/// Writes the PDF's commented highlights to `dest_path` as a tab-separated
+/// file Anki can import, and returns the number of cards written.
+#[tauri::command]
+pub async fn export_anki(
+  app: AppHandle,
+  pdf_id: i64,
+  dest_path: String,
+  include_source: Option<bool>,
+) -> Result<usize, AppError> {
+  let dest = PathBuf::from(dest_path);
+  let conn = db::open(&app)?;
+  let name = pdf_name(&conn, pdf_id)?;
+  let rows = highlights::in_reading_order(&conn, pdf_id)?;
+  let (tsv, cards) = render_anki(&name, &rows, include_source.unwrap_or(false));
+  fs::write(&dest, tsv)
+    .map_err(|e| AppError::Io(format!("Cannot write {}: {}", dest.display(), e)))?;
+  log::info!("Exported {} Anki cards to {}", cards, dest.display());
+  Ok(cards)
+}
+
+/// Tolerance for a highlight rect spilling over the page edge, as a fraction
+/// of the page size; selections dragged to the edge can overshoot slightly.
+const PAGE_EDGE_TOLERANCE: f64 = 0.01;
+
+/// Converts a highlight's bounding rect into PDF points within `page`, the
+/// visible area of a page turned by `rotate` degrees. Stored positions are in
+/// viewport pixels with a top-left origin, scaled by the viewport's `width` x
+/// `height`, unless saved in PDF coordinates; PDF space has a bottom-left
+/// origin.
+fn crop_rect(
+  position: &position::ScaledPosition,
+  page: &PdfBox,
+  rotate: i64,
+) -> Result<PdfBox, AppError> {
+  let pdf_coordinates = position.use_pdf_coordinates == Some(true);
+  let fractions = geometry::fractions(&position.bounding_rect, pdf_coordinates, page, rotate)
+    .ok_or_else(|| AppError::InvalidInput("Highlight has an empty viewport size".to_string()))?;
+  let on_page = |v: f64| (-PAGE_EDGE_TOLERANCE..=1.0 + PAGE_EDGE_TOLERANCE).contains(&v);
+  if !fractions.into_iter().all(on_page) {
+    return Err(AppError::InvalidInput(
+      "Highlight lies outside its page".to_string(),
+    ));
+  }
+  let [left, top, right, bottom] = fractions;
+  if right <= left || bottom <= top {
+    return Err(AppError::InvalidInput(
+      "Highlight has an empty area".to_string(),
+    ));
+  }
+  Ok(geometry::to_pdf_rect(
+    fractions.map(|v| v.clamp(0.0, 1.0)),
+    page,
+    rotate,
+  ))
+}
+
+fn write_region_pdf(
+  source: &Path,
+  position: &position::ScaledPosition,
+  dest: &Path,
+) -> Result<(), AppError> {
+  let mut document = Document::load(source)
+    .map_err(|e| AppError::Corrupt(format!("Cannot parse {}: {}", source.display(), e)))?;
+  // Saving would silently drop the encryption
+  if document.is_encrypted() {
+    return Err(AppError::Encrypted(format!(
+      "{} is encrypted",
+      source.display()
+    )));
+  }
+
+  let pages = document.get_pages();
+  let page_id = u32::try_from(position.page_number)
+    .ok()
+    .and_then(|number| pages.get(&number).copied())
+    .ok_or_else(|| {
+      AppError::InvalidInput(format!(
+        "Page {} is out of range (document has {} pages)",
+        position.page_number,
+        pages.len()
+      ))
+    })?;
+  let page = geometry::page_box(&document, page_id).ok_or_else(|| {
+    AppError::Corrupt(format!(
+      "Page {} of {} has no valid MediaBox",
+      position.page_number,
+      source.display()
+    ))
+  })?;
+  // The Rotate stays on the page, so the region shows the way it was selected
+  let rotate = geometry::rotation(&document, page_id);
+  let crop = crop_rect(position, &page, rotate)?;
+
+  let others: Vec<u32> = pages
+    .keys()
+    .copied()
+    .filter(|&number| i64::from(number) != position.page_number)
+    .collect();
+  document.delete_pages(&others);
+  // Outlines and named destinations would point at the dropped pages
+  if let Ok(catalog) = document.catalog_mut() {
+    for key in [&b"Outlines"[..], b"Dests", b"Names"] {
+      catalog.remove(key);
+    }
+  }
+  document.prune_objects();
+
+  let crop: Vec<Object> = crop.iter().map(|&v| Object::Real(v as f32)).collect();
+  let page = document
+    .get_dictionary_mut(page_id)
+    .map_err(|e| AppError::Corrupt(format!("Cannot edit page {}: {}", position.page_number, e)))?;
+  // Both boxes, so viewers that ignore the CropBox still show only the region
+  page.set("MediaBox", crop.clone());
+  page.set("CropBox", crop);
+  for key in [&b"BleedBox"[..], b"TrimBox", b"ArtBox"] {
+    page.remove(key);
+  }
+
+  document
+    .save(dest)
+    .map_err(|e| AppError::Io(format!("Cannot write {}: {}", dest.display(), e)))?;
+  Ok(())
+}
+
+/// Writes the region of a highlight as a standalone one-page PDF, for
+/// citing. The page content is kept as vectors, only its boxes are cropped.
+#[tauri::command]
+pub async fn export_highlight_region_pdf(
+  app: AppHandle,
+  highlight_id: String,
+  dest_path: String,
+) -> Result<(), AppError> {
+  let dest = PathBuf::from(dest_path);
+  let conn = db::open(&app)?;
+  let highlight = highlights::get(&conn, &highlight_id)?
+    .ok_or_else(|| AppError::NotFound(format!("Highlight {} not found", highlight_id)))?;
+  let pdf = pdfs::get(&conn, highlight.pdf_id)?
+    .ok_or_else(|| AppError::NotFound(format!("PDF {} not found", highlight.pdf_id)))?;
+  let position = position::parse(&highlight.position_data).map_err(|e| {
+    AppError::InvalidInput(format!(
+      "Highlight {} has invalid position data: {}",
+      highlight_id, e
+    ))
+  })?;
+  drop(conn);
+
+  let source = PathBuf::from(&pdf.path);
+  if optimize::same_file(&source, &dest) {
+    return Err(AppError::InvalidInput(
+      "dest_path must not be the PDF itself".to_string(),
+    ));
+  }
+  if let Err(e) = write_region_pdf(&source, &position, &dest) {
+    let _ = fs::remove_file(&dest);
+    return Err(e);
+  }
+  log::info!("Exported highlight {} to {}", highlight_id, dest.display());
+  Ok(())
+}
+
+/// The PNG of every image highlight of a PDF with its file name, or an error
+/// naming the highlights whose image can't be read or decoded.
+fn highlight_images(
+  images_dir: Option<&Path>,
+  rows: &[HighlightRecord],
+) -> Result<Vec<(String, Vec<u8>)>, AppError> {
+  let mut images = Vec::new();
+  let mut malformed = Vec::new();
+  for row in rows {
+    let png = match images::load(images_dir, row) {
+      Ok(Some(png)) => match image::guess_format(&png) {
+        Ok(ImageFormat::Png) => Ok(png),
+        _ => Err("Image is not a PNG".to_string()),
+      },
+      Ok(None) => continue,
+      Err(e) => Err(e),
+    };
+    match png {
+      Ok(png) => images.push((
+        format!(
+          "page{:02}-{}.png",
+          row.page_number,
+          safe_file_stem(&row.highlight_id)
+        ),
+        png,
+      )),
+      Err(e) => malformed.push(format!("{} ({})", row.highlight_id, e)),
+    }
+  }
+  if !malformed.is_empty() {
+    return Err(AppError::Corrupt(format!(
+      "Highlights with a missing or malformed image: {}",
+      malformed.join(", ")
+    )));
+  }
+  Ok(images)
+}
+
+/// Writes the image of every area highlight of a PDF to `dest_dir` as
+/// `page<NN>-<highlight_id>.png` and returns how many were written. If any
+/// image is malformed, nothing is written and the error names them all.
+#[tauri::command]
+pub async fn export_highlight_images(
+  app: AppHandle,
+  pdf_id: i64,
+  dest_dir: String,
+) -> Result<usize, AppError> {
+  let dest_dir = PathBuf::from(dest_dir);
+  let conn = db::open(&app)?;
+  // Fails for an unknown PDF instead of writing nothing
+  pdf_name(&conn, pdf_id)?;
+  let rows = highlights::list_for_pdf(&conn, pdf_id)?;
+  let images_dir = images::images_dir(&conn)?;
+  drop(conn);
+
+  let images = highlight_images(images_dir.as_deref(), &rows)?;
+  fs::create_dir_all(&dest_dir)
+    .map_err(|e| AppError::Io(format!("Cannot create {}: {}", dest_dir.display(), e)))?;
+  for (file_name, png) in &images {
+    let dest = dest_dir.join(file_name);
+    fs::write(&dest, png)
+      .map_err(|e| AppError::Io(format!("Cannot write {}: {}", dest.display(), e)))?;
+  }
+  log::info!(
+    "Exported {} highlight images of PDF {} to {}",
+    images.len(),
+    pdf_id,
+    dest_dir.display()
+  );
+  Ok(images.len())
+}
+
+/// Quotes a CSV field when it contains a separator, quote or line break
+/// (RFC 4180).
+fn csv_field(value: &str) -> String {
+  if value.contains([',', '"', '\n', '\r']) {
+    format!("\"{}\"", value.replace('"', "\"\""))
+  } else {
+    value.to_string()
+  }
+}
+
+fn render_tags_csv(conn: &Connection) -> rusqlite::Result<(String, usize)> {
+  let mut stmt = conn.prepare(
+    "SELECT t.name, COUNT(h.id), t.created_at
+     FROM tags t
+     LEFT JOIN highlight_tags ht ON ht.tag_id = t.id
+     LEFT JOIN highlights h ON h.highlight_id = ht.highlight_id AND h.deleted_at IS NULL
+     GROUP BY t.id, t.name, t.created_at
+     ORDER BY t.name ASC",
+  )?;
+  let rows = stmt.query_map([], |row| {
+    Ok((
+      row.get::<_, String>(0)?,
+      row.get::<_, i64>(1)?,
+      row.get::<_, Option<String>>(2)?,
+    ))
+  })?;
+
+  let mut out = String::from("name,highlight_count,created_at\n");
+  let mut count = 0;
+  for row in rows {
+    let (name, highlight_count, created_at) = row?;
+    let _ = writeln!(
+      out,
+      "{},{},{}",
+      csv_field(&name),
+      highlight_count,
+      csv_field(created_at.as_deref().unwrap_or(""))
+    );
+    count += 1;
+  }
+  Ok((out, count))
+}
+
+/// Writes every tag with its number of highlights to `dest_path` as CSV and
+/// returns the number of tags written.
+#[tauri::command]
+pub async fn export_tags_csv(app: AppHandle, dest_path: String) -> Result<usize, AppError> {
+  let dest = PathBuf::from(dest_path);
+  let conn = db::open(&app)?;
+  let (csv, count) = render_tags_csv(&conn)?;
+  fs::write(&dest, csv)
+    .map_err(|e| AppError::Io(format!("Cannot write {}: {}", dest.display(), e)))?;
+  log::info!("Exported {} tags to {}", count, dest.display());
+  Ok(count)
+}
+
+fn render_highlights_csv(
+  conn: &Connection,
+  pdf_id: Option<i64>,
+) -> rusqlite::Result<(String, usize)> {
+  let tags = tag_names_for_pdf(conn, pdf_id)?;
+  let mut stmt = conn.prepare(&format!(
+    "SELECT {}, p.name AS pdf_name FROM highlights h
+     JOIN pdfs p ON p.id = h.pdf_id
+     WHERE (?1 IS NULL OR h.pdf_id = ?1) AND h.deleted_at IS NULL
+     ORDER BY p.name COLLATE NOCASE, p.id, h.page_number, h.id",
+    highlights::COLUMNS
+  ))?;
+  let rows = stmt.query_map(params![pdf_id], |row| {
+    Ok((
+      row.get::<_, String>("pdf_name")?,
+      HighlightRecord::from_row(row)?,
+    ))
+  })?;
+
+  let mut out = String::from("pdf_name,page,content_text,comment,emoji,color,tags,created_at\n");
+  let mut count = 0;
+  for row in rows {
+    let (pdf_name, record) = row?;
+    let tag_names = tags
+      .get(&record.highlight_id)
+      .map(|names| names.join(";"))
+      .unwrap_or_default();
+    let _ = writeln!(
+      out,
+      "{},{},{},{},{},{},{},{}",
+      csv_field(&pdf_name),
+      record.page_number,
+      csv_field(record.content_text.as_deref().unwrap_or("")),
+      csv_field(record.comment_text.as_deref().unwrap_or("")),
+      csv_field(record.comment_emoji.as_deref().unwrap_or("")),
+      csv_field(record.color.as_deref().unwrap_or("")),
+      csv_field(&tag_names),
+      csv_field(&record.created_at)
+    );
+    count += 1;
+  }
+  Ok((out, count))
+}
+
+/// Writes the highlights of a PDF, or of the whole library with `pdf_id`
+/// `None`, to `dest_path` as CSV with one flat row per highlight and tags
+/// joined by `;`, and returns the number of rows written. Trashed
+/// highlights are left out.
+#[tauri::command]
+pub async fn export_highlights_csv(
+  app: AppHandle,
+  pdf_id: Option<i64>,
+  dest_path: String,
+) -> Result<usize, AppError> {
+  let dest = PathBuf::from(dest_path);
+  let conn = db::open(&app)?;
+  if let Some(pdf_id) = pdf_id {
+    // Fails for an unknown PDF instead of writing an empty file
+    pdf_name(&conn, pdf_id)?;
+  }
+  let (csv, count) = render_highlights_csv(&conn, pdf_id)?;
+  fs::write(&dest, csv)
+    .map_err(|e| AppError::Io(format!("Cannot write {}: {}", dest.display(), e)))?;
+  log::info!("Exported {} highlights to {}", count, dest.display());
+  Ok(count)
+}
+
+#[derive(Debug, Clone, Copy, Deserialize)]
+pub enum CitationTemplate {
+  /// One BibTeX `@comment{...}` entry per highlight, which BibTeX ignores,
+  /// so the notes can live inside the `.bib` file.
+  BibtexComment,
+  /// One `\annotation{citekey}{page}{quote}{comment}` line per highlight, for
+  /// a LaTeX document defining that macro.
+  LatexAnnotation,
+}
+
+/// Escapes LaTeX special characters, and flattens line breaks so each note
+/// stays on one line.
+fn latex_escape(text: &str) -> String {
+  let mut out = String::with_capacity(text.len());
+  for c in text.trim().chars() {
+    match c {
+      '\\' => out.push_str("\\textbackslash{}"),
+      '%' | '&' | '_' | '#' | '$' | '{' | '}' => {
+        out.push('\\');
+        out.push(c);
+      }
+      '~' => out.push_str("\\textasciitilde{}"),
+      '^' => out.push_str("\\textasciicircum{}"),
+      '\r' => {}
+      '\n' => out.push(' '),
+      c => out.push(c),
+    }
+  }
+  out
+}
+
+fn render_citation_notes(
+  rows: &[HighlightRecord],
+  citekey: &str,
+  template: CitationTemplate,
+) -> String {
+  let citekey = latex_escape(citekey);
+  let mut out = String::new();
+  for row in rows {
+    let quote = match &row.content_text {
+      Some(text) if !text.trim().is_empty() => latex_escape(text),
+      _ if row.has_image() => "[Image highlight]".to_string(),
+      _ => String::new(),
+    };
+    let comment = latex_escape(row.comment_text.as_deref().unwrap_or(""));
+    let _ = match template {
+      CitationTemplate::BibtexComment => writeln!(
+        out,
+        "@comment{{\n  citekey = {{{}}},\n  page = {{{}}},\n  quote = {{{}}},\n  comment = {{{}}}\n}}\n",
+        citekey, row.page_number, quote, comment
+      ),
+      CitationTemplate::LatexAnnotation => writeln!(
+        out,
+        "\\annotation{{{}}}{{{}}}{{{}}}{{{}}}",
+        citekey, row.page_number, quote, comment
+      ),
+    };
+  }
+  out
+}
+
+/// Writes the PDF's highlights in reading order as citation notes for
+/// `citekey`, in the chosen template, to `dest_path`.
+#[tauri::command]
+pub async fn export_notes_for_citation(
+  app: AppHandle,
+  pdf_id: i64,
+  citekey: String,
+  dest_path: String,
+  template: CitationTemplate,
+) -> Result<(), AppError> {
+  let citekey = citekey.trim();
+  if citekey.is_empty() {
+    return Err(AppError::InvalidInput(
+      "citekey must not be empty".to_string(),
+    ));
+  }
+  let dest = PathBuf::from(dest_path);
+  let conn = db::open(&app)?;
+  // Fails for an unknown PDF instead of writing an empty file
+  pdf_name(&conn, pdf_id)?;
+  let rows = highlights::in_reading_order(&conn, pdf_id)?;
+  let notes = render_citation_notes(&rows, citekey, template);
+  fs::write(&dest, notes)
+    .map_err(|e| AppError::Io(format!("Cannot write {}: {}", dest.display(), e)))?;
+  log::info!(
+    "Exported citation notes for {} to {}",
+    citekey,
+    dest.display()
+  );
+  Ok(())
+}
+
+/// Escapes text for HTML element content and quoted attribute values.
+pub fn html_escape(text: &str) -> String {
+  let mut escaped = String::with_capacity(text.len());
+  for c in text.chars() {
+    match c {
+      '&' => escaped.push_str("&amp;"),
+      '<' => escaped.push_str("&lt;"),
+      '>' => escaped.push_str("&gt;"),
+      '"' => escaped.push_str("&quot;"),
+      '\'' => escaped.push_str("&#39;"),
+      _ => escaped.push(c),
+    }
+  }
+  escaped
+}
+
+const HTML_REPORT_STYLE: &str = "
+  body { font-family: -apple-system, 'Segoe UI', Roboto, sans-serif; max-width: 52rem;
+    margin: 2rem auto; padding: 0 1rem; color: #222; background: #fafafa; line-height: 1.5; }
+  h1 { margin-bottom: 0.25rem; }
+  .summary { color: #666; margin-top: 0; }
+  nav ul { list-style: none; padding: 0; display: flex; flex-wrap: wrap; gap: 0.5rem; }
+  nav a { display: inline-block; padding: 0.2rem 0.6rem; border-radius: 1rem;
+    background: #e8e8e8; color: #222; text-decoration: none; font-size: 0.9rem; }
+  h2 { margin-top: 2rem; border-bottom: 1px solid #ddd; padding-bottom: 0.25rem; }
+  .card { background: #fff; border-radius: 0.5rem; box-shadow: 0 1px 3px rgba(0,0,0,0.12);
+    padding: 1rem 1rem 1rem 1.25rem; margin: 1rem 0; border-left: 0.4rem solid var(--color); }
+  .card blockquote { margin: 0; white-space: pre-wrap; }
+  .card img { max-width: 100%; border: 1px solid #eee; }
+  .missing { color: #999; font-style: italic; }
+  .comment { margin: 0.75rem 0 0; padding: 0.5rem 0.75rem; background: #f4f4f4;
+    border-radius: 0.25rem; white-space: pre-wrap; }
+  .meta { display: flex; align-items: center; gap: 0.5rem; margin-top: 0.75rem;
+    font-size: 0.85rem; color: #666; }
+  .swatch { width: 0.9rem; height: 0.9rem; border-radius: 50%; background: var(--color);
+    border: 1px solid rgba(0,0,0,0.2); }
+  .tag { padding: 0.05rem 0.5rem; border-radius: 1rem; background: #eef; color: #335; }
+";
+
+/// Renders a standalone HTML page of a PDF's highlights. `images` holds the
+/// data URL of each image highlight that could be read, keyed by
+/// `highlight_id`.
+fn render_html_report(
+  name: &str,
+  rows: &[HighlightRecord],
+  tags: &HashMap<String, Vec<String>>,
+  images: &HashMap<String, String>,
+) -> String {
+  let title = html_escape(name);
+  let mut out = String::new();
+  let _ = write!(
+    out,
+    "<!DOCTYPE html>\n<html lang=\"en\">\n<head>\n<meta charset=\"utf-8\">\n\
+     <meta name=\"viewport\" content=\"width=device-width, initial-scale=1\">\n\
+     <title>{}</title>\n<style>{}</style>\n</head>\n<body>\n<h1>{}</h1>\n",
+    title, HTML_REPORT_STYLE, title
+  );
+  let _ = writeln!(
+    out,
+    "<p class=\"summary\">{} highlight{}</p>",
+    rows.len(),
+    if rows.len() == 1 { "" } else { "s" }
+  );
+
+  let mut pages: Vec<(i64, usize)> = Vec::new();
+  for row in rows {
+    match pages.last_mut() {
+      Some((page, count)) if *page == row.page_number => *count += 1,
+      _ => pages.push((row.page_number, 1)),
+    }
+  }
+  out.push_str("<nav>\n<ul>\n");
+  for (page, count) in &pages {
+    let _ = writeln!(
+      out,
+      "<li><a href=\"#page-{}\">Page {} ({})</a></li>",
+      page, page, count
+    );
+  }
+  out.push_str("</ul>\n</nav>\n");
+
+  let mut current_page = None;
+  for row in rows {
+    if current_page != Some(row.page_number) {
+      current_page = Some(row.page_number);
+      let _ = writeln!(
+        out,
+        "<h2 id=\"page-{}\">Page {}</h2>",
+        row.page_number, row.page_number
+      );
+    }
+    // Only validated colors go into the style attribute
+    let swatch = row
+      .color
+      .as_deref()
+      .filter(|hex| color::validate_hex(hex).is_ok())
+      .unwrap_or("#ffff00");
+    let _ = writeln!(
+      out,
+      "<article class=\"card\" style=\"--color: {}\">",
+      swatch
+    );
+
+    match &row.content_text {
+      Some(text) if !text.is_empty() => {
+        let _ = writeln!(out, "<blockquote>{}</blockquote>", html_escape(text));
+      }
+      _ if row.has_image() => match images.get(&row.highlight_id) {
+        Some(data_url) => {
+          let _ = writeln!(
+            out,
+            "<img src=\"{}\" alt=\"Image highlight on page {}\">",
+            data_url, row.page_number
+          );
+        }
+        None => out.push_str("<p class=\"missing\">Image unavailable</p>\n"),
+      },
+      _ => out.push_str("<p class=\"missing\">Empty highlight</p>\n"),
+    }
+
+    let comment = row.comment_text.as_deref().unwrap_or("").trim();
+    let emoji = row.comment_emoji.as_deref().unwrap_or("").trim();
+    if !comment.is_empty() || !emoji.is_empty() {
+      let line = [emoji, comment]
+        .iter()
+        .filter(|s| !s.is_empty())
+        .copied()
+        .collect::<Vec<_>>()
+        .join(" ");
+      let _ = writeln!(out, "<p class=\"comment\">{}</p>", html_escape(&line));
+    }
+
+    let _ = write!(
+      out,
+      "<div class=\"meta\"><span class=\"swatch\" title=\"{}\"></span>",
+      swatch
+    );
+    for tag in tags.get(&row.highlight_id).into_iter().flatten() {
+      let _ = write!(out, "<span class=\"tag\">{}</span>", html_escape(tag));
+    }
+    out.push_str("</div>\n</article>\n");
+  }
+  out.push_str("</body>\n</html>\n");
+  out
+}
+
+/// Writes a self-contained HTML report of the PDF's highlights to
+/// `dest_path`: a table of contents by page, then each highlight as a card
+/// with its quote or image, comment, color and tags. Images are embedded, so
+/// the file opens anywhere; one that can't be read is noted in its card.
+#[tauri::command]
+pub async fn export_html_report(
+  app: AppHandle,
+  pdf_id: i64,
+  dest_path: String,
+) -> Result<(), AppError> {
+  let dest = PathBuf::from(dest_path);
+  let conn = db::open(&app)?;
+  let name = pdf_name(&conn, pdf_id)?;
+  let rows = highlights::in_reading_order(&conn, pdf_id)?;
+  let tags = tag_names_for_pdf(&conn, Some(pdf_id))?;
+  let images_dir = images::images_dir(&conn)?;
+  drop(conn);
+
+  let mut data_urls = HashMap::new();
+  for row in &rows {
+    match images::load(images_dir.as_deref(), row) {
+      Ok(Some(png)) => {
+        data_urls.insert(row.highlight_id.clone(), render::data_url(&png));
+      }
+      Ok(None) => {}
+      Err(e) => log::warn!(
+        "Highlight {} exported without its image: {}",
+        row.highlight_id,
+        e
+      ),
+    }
+  }
+
+  let html = render_html_report(&name, &rows, &tags, &data_urls);
+  fs::write(&dest, html)
+    .map_err(|e| AppError::Io(format!("Cannot write {}: {}", dest.display(), e)))?;
+  log::info!(
+    "Exported an HTML report of PDF {} to {}",
+    pdf_id,
+    dest.display()
+  );
+  Ok(())
+}
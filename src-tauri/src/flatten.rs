@@ -0,0 +1,274 @@
+//! Burning highlights into a copy of a PDF, for archiving. Each highlight is
+//! painted into its page's content with a multiply blend, like a marker
+//! over the text, so the copy shows it in any viewer while the text stays
+//! selectable. pdfium can only save documents as they are, so this uses
+//! lopdf, like optimize.rs.
+
+use lopdf::{dictionary, Dictionary, Document, Object, ObjectId, Stream};
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt::Write;
+use std::fs;
+use std::path::{Path, PathBuf};
+use tauri::AppHandle;
+
+use crate::error::AppError;
+use crate::geometry::{self, inherited, resolve, PdfBox};
+use crate::highlights::{self, HighlightRecord};
+use crate::position::Scaled;
+use crate::{color, db, optimize, pdfs};
+
+/// Color of highlights without a valid one, the viewer's default yellow.
+const DEFAULT_RGB: [u8; 3] = [255, 255, 0];
+
+/// Stroke width in points of the frame drawn around area highlights, which
+/// are outlined rather than filled so the figure stays legible.
+const AREA_BORDER_WIDTH: f64 = 1.5;
+
+/// Prefix of the graphics state resources added to pages, one per opacity.
+const GRAPHICS_STATE_PREFIX: &str = "PdfHighlighterGS";
+
+/// A highlight rect from the viewer in PDF points, or `None` when it is
+/// degenerate or entirely off the page. `pdf_coordinates` is the position's
+/// `usePdfCoordinates`.
+fn pdf_rect(rect: &Scaled, pdf_coordinates: bool, page: &PdfBox, rotate: i64) -> Option<PdfBox> {
+  let fractions =
+    geometry::fractions(rect, pdf_coordinates, page, rotate)?.map(|v| v.clamp(0.0, 1.0));
+  let rect = geometry::to_pdf_rect(fractions, page, rotate);
+  (rect[2] > rect[0] && rect[3] > rect[1]).then_some(rect)
+}
+
+/// Appends the operators painting `record` to `content` and returns the
+/// opacity it is painted with, or `None` when none of its rects is on the
+/// page. Text highlights spanning several lines fill one rect per line.
+fn paint(content: &mut String, record: &HighlightRecord, page: &PdfBox, rotate: i64) -> Option<u8> {
+  let position = match record.position() {
+    Ok(position) => position,
+    Err(e) => {
+      log::warn!(
+        "Highlight {} has an unreadable position: {}",
+        record.highlight_id,
+        e
+      );
+      return None;
+    }
+  };
+  let pdf_coordinates = position.use_pdf_coordinates == Some(true);
+  // Area highlights only have a bounding rect
+  let rects: Vec<PdfBox> = if position.rects.is_empty() {
+    pdf_rect(&position.bounding_rect, pdf_coordinates, page, rotate)
+      .into_iter()
+      .collect()
+  } else {
+    position
+      .rects
+      .iter()
+      .filter_map(|rect| pdf_rect(rect, pdf_coordinates, page, rotate))
+      .collect()
+  };
+  if rects.is_empty() {
+    return None;
+  }
+
+  let (rgb, alpha) = record
+    .color
+    .as_deref()
+    .and_then(color::parse_hex)
+    .unwrap_or((DEFAULT_RGB, None));
+  let alpha = alpha.unwrap_or(u8::MAX);
+  let [r, g, b] = rgb.map(|channel| f64::from(channel) / 255.0);
+
+  let _ = writeln!(content, "q /{}{} gs", GRAPHICS_STATE_PREFIX, alpha);
+  if record.has_image() {
+    let _ = writeln!(
+      content,
+      "{:.3} {:.3} {:.3} RG {} w",
+      r, g, b, AREA_BORDER_WIDTH
+    );
+  } else {
+    let _ = writeln!(content, "{:.3} {:.3} {:.3} rg", r, g, b);
+  }
+  for [left, bottom, right, top] in rects {
+    let _ = writeln!(
+      content,
+      "{:.3} {:.3} {:.3} {:.3} re",
+      left,
+      bottom,
+      right - left,
+      top - bottom
+    );
+  }
+  content.push_str(if record.has_image() {
+    "S\nQ\n"
+  } else {
+    "f\nQ\n"
+  });
+  Some(alpha)
+}
+
+/// Gives the page its own copy of its resources, inherited or shared ones
+/// included, with a multiply-blend graphics state for each opacity.
+fn add_graphics_states(
+  document: &mut Document,
+  page_id: ObjectId,
+  alphas: &BTreeSet<u8>,
+) -> lopdf::Result<()> {
+  let mut resources = match inherited(document, page_id, b"Resources") {
+    Some(Object::Dictionary(resources)) => resources.clone(),
+    _ => Dictionary::new(),
+  };
+  let mut states = match resources
+    .get(b"ExtGState")
+    .map(|states| resolve(document, states))
+  {
+    Ok(Object::Dictionary(states)) => states.clone(),
+    _ => Dictionary::new(),
+  };
+  for &alpha in alphas {
+    let opacity = f32::from(alpha) / 255.0;
+    states.set(
+      format!("{}{}", GRAPHICS_STATE_PREFIX, alpha),
+      dictionary! {
+        "Type" => "ExtGState",
+        "BM" => "Multiply",
+        "ca" => opacity,
+        "CA" => opacity,
+      },
+    );
+  }
+  resources.set("ExtGState", states);
+  document
+    .get_dictionary_mut(page_id)?
+    .set("Resources", resources);
+  Ok(())
+}
+
+/// Adds `content` after the page's own, which is wrapped in `q`/`Q` so a
+/// graphics state it leaves behind can't move or clip the highlights.
+fn append_content(
+  document: &mut Document,
+  page_id: ObjectId,
+  content: String,
+) -> lopdf::Result<()> {
+  let existing = match document.get_dictionary(page_id)?.get(b"Contents") {
+    Ok(Object::Array(streams)) => streams.clone(),
+    Ok(Object::Reference(id)) => match document.get_object(*id) {
+      Ok(Object::Array(streams)) => streams.clone(),
+      _ => vec![Object::Reference(*id)],
+    },
+    _ => Vec::new(),
+  };
+
+  let save = document.add_object(Stream::new(dictionary! {}, b"q\n".to_vec()));
+  let paint = document.add_object(Stream::new(
+    dictionary! {},
+    format!("Q\n{}", content).into_bytes(),
+  ));
+  let mut contents = vec![Object::Reference(save)];
+  contents.extend(existing);
+  contents.push(Object::Reference(paint));
+  document
+    .get_dictionary_mut(page_id)?
+    .set("Contents", contents);
+  Ok(())
+}
+
+/// Writes a copy of `source` with `rows` painted in to `dest` and returns the
+/// number of highlights painted.
+fn flatten(source: &Path, dest: &Path, rows: &[HighlightRecord]) -> Result<usize, AppError> {
+  let mut document = Document::load(source)
+    .map_err(|e| AppError::Corrupt(format!("Cannot parse {}: {}", source.display(), e)))?;
+  // Saving would silently drop the encryption
+  if document.is_encrypted() {
+    return Err(AppError::Encrypted(format!(
+      "{} is encrypted",
+      source.display()
+    )));
+  }
+
+  let pages = document.get_pages();
+  let mut by_page: BTreeMap<i64, Vec<&HighlightRecord>> = BTreeMap::new();
+  for row in rows {
+    by_page.entry(row.page_number).or_default().push(row);
+  }
+
+  let mut painted = 0;
+  for (page_number, records) in by_page {
+    let page = u32::try_from(page_number)
+      .ok()
+      .and_then(|number| pages.get(&number).copied())
+      .and_then(|page_id| Some((page_id, geometry::page_box(&document, page_id)?)));
+    let Some((page_id, page)) = page else {
+      log::warn!(
+        "Skipped {} highlights on page {} of {}, which has no such page",
+        records.len(),
+        page_number,
+        source.display()
+      );
+      continue;
+    };
+    let rotate = geometry::rotation(&document, page_id);
+
+    let mut content = String::new();
+    let mut alphas = BTreeSet::new();
+    for record in records {
+      if let Some(alpha) = paint(&mut content, record, &page, rotate) {
+        alphas.insert(alpha);
+        painted += 1;
+      }
+    }
+    if content.is_empty() {
+      continue;
+    }
+    add_graphics_states(&mut document, page_id, &alphas)
+      .and_then(|()| append_content(&mut document, page_id, content))
+      .map_err(|e| AppError::Corrupt(format!("Cannot edit page {}: {}", page_number, e)))?;
+  }
+
+  if let Err(e) = document.save(dest) {
+    let _ = fs::remove_file(dest);
+    return Err(AppError::Io(format!(
+      "Cannot write {}: {}",
+      dest.display(),
+      e
+    )));
+  }
+  Ok(painted)
+}
+
+/// Writes a copy of a PDF to `dest_path` with its highlights, trashed ones
+/// aside, drawn permanently into the pages in their colors. Unlike
+/// `render_page_with_highlights` the result is still a real PDF with
+/// selectable text. The library's file is never modified, so `dest_path`
+/// must be another file.
+#[tauri::command]
+pub async fn export_flattened_pdf(
+  app: AppHandle,
+  pdf_id: i64,
+  dest_path: String,
+) -> Result<(), AppError> {
+  let conn = db::open(&app)?;
+  let pdf = pdfs::get(&conn, pdf_id)?
+    .ok_or_else(|| AppError::NotFound(format!("PDF {} not found", pdf_id)))?;
+  let rows = highlights::list_for_pdf(&conn, pdf_id)?;
+  drop(conn);
+
+  let source = PathBuf::from(&pdf.path);
+  let dest = PathBuf::from(&dest_path);
+  if optimize::same_file(&source, &dest) {
+    return Err(AppError::InvalidInput(
+      "dest_path must not be the PDF itself".to_string(),
+    ));
+  }
+
+  // Parsing and rewriting a large document takes a while
+  let painted = tauri::async_runtime::spawn_blocking(move || flatten(&source, &dest, &rows))
+    .await
+    .map_err(|e| format!("Flattening failed: {}", e))??;
+  log::info!(
+    "Burned {} highlights of {} into {}",
+    painted,
+    pdf.path,
+    dest_path
+  );
+  Ok(())
+}
@@ -0,0 +1,174 @@
+//! Page geometry shared by the exports that write PDFs and the highlight
+//! overlay: which area of a page the viewer shows, and where a spot of it
+//! lies in PDF points. Boxes of PDFs opened with lopdf are read here; pdfium
+//! callers read their own and pass them in.
+
+use lopdf::{Document, Object, ObjectId};
+
+use crate::position::Scaled;
+
+/// Depth past which a `Parent` chain is taken to be cyclic.
+const MAX_TREE_DEPTH: usize = 64;
+
+/// A rectangle in PDF points as `[left, bottom, right, top]`.
+pub type PdfBox = [f64; 4];
+
+pub fn resolve<'a>(document: &'a Document, object: &'a Object) -> &'a Object {
+  match object {
+    Object::Reference(id) => document.get_object(*id).unwrap_or(object),
+    _ => object,
+  }
+}
+
+/// A page attribute, looked up through the `Parent` chain since `MediaBox`,
+/// `CropBox`, `Rotate` and `Resources` are inherited from the page tree.
+pub fn inherited<'a>(document: &'a Document, page_id: ObjectId, key: &[u8]) -> Option<&'a Object> {
+  let mut node = document.get_dictionary(page_id).ok()?;
+  for _ in 0..MAX_TREE_DEPTH {
+    if let Ok(value) = node.get(key) {
+      return Some(resolve(document, value));
+    }
+    let parent = node.get(b"Parent").and_then(Object::as_reference).ok()?;
+    node = document.get_dictionary(parent).ok()?;
+  }
+  None
+}
+
+fn read_box(document: &Document, object: &Object) -> Option<PdfBox> {
+  let values = object.as_array().ok()?;
+  if values.len() != 4 {
+    return None;
+  }
+  let mut corners = [0.0; 4];
+  for (corner, value) in corners.iter_mut().zip(values) {
+    *corner = f64::from(resolve(document, value).as_float().ok()?);
+  }
+  // Any two opposite corners may be given
+  let [a, b, c, d] = corners;
+  Some([a.min(c), b.min(d), a.max(c), b.max(d)])
+}
+
+/// The visible area of a page: its crop box clipped to its media box, which
+/// is what the viewer laid highlights out on.
+pub fn visible_box(media: PdfBox, crop: Option<PdfBox>) -> PdfBox {
+  crop
+    .map(|crop| {
+      [
+        crop[0].max(media[0]),
+        crop[1].max(media[1]),
+        crop[2].min(media[2]),
+        crop[3].min(media[3]),
+      ]
+    })
+    .filter(|crop| crop[2] > crop[0] && crop[3] > crop[1])
+    .unwrap_or(media)
+}
+
+/// `visible_box` of a page of a lopdf document.
+pub fn page_box(document: &Document, page_id: ObjectId) -> Option<PdfBox> {
+  let media = read_box(document, inherited(document, page_id, b"MediaBox")?)?;
+  let crop = inherited(document, page_id, b"CropBox").and_then(|crop| read_box(document, crop));
+  Some(visible_box(media, crop))
+}
+
+/// The page's `Rotate` in degrees, between 0 and 359.
+pub fn rotation(document: &Document, page_id: ObjectId) -> i64 {
+  inherited(document, page_id, b"Rotate")
+    .and_then(|rotate| rotate.as_i64().ok())
+    .unwrap_or(0)
+    .rem_euclid(360)
+}
+
+/// Maps a point of the page as displayed, in fractions of its width and
+/// height from the top-left corner, to PDF points, undoing the page's
+/// `Rotate`.
+fn to_pdf(u: f64, v: f64, page: &PdfBox, rotate: i64) -> (f64, f64) {
+  let [left, bottom, right, top] = *page;
+  let (width, height) = (right - left, top - bottom);
+  match rotate {
+    90 => (left + v * width, bottom + u * height),
+    180 => (right - u * width, bottom + v * height),
+    270 => (right - v * width, top - u * height),
+    _ => (left + u * width, top - v * height),
+  }
+}
+
+/// Maps a rect of the page as displayed, `[left, top, right, bottom]` in
+/// fractions like `Scaled::normalized`, to PDF points.
+pub fn to_pdf_rect(rect: [f64; 4], page: &PdfBox, rotate: i64) -> PdfBox {
+  let [left, top, right, bottom] = rect;
+  let (ax, ay) = to_pdf(left, top, page, rotate);
+  let (bx, by) = to_pdf(right, bottom, page, rotate);
+  [ax.min(bx), ay.min(by), ax.max(bx), ay.max(by)]
+}
+
+/// The inverse of `to_pdf`.
+fn from_pdf(x: f64, y: f64, page: &PdfBox, rotate: i64) -> (f64, f64) {
+  let [left, bottom, right, top] = *page;
+  let (width, height) = (right - left, top - bottom);
+  match rotate {
+    90 => ((y - bottom) / height, (x - left) / width),
+    180 => ((right - x) / width, (y - bottom) / height),
+    270 => ((top - y) / height, (right - x) / width),
+    _ => ((x - left) / width, (top - y) / height),
+  }
+}
+
+/// A highlight rect as `[left, top, right, bottom]` fractions of the page as
+/// displayed, like `Scaled::normalized`. Positions saved with
+/// `usePdfCoordinates` hold PDF points with a bottom-left origin instead of
+/// viewport pixels, so they are mapped through the page's visible box and
+/// rotation. `None` for a degenerate page size.
+pub fn fractions(
+  rect: &Scaled,
+  pdf_coordinates: bool,
+  page: &PdfBox,
+  rotate: i64,
+) -> Option<[f64; 4]> {
+  if !pdf_coordinates {
+    return rect.normalized();
+  }
+  if page[2] <= page[0] || page[3] <= page[1] {
+    return None;
+  }
+  let (au, av) = from_pdf(rect.x1, rect.y1, page, rotate);
+  let (bu, bv) = from_pdf(rect.x2, rect.y2, page, rotate);
+  Some([au.min(bu), av.min(bv), au.max(bu), av.max(bv)])
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn pdf_points_map_back_to_the_displayed_fractions() {
+    let page = [10.0, 20.0, 610.0, 820.0];
+    for rotate in [0, 90, 180, 270] {
+      let (x, y) = to_pdf(0.25, 0.75, &page, rotate);
+      let (u, v) = from_pdf(x, y, &page, rotate);
+      assert!(
+        (u - 0.25).abs() < 1e-9 && (v - 0.75).abs() < 1e-9,
+        "{}",
+        rotate
+      );
+    }
+  }
+
+  #[test]
+  fn pdf_coordinates_have_a_bottom_left_origin() {
+    let rect = Scaled {
+      x1: 10.0,
+      y1: 720.0,
+      x2: 310.0,
+      y2: 820.0,
+      width: 600.0,
+      height: 800.0,
+      page_number: None,
+    };
+    let page = [10.0, 20.0, 610.0, 820.0];
+    assert_eq!(
+      fractions(&rect, true, &page, 0),
+      Some([0.0, 0.0, 0.5, 0.125])
+    );
+  }
+}
@@ -0,0 +1,128 @@
+//! Per-PDF reading goals: a page to reach, optionally by a deadline.
+
+use chrono::{NaiveDateTime, Utc};
+use rusqlite::{params, Connection, OptionalExtension};
+use serde::Serialize;
+use tauri::AppHandle;
+
+use crate::error::AppError;
+use crate::highlights::{self, RangeBound};
+use crate::{db, pdfs, render};
+
+#[derive(Debug, Serialize)]
+pub struct ReadingGoal {
+  /// Page to have read up to.
+  pub goal: i64,
+  /// `YYYY-MM-DD HH:MM:SS` UTC, like the other timestamps.
+  pub deadline: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct GoalProgress {
+  pub goal: i64,
+  pub deadline: Option<String>,
+  /// Page the document was last viewed at.
+  pub page: i64,
+  /// `None` when the file can't be read right now.
+  pub page_count: Option<u32>,
+  /// How far `page` is towards the goal, capped at the page count, from 0 to
+  /// 100.
+  pub percent: f64,
+  /// Whole days left until the deadline, negative once it has passed.
+  pub days_remaining: Option<i64>,
+}
+
+fn get(conn: &Connection, pdf_id: i64) -> Result<Option<ReadingGoal>, AppError> {
+  let (goal, deadline): (Option<i64>, Option<String>) = conn
+    .query_row(
+      "SELECT reading_goal, goal_deadline FROM pdfs WHERE id = ?1",
+      params![pdf_id],
+      |row| Ok((row.get(0)?, row.get(1)?)),
+    )
+    .optional()?
+    .ok_or_else(|| AppError::NotFound(format!("PDF {} not found", pdf_id)))?;
+  Ok(goal.map(|goal| ReadingGoal { goal, deadline }))
+}
+
+/// Sets the page to have read up to by `deadline` (ISO-8601; a bare date
+/// means the end of that day, UTC), or clears the goal when `goal` is
+/// `None`.
+#[tauri::command]
+pub async fn set_reading_goal(
+  app: AppHandle,
+  pdf_id: i64,
+  goal: Option<i64>,
+  deadline: Option<String>,
+) -> Result<(), AppError> {
+  if goal.is_some_and(|goal| goal < 1) {
+    return Err(AppError::InvalidInput(
+      "goal must be 1 or greater".to_string(),
+    ));
+  }
+  if goal.is_none() && deadline.is_some() {
+    return Err(AppError::InvalidInput(
+      "A deadline needs a goal".to_string(),
+    ));
+  }
+  let deadline = deadline
+    .map(|deadline| highlights::sqlite_timestamp(&deadline, RangeBound::To))
+    .transpose()
+    .map_err(AppError::InvalidInput)?;
+
+  let conn = db::open(&app)?;
+  let updated = conn.execute(
+    "UPDATE pdfs SET reading_goal = ?1, goal_deadline = ?2 WHERE id = ?3",
+    params![goal, deadline, pdf_id],
+  )?;
+  if updated == 0 {
+    return Err(AppError::NotFound(format!("PDF {} not found", pdf_id)));
+  }
+  Ok(())
+}
+
+/// The PDF's reading goal, or `None` if it has none.
+#[tauri::command]
+pub async fn get_reading_goal(
+  app: AppHandle,
+  pdf_id: i64,
+) -> Result<Option<ReadingGoal>, AppError> {
+  let conn = db::open(&app)?;
+  get(&conn, pdf_id)
+}
+
+/// Progress towards the PDF's reading goal from the page it was last viewed
+/// at, or `None` if it has no goal.
+#[tauri::command]
+pub async fn reading_goal_progress(
+  app: AppHandle,
+  pdf_id: i64,
+) -> Result<Option<GoalProgress>, AppError> {
+  let conn = db::open(&app)?;
+  let Some(ReadingGoal { goal, deadline }) = get(&conn, pdf_id)? else {
+    return Ok(None);
+  };
+  let pdf = pdfs::get(&conn, pdf_id)?
+    .ok_or_else(|| AppError::NotFound(format!("PDF {} not found", pdf_id)))?;
+  drop(conn);
+
+  let page_count = render::page_count(&app, &pdf.path).ok();
+  // A goal past the end of the document is met on its last page
+  let target = match page_count {
+    Some(count) => goal.min(i64::from(count.max(1))),
+    None => goal,
+  };
+  let percent = (pdf.last_page as f64 / target as f64 * 100.0).min(100.0);
+  let days_remaining = deadline
+    .as_deref()
+    .and_then(|deadline| NaiveDateTime::parse_from_str(deadline, "%Y-%m-%d %H:%M:%S").ok())
+    .map(|deadline| (deadline - Utc::now().naive_utc()).num_days());
+
+  Ok(Some(GoalProgress {
+    goal,
+    deadline,
+    page: pdf.last_page,
+    page_count,
+    percent,
+    days_remaining,
+  }))
+}
@@ -0,0 +1,1412 @@
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+use rusqlite::{params, params_from_iter, Connection, OptionalExtension, Row, Transaction};
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use tauri::AppHandle;
+
+use crate::actions::{self, Action};
+use crate::error::AppError;
+use crate::position::ScaledPosition;
+use crate::tags::TagRecord;
+use crate::{color, db, images, pdfs, position, render, tags};
+
+/// A row of the `highlights` table, mirroring `HighlightRecord` in
+/// example/src/services/database.ts.
+#[derive(Debug, Clone, Serialize)]
+pub struct HighlightRecord {
+  pub id: i64,
+  pub pdf_id: i64,
+  pub highlight_id: String,
+  pub content_text: Option<String>,
+  pub content_image: Option<String>,
+  pub comment_text: Option<String>,
+  pub comment_emoji: Option<String>,
+  pub position_data: String,
+  pub page_number: i64,
+  pub created_at: String,
+  pub color: Option<String>,
+  pub updated_at: String,
+  /// File of the image in the images folder once moved out of
+  /// `content_image` by `images::externalize_images`.
+  pub content_image_path: Option<String>,
+}
+
+/// Column list matching `HighlightRecord::from_row`, prefixed with `h.` so it
+/// can be used in joins.
+pub const COLUMNS: &str = "h.id, h.pdf_id, h.highlight_id, h.content_text, h.content_image,
+  h.comment_text, h.comment_emoji, h.position_data, h.page_number, h.created_at, h.color,
+  h.updated_at, h.content_image_path";
+
+impl HighlightRecord {
+  pub fn from_row(row: &Row) -> rusqlite::Result<Self> {
+    Ok(HighlightRecord {
+      id: row.get(0)?,
+      pdf_id: row.get(1)?,
+      highlight_id: row.get(2)?,
+      content_text: row.get(3)?,
+      content_image: row.get(4)?,
+      comment_text: row.get(5)?,
+      comment_emoji: row.get(6)?,
+      position_data: row.get(7)?,
+      page_number: row.get(8)?,
+      created_at: row.get(9)?,
+      color: row.get(10)?,
+      updated_at: row.get(11)?,
+      content_image_path: row.get(12)?,
+    })
+  }
+
+  /// Whether this is an area highlight, its image inline or externalized.
+  pub fn has_image(&self) -> bool {
+    self
+      .content_image
+      .as_deref()
+      .is_some_and(|image| !image.is_empty())
+      || self.content_image_path.is_some()
+  }
+
+  /// The parsed `position_data`, through the position cache.
+  pub fn position(&self) -> serde_json::Result<ScaledPosition> {
+    position::parse_cached(&self.highlight_id, &self.position_data)
+  }
+}
+
+/// Decodes a `content_image` value, which the frontend stores either as a
+/// `data:image/png;base64,` URL or as bare base64, into the image file.
+pub fn decode_image(content_image: &str) -> Result<Vec<u8>, String> {
+  let encoded = match content_image.split_once(";base64,") {
+    Some((_, data)) => data,
+    None => content_image,
+  };
+  STANDARD
+    .decode(encoded.trim())
+    .map_err(|e| format!("Image is not valid base64: {}", e))
+}
+
+/// A highlight as the frontend creates it, before it has a row.
+#[derive(Debug, Deserialize)]
+pub struct NewHighlight {
+  pub pdf_id: i64,
+  pub highlight_id: String,
+  pub content_text: Option<String>,
+  pub content_image: Option<String>,
+  pub comment_text: Option<String>,
+  pub comment_emoji: Option<String>,
+  /// `ScaledPosition` JSON; validated and stored re-serialized.
+  pub position_data: String,
+  pub color: Option<String>,
+}
+
+/// Fresh value for the `highlight_id` column of a highlight created on the
+/// backend (the frontend generates its own when the user highlights).
+pub fn new_highlight_id() -> String {
+  uuid::Uuid::new_v4().simple().to_string()
+}
+
+/// Looks up a highlight that is not in the trash.
+pub fn get(conn: &Connection, highlight_id: &str) -> rusqlite::Result<Option<HighlightRecord>> {
+  conn
+    .query_row(
+      &format!(
+        "SELECT {} FROM highlights h WHERE h.highlight_id = ?1 AND h.deleted_at IS NULL",
+        COLUMNS
+      ),
+      params![highlight_id],
+      HighlightRecord::from_row,
+    )
+    .optional()
+}
+
+pub fn list_for_pdf(conn: &Connection, pdf_id: i64) -> rusqlite::Result<Vec<HighlightRecord>> {
+  let mut stmt = conn.prepare(&format!(
+    "SELECT {} FROM highlights h
+     WHERE h.pdf_id = ?1 AND h.deleted_at IS NULL
+     ORDER BY h.page_number, h.id",
+    COLUMNS
+  ))?;
+  let rows = stmt.query_map(params![pdf_id], HighlightRecord::from_row)?;
+  rows.collect()
+}
+
+/// Highlights of a PDF in reading order: by page, then top to bottom.
+pub fn in_reading_order(conn: &Connection, pdf_id: i64) -> rusqlite::Result<Vec<HighlightRecord>> {
+  let mut rows = list_for_pdf(conn, pdf_id)?;
+  sort_in_reading_order(&mut rows);
+  Ok(rows)
+}
+
+/// Sorts highlights of one PDF by page, then top to bottom within the page.
+pub fn sort_in_reading_order(rows: &mut [HighlightRecord]) {
+  rows.sort_by(|a, b| {
+    a.page_number.cmp(&b.page_number).then(
+      position::top(&a.highlight_id, &a.position_data)
+        .total_cmp(&position::top(&b.highlight_id, &b.position_data)),
+    )
+  });
+}
+
+/// Checks the color and position of a new highlight against a document of
+/// `page_count` pages, and returns its `position_data` in canonical form
+/// along with its page number.
+fn validate_fields(
+  color: Option<&str>,
+  position_data: &str,
+  page_count: Option<u32>,
+) -> Result<(String, i64), String> {
+  if let Some(color) = color {
+    color::validate_hex(color)?;
+  }
+  let position = position::validate(position_data, page_count)?;
+  let position_data = serde_json::to_string(&position).map_err(|e| e.to_string())?;
+  Ok((position_data, position.page_number))
+}
+
+/// Pages of the document a highlight is saved on. A document that can't be
+/// read right now only loses the page range check.
+fn page_count_for(
+  app: &AppHandle,
+  conn: &Connection,
+  pdf_id: i64,
+) -> Result<Option<u32>, AppError> {
+  let pdf = pdfs::get(conn, pdf_id)?
+    .ok_or_else(|| AppError::NotFound(format!("PDF {} not found", pdf_id)))?;
+  Ok(render::page_count(app, &pdf.path).ok())
+}
+
+/// Checks a highlight from the frontend and returns its `position_data` in
+/// canonical form along with its page number. `position_data` is rejected
+/// unless it is a well-formed position on an existing page of the document.
+fn validate_new(
+  app: &AppHandle,
+  conn: &Connection,
+  highlight: &NewHighlight,
+) -> Result<(String, i64), AppError> {
+  let page_count = page_count_for(app, conn, highlight.pdf_id)?;
+  validate_fields(
+    highlight.color.as_deref(),
+    &highlight.position_data,
+    page_count,
+  )
+  .map_err(AppError::InvalidInput)
+}
+
+/// Stores a new highlight, with `position_data` validated and stored in
+/// canonical form so the viewer never reads back something it can't render.
+#[tauri::command]
+pub async fn save_highlight(
+  app: AppHandle,
+  highlight: NewHighlight,
+) -> Result<HighlightRecord, AppError> {
+  let conn = db::open(&app)?;
+  let (position_data, page_number) = validate_new(&app, &conn, &highlight)?;
+
+  insert(&conn, &highlight, &position_data, page_number)?;
+  get(&conn, &highlight.highlight_id)?
+    .ok_or_else(|| AppError::NotFound(format!("Highlight {} not found", highlight.highlight_id)))
+}
+
+const INSERT_SQL: &str = "INSERT INTO highlights (pdf_id, highlight_id, content_text,
+    content_image, comment_text, comment_emoji, position_data, page_number, color)
+  VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, COALESCE(?9, '#ffff00'))";
+
+/// Stores a highlight checked by `validate_new`.
+fn insert(
+  conn: &Connection,
+  highlight: &NewHighlight,
+  position_data: &str,
+  page_number: i64,
+) -> rusqlite::Result<()> {
+  conn.execute(
+    INSERT_SQL,
+    params![
+      highlight.pdf_id,
+      highlight.highlight_id,
+      highlight.content_text,
+      highlight.content_image,
+      highlight.comment_text,
+      highlight.comment_emoji,
+      position_data,
+      page_number,
+      highlight.color,
+    ],
+  )?;
+  Ok(())
+}
+
+/// A highlight of a `save_highlights` batch, whose PDF is given once for the
+/// whole batch.
+#[derive(Debug, Deserialize)]
+pub struct HighlightInput {
+  /// Generated when absent, as for highlights imported from elsewhere.
+  #[serde(default)]
+  pub highlight_id: Option<String>,
+  pub content_text: Option<String>,
+  pub content_image: Option<String>,
+  pub comment_text: Option<String>,
+  pub comment_emoji: Option<String>,
+  /// `ScaledPosition` JSON; validated and stored re-serialized.
+  pub position_data: String,
+  pub color: Option<String>,
+}
+
+/// Stores many new highlights of a PDF in one transaction through a single
+/// prepared statement, for imports, and returns their `highlight_id`s in
+/// order. Every highlight is checked as by `save_highlight` before anything
+/// is written; if any fails, or an id is already taken, nothing is saved and
+/// the error names the index of the offending highlight.
+#[tauri::command]
+pub async fn save_highlights(
+  app: AppHandle,
+  pdf_id: i64,
+  highlights: Vec<HighlightInput>,
+) -> Result<Vec<String>, AppError> {
+  let mut conn = db::open(&app)?;
+  let page_count = page_count_for(&app, &conn, pdf_id)?;
+  let validated = highlights
+    .iter()
+    .enumerate()
+    .map(|(i, highlight)| {
+      validate_fields(
+        highlight.color.as_deref(),
+        &highlight.position_data,
+        page_count,
+      )
+      .map_err(|e| AppError::InvalidInput(format!("Highlight {}: {}", i, e)))
+    })
+    .collect::<Result<Vec<_>, _>>()?;
+
+  let tx = conn.transaction()?;
+  let mut ids = Vec::with_capacity(highlights.len());
+  {
+    let mut stmt = tx.prepare(INSERT_SQL)?;
+    for (i, (highlight, (position_data, page_number))) in
+      highlights.into_iter().zip(validated).enumerate()
+    {
+      let highlight_id = highlight
+        .highlight_id
+        .filter(|id| !id.trim().is_empty())
+        .unwrap_or_else(new_highlight_id);
+      stmt
+        .execute(params![
+          pdf_id,
+          highlight_id,
+          highlight.content_text,
+          highlight.content_image,
+          highlight.comment_text,
+          highlight.comment_emoji,
+          position_data,
+          page_number,
+          highlight.color,
+        ])
+        .map_err(|e| match AppError::from(e) {
+          AppError::Conflict(_) => AppError::Conflict(format!(
+            "Highlight {}: highlight_id {} is already taken",
+            i, highlight_id
+          )),
+          e => e,
+        })?;
+      ids.push(highlight_id);
+    }
+  }
+  tx.commit()?;
+  log::info!("Saved {} highlights into PDF {}", ids.len(), pdf_id);
+  Ok(ids)
+}
+
+/// Like `save_highlight`, but also tags the new highlight with `tag_names`,
+/// creating tags that don't exist yet, all in one transaction so a failed
+/// save leaves neither an untagged highlight nor stray tags behind. Returns
+/// the `highlight_id`.
+#[tauri::command]
+pub async fn save_highlight_with_tags(
+  app: AppHandle,
+  highlight: NewHighlight,
+  tag_names: Vec<String>,
+) -> Result<String, AppError> {
+  let tag_names = tag_names.iter().map(|name| name.trim()).collect::<Vec<_>>();
+  if tag_names.iter().any(|name| name.is_empty()) {
+    return Err(AppError::InvalidInput(
+      "Tag name must not be empty".to_string(),
+    ));
+  }
+
+  let mut conn = db::open(&app)?;
+  let (position_data, page_number) = validate_new(&app, &conn, &highlight)?;
+
+  let tx = conn.transaction()?;
+  insert(&tx, &highlight, &position_data, page_number)?;
+  for name in tag_names {
+    let tag_id = tags::get_or_create(&tx, name)?;
+    tags::link(&tx, tag_id, &highlight.highlight_id)?;
+  }
+  tx.commit()?;
+  Ok(highlight.highlight_id)
+}
+
+#[derive(Debug, Clone, Copy, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum UpsertOutcome {
+  Inserted,
+  Updated,
+}
+
+/// Like `save_highlight`, but saving a `highlight_id` that already exists
+/// updates that row instead of failing on the UNIQUE constraint, so several
+/// windows can save the same highlight. A color of `None` keeps the stored
+/// one. A trashed highlight is not brought back by saving it again: that fails
+/// with `AppError::Conflict`, and it has to be restored first.
+#[tauri::command]
+pub async fn upsert_highlight(
+  app: AppHandle,
+  highlight: NewHighlight,
+) -> Result<UpsertOutcome, AppError> {
+  let mut conn = db::open(&app)?;
+  let (position_data, page_number) = validate_new(&app, &conn, &highlight)?;
+
+  let tx = conn.transaction()?;
+  let trashed: Option<bool> = tx
+    .query_row(
+      "SELECT deleted_at IS NOT NULL FROM highlights WHERE highlight_id = ?1",
+      params![highlight.highlight_id],
+      |row| row.get(0),
+    )
+    .optional()?;
+  if trashed == Some(true) {
+    return Err(AppError::Conflict(format!(
+      "Highlight {} is in the trash",
+      highlight.highlight_id
+    )));
+  }
+  tx.execute(
+    "INSERT INTO highlights (pdf_id, highlight_id, content_text, content_image,
+       comment_text, comment_emoji, position_data, page_number, color)
+     VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, COALESCE(?9, '#ffff00'))
+     ON CONFLICT(highlight_id) DO UPDATE SET
+       pdf_id = excluded.pdf_id,
+       content_text = excluded.content_text,
+       content_image = excluded.content_image,
+       comment_text = excluded.comment_text,
+       comment_emoji = excluded.comment_emoji,
+       position_data = excluded.position_data,
+       page_number = excluded.page_number,
+       color = COALESCE(?9, color)",
+    params![
+      highlight.pdf_id,
+      highlight.highlight_id,
+      highlight.content_text,
+      highlight.content_image,
+      highlight.comment_text,
+      highlight.comment_emoji,
+      position_data,
+      page_number,
+      highlight.color,
+    ],
+  )?;
+  tx.commit()?;
+
+  Ok(if trashed.is_some() {
+    UpsertOutcome::Updated
+  } else {
+    UpsertOutcome::Inserted
+  })
+}
+
+#[tauri::command]
+pub async fn update_highlight_color(
+  app: AppHandle,
+  highlight_id: String,
+  color: String,
+) -> Result<(), AppError> {
+  color::validate_hex(&color).map_err(AppError::InvalidInput)?;
+  let conn = db::open(&app)?;
+  let updated = conn.execute(
+    "UPDATE highlights SET color = ?1 WHERE highlight_id = ?2 AND deleted_at IS NULL",
+    params![color, highlight_id],
+  )?;
+  if updated == 0 {
+    return Err(AppError::NotFound(format!(
+      "Highlight {} not found",
+      highlight_id
+    )));
+  }
+  Ok(())
+}
+
+/// Replaces a highlight's comment. `updated_at` is bumped by the
+/// `highlights_updated_at_update` trigger.
+#[tauri::command]
+pub async fn update_highlight_comment(
+  app: AppHandle,
+  highlight_id: String,
+  comment_text: Option<String>,
+  comment_emoji: Option<String>,
+) -> Result<(), AppError> {
+  let conn = db::open(&app)?;
+  let updated = conn.execute(
+    "UPDATE highlights SET comment_text = ?1, comment_emoji = ?2
+       WHERE highlight_id = ?3 AND deleted_at IS NULL",
+    params![comment_text, comment_emoji, highlight_id],
+  )?;
+  if updated == 0 {
+    return Err(AppError::NotFound(format!(
+      "Highlight {} not found",
+      highlight_id
+    )));
+  }
+  Ok(())
+}
+
+/// Moves a highlight to the trash. Trashed highlights are hidden from every
+/// read query until restored or purged.
+#[tauri::command]
+pub async fn soft_delete_highlight(app: AppHandle, highlight_id: String) -> Result<(), AppError> {
+  let mut conn = db::open(&app)?;
+  let tx = conn.transaction()?;
+  let updated = tx.execute(
+    "UPDATE highlights SET deleted_at = CURRENT_TIMESTAMP
+       WHERE highlight_id = ?1 AND deleted_at IS NULL",
+    params![highlight_id],
+  )?;
+  if updated == 0 {
+    return Err(AppError::NotFound(format!(
+      "Highlight {} not found",
+      highlight_id
+    )));
+  }
+  actions::record(&tx, &Action::TrashHighlight { highlight_id })?;
+  tx.commit()?;
+  Ok(())
+}
+
+#[tauri::command]
+pub async fn restore_highlight(app: AppHandle, highlight_id: String) -> Result<(), AppError> {
+  let conn = db::open(&app)?;
+  let updated = conn.execute(
+    "UPDATE highlights SET deleted_at = NULL
+       WHERE highlight_id = ?1 AND deleted_at IS NOT NULL",
+    params![highlight_id],
+  )?;
+  if updated == 0 {
+    return Err(AppError::NotFound(format!(
+      "Highlight {} is not in the trash",
+      highlight_id
+    )));
+  }
+  Ok(())
+}
+
+/// Trashed highlights due for purging, given `?1` from `purge_cutoff`.
+const EXPIRED: &str = "deleted_at IS NOT NULL AND deleted_at <= datetime('now', ?1)";
+
+fn purge_cutoff(older_than_days: i64) -> String {
+  format!("-{} days", older_than_days)
+}
+
+fn purge(tx: &Transaction, older_than_days: i64) -> rusqlite::Result<usize> {
+  let cutoff = purge_cutoff(older_than_days);
+  let expired = format!("SELECT highlight_id FROM highlights WHERE {}", EXPIRED);
+
+  // Remove dependent rows explicitly rather than relying on ON DELETE CASCADE,
+  // which only fires on connections that enabled foreign keys; databases
+  // edited by other tools may not have had them.
+  tx.execute(
+    &format!(
+      "DELETE FROM highlight_tags WHERE highlight_id IN ({})",
+      expired
+    ),
+    params![cutoff],
+  )?;
+  tx.execute(
+    &format!(
+      "DELETE FROM tag_usage_history WHERE highlight_id IN ({})",
+      expired
+    ),
+    params![cutoff],
+  )?;
+  tx.execute(
+    &format!("DELETE FROM highlights WHERE {}", EXPIRED),
+    params![cutoff],
+  )
+}
+
+/// Permanently deletes highlights that have been in the trash for at least
+/// `older_than_days` days and returns how many were removed.
+#[tauri::command]
+pub async fn purge_trash(app: AppHandle, older_than_days: i64) -> Result<usize, AppError> {
+  if older_than_days < 0 {
+    return Err(AppError::InvalidInput(
+      "older_than_days must not be negative".to_string(),
+    ));
+  }
+  let mut conn = db::open(&app)?;
+  let tx = conn.transaction()?;
+  let files = images::files_where(&tx, EXPIRED, params![purge_cutoff(older_than_days)])?;
+  let purged = purge(&tx, older_than_days)?;
+  if purged > 0 {
+    actions::record(&tx, &Action::PurgeTrash { purged })?;
+  }
+  tx.commit()?;
+  images::remove_files(&files);
+  log::info!("Purged {} highlights from the trash", purged);
+  Ok(purged)
+}
+
+/// Backs `delete_highlights_for_pdf`, also removing the tag links and
+/// history of the deleted highlights.
+pub fn delete_for_pdf(tx: &Transaction, pdf_id: i64) -> rusqlite::Result<usize> {
+  let of_pdf = "SELECT highlight_id FROM highlights WHERE pdf_id = ?1";
+
+  // Same as `purge`: don't count on ON DELETE CASCADE for the tag rows.
+  tx.execute(
+    &format!(
+      "DELETE FROM highlight_tags WHERE highlight_id IN ({})",
+      of_pdf
+    ),
+    params![pdf_id],
+  )?;
+  tx.execute(
+    &format!(
+      "DELETE FROM tag_usage_history WHERE highlight_id IN ({})",
+      of_pdf
+    ),
+    params![pdf_id],
+  )?;
+  tx.execute("DELETE FROM highlights WHERE pdf_id = ?1", params![pdf_id])
+}
+
+/// Permanently deletes every highlight of a PDF, trashed ones included, and
+/// returns how many were removed.
+#[tauri::command]
+pub async fn delete_highlights_for_pdf(app: AppHandle, pdf_id: i64) -> Result<usize, AppError> {
+  let mut conn = db::open(&app)?;
+  let tx = conn.transaction()?;
+  let snapshot = actions::snapshot_highlights(&tx, pdf_id)?;
+  let files = images::files_where(&tx, "pdf_id = ?1", params![pdf_id])?;
+  let deleted = delete_for_pdf(&tx, pdf_id)?;
+  if deleted > 0 {
+    actions::record(&tx, &Action::DeleteHighlights { pdf_id, snapshot })?;
+  }
+  tx.commit()?;
+  images::remove_files(&files);
+  log::info!("Deleted {} highlights of PDF {}", deleted, pdf_id);
+  Ok(deleted)
+}
+
+#[derive(Debug, Clone, Copy, Deserialize)]
+pub enum TagFilterMode {
+  /// Highlights carrying every one of the tags.
+  All,
+  /// Highlights carrying at least one of the tags.
+  Any,
+}
+
+fn by_tags(
+  conn: &Connection,
+  tag_ids: &[i64],
+  mode: TagFilterMode,
+) -> rusqlite::Result<Vec<HighlightRecord>> {
+  let placeholders = vec!["?"; tag_ids.len()].join(", ");
+  let having = match mode {
+    TagFilterMode::All => format!("HAVING COUNT(DISTINCT tag_id) = {}", tag_ids.len()),
+    TagFilterMode::Any => String::new(),
+  };
+  let mut stmt = conn.prepare(&format!(
+    "SELECT {} FROM highlights h
+     WHERE h.deleted_at IS NULL AND h.highlight_id IN (
+       SELECT highlight_id FROM highlight_tags
+       WHERE tag_id IN ({})
+       GROUP BY highlight_id
+       {}
+     )
+     ORDER BY h.pdf_id, h.page_number, h.id",
+    COLUMNS, placeholders, having
+  ))?;
+  let rows = stmt.query_map(params_from_iter(tag_ids), HighlightRecord::from_row)?;
+  rows.collect()
+}
+
+/// Highlights tagged with all (intersection) or any (union) of `tag_ids`,
+/// ordered by PDF and page so the UI can group them.
+#[tauri::command]
+pub async fn find_highlights_by_tags(
+  app: AppHandle,
+  tag_ids: Vec<i64>,
+  mode: TagFilterMode,
+) -> Result<Vec<HighlightRecord>, AppError> {
+  let mut tag_ids = tag_ids;
+  tag_ids.sort_unstable();
+  tag_ids.dedup();
+  if tag_ids.is_empty() {
+    return Ok(Vec::new());
+  }
+  let conn = db::open(&app)?;
+  Ok(by_tags(&conn, &tag_ids, mode)?)
+}
+
+/// Inserts a copy of `source` under `new_id`, tags included, on `page_number`.
+fn insert_clone(
+  tx: &Transaction,
+  source: &HighlightRecord,
+  new_id: &str,
+  page_number: i64,
+  position_data: &str,
+) -> rusqlite::Result<()> {
+  tx.execute(
+    "INSERT INTO highlights (pdf_id, highlight_id, content_text, content_image,
+       comment_text, comment_emoji, position_data, page_number, color)
+     VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9)",
+    params![
+      source.pdf_id,
+      new_id,
+      source.content_text,
+      source.content_image,
+      source.comment_text,
+      source.comment_emoji,
+      position_data,
+      page_number,
+      source.color,
+    ],
+  )?;
+  tx.execute(
+    "INSERT INTO highlight_tags (highlight_id, tag_id)
+     SELECT ?2, tag_id FROM highlight_tags WHERE highlight_id = ?1",
+    params![source.highlight_id, new_id],
+  )?;
+  Ok(())
+}
+
+/// Copies a highlight, with its comment and tags, under a new `highlight_id`
+/// and returns that id. With `target_page` the copy is placed at the same
+/// coordinates on another page.
+#[tauri::command]
+pub async fn clone_highlight(
+  app: AppHandle,
+  highlight_id: String,
+  target_page: Option<i64>,
+) -> Result<String, AppError> {
+  if target_page.is_some_and(|page| page < 1) {
+    return Err(AppError::InvalidInput(
+      "target_page must be 1 or greater".to_string(),
+    ));
+  }
+
+  let mut conn = db::open(&app)?;
+  let tx = conn.transaction()?;
+  let source = get(&tx, &highlight_id)?
+    .ok_or_else(|| AppError::NotFound(format!("Highlight {} not found", highlight_id)))?;
+
+  let (page_number, position_data) = match target_page {
+    Some(page) if page != source.page_number => (
+      page,
+      position::move_to_page(&source.position_data, page).map_err(|e| {
+        format!(
+          "Highlight {} has invalid position data: {}",
+          highlight_id, e
+        )
+      })?,
+    ),
+    _ => (source.page_number, source.position_data.clone()),
+  };
+
+  let new_id = new_highlight_id();
+  insert_clone(&tx, &source, &new_id, page_number, &position_data)?;
+  tx.commit()?;
+  Ok(new_id)
+}
+
+#[derive(Debug, Clone, Copy, Deserialize)]
+#[allow(clippy::enum_variant_names)]
+pub enum HighlightOrder {
+  /// Reading order: page, then creation.
+  ByPage,
+  /// Newest first.
+  ByCreatedAt,
+  /// Most recently edited first.
+  ByUpdatedAt,
+}
+
+impl HighlightOrder {
+  fn order_by(self) -> &'static str {
+    match self {
+      HighlightOrder::ByPage => "h.page_number, h.id",
+      HighlightOrder::ByCreatedAt => "h.created_at DESC, h.id DESC",
+      HighlightOrder::ByUpdatedAt => "h.updated_at DESC, h.id DESC",
+    }
+  }
+}
+
+#[derive(Debug, Serialize)]
+pub struct HighlightPage {
+  pub highlights: Vec<HighlightRecord>,
+  /// Number of highlights of the PDF across all pages of the listing.
+  pub total: i64,
+}
+
+fn list_page(
+  conn: &Connection,
+  pdf_id: i64,
+  limit: i64,
+  offset: i64,
+  order: HighlightOrder,
+) -> rusqlite::Result<HighlightPage> {
+  let total = conn.query_row(
+    "SELECT COUNT(*) FROM highlights WHERE pdf_id = ?1 AND deleted_at IS NULL",
+    params![pdf_id],
+    |row| row.get(0),
+  )?;
+  let mut stmt = conn.prepare(&format!(
+    "SELECT {} FROM highlights h
+     WHERE h.pdf_id = ?1 AND h.deleted_at IS NULL
+     ORDER BY {}
+     LIMIT ?2 OFFSET ?3",
+    COLUMNS,
+    order.order_by()
+  ))?;
+  let highlights = stmt
+    .query_map(params![pdf_id, limit, offset], HighlightRecord::from_row)?
+    .collect::<rusqlite::Result<Vec<_>>>()?;
+  Ok(HighlightPage { highlights, total })
+}
+
+/// One page of a PDF's highlights, so heavily annotated documents can be
+/// loaded incrementally.
+#[tauri::command]
+pub async fn list_highlights_page(
+  app: AppHandle,
+  pdf_id: i64,
+  limit: i64,
+  offset: i64,
+  order: HighlightOrder,
+) -> Result<HighlightPage, AppError> {
+  if limit < 1 {
+    return Err(AppError::InvalidInput(
+      "limit must be 1 or greater".to_string(),
+    ));
+  }
+  if offset < 0 {
+    return Err(AppError::InvalidInput(
+      "offset must not be negative".to_string(),
+    ));
+  }
+  let conn = db::open(&app)?;
+  Ok(list_page(&conn, pdf_id, limit, offset, order)?)
+}
+
+#[derive(Debug, Clone, Copy, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum HighlightKind {
+  /// A text selection.
+  Text,
+  /// An area highlight, stored as a screenshot in `content_image` or in the
+  /// images folder.
+  Image,
+}
+
+#[derive(Debug, Serialize)]
+pub struct HighlightWithKind {
+  #[serde(flatten)]
+  pub highlight: HighlightRecord,
+  pub kind: HighlightKind,
+}
+
+fn with_comments(
+  conn: &Connection,
+  pdf_id: i64,
+  only_commented: bool,
+) -> rusqlite::Result<Vec<HighlightWithKind>> {
+  let mut stmt = conn.prepare(&format!(
+    "SELECT {} FROM highlights h
+     WHERE h.pdf_id = ?1 AND h.deleted_at IS NULL
+       AND (NOT ?2 OR TRIM(COALESCE(h.comment_text, '')) != '')
+     ORDER BY h.page_number, h.id",
+    COLUMNS
+  ))?;
+  let rows = stmt.query_map(params![pdf_id, only_commented], |row| {
+    let highlight = HighlightRecord::from_row(row)?;
+    let kind = if highlight.has_image() {
+      HighlightKind::Image
+    } else {
+      HighlightKind::Text
+    };
+    Ok(HighlightWithKind { highlight, kind })
+  })?;
+  rows.collect()
+}
+
+/// A PDF's highlights in page order, only those with a non-blank comment if
+/// `only_commented` is set, each marked as a text or an area highlight.
+#[tauri::command]
+pub async fn list_highlights_with_comments(
+  app: AppHandle,
+  pdf_id: i64,
+  only_commented: bool,
+) -> Result<Vec<HighlightWithKind>, AppError> {
+  let conn = db::open(&app)?;
+  Ok(with_comments(&conn, pdf_id, only_commented)?)
+}
+
+#[derive(Debug, Serialize)]
+pub struct PageSummary {
+  pub page_number: i64,
+  pub highlight_count: i64,
+}
+
+/// Pages of a PDF with at least one highlight, in page order, for the
+/// scrollbar overview.
+#[tauri::command]
+pub async fn annotated_pages(app: AppHandle, pdf_id: i64) -> Result<Vec<PageSummary>, AppError> {
+  let conn = db::open(&app)?;
+  let mut stmt = conn.prepare(
+    "SELECT page_number, COUNT(*) FROM highlights
+       WHERE pdf_id = ?1 AND deleted_at IS NULL
+       GROUP BY page_number
+       ORDER BY page_number",
+  )?;
+  let rows = stmt.query_map(params![pdf_id], |row| {
+    Ok(PageSummary {
+      page_number: row.get(0)?,
+      highlight_count: row.get(1)?,
+    })
+  })?;
+  Ok(rows.collect::<rusqlite::Result<Vec<_>>>()?)
+}
+
+/// Highlight count of each page relative to the busiest page, from 0.0 to
+/// 1.0, for the scrollbar heat-map. Index 0 is page 1. Highlights on pages
+/// past the end of the document are left out.
+#[tauri::command]
+pub async fn highlight_density(app: AppHandle, pdf_id: i64) -> Result<Vec<f32>, AppError> {
+  let conn = db::open(&app)?;
+  let pdf = pdfs::get(&conn, pdf_id)?
+    .ok_or_else(|| AppError::NotFound(format!("PDF {} not found", pdf_id)))?;
+  let page_count = render::page_count(&app, &pdf.path)?;
+
+  let mut stmt = conn.prepare(
+    "SELECT page_number, COUNT(*) FROM highlights
+       WHERE pdf_id = ?1 AND deleted_at IS NULL AND page_number BETWEEN 1 AND ?2
+       GROUP BY page_number",
+  )?;
+  let counts = stmt
+    .query_map(params![pdf_id, page_count], |row| {
+      Ok((row.get::<_, i64>(0)?, row.get::<_, i64>(1)?))
+    })?
+    .collect::<rusqlite::Result<Vec<_>>>()?;
+
+  let mut density = vec![0.0; page_count as usize];
+  let busiest = counts.iter().map(|&(_, count)| count).max().unwrap_or(0);
+  for (page_number, count) in counts {
+    density[(page_number - 1) as usize] = count as f32 / busiest as f32;
+  }
+  Ok(density)
+}
+
+/// Moves highlights to another PDF, e.g. after splitting a document. Tags
+/// stay attached since they reference `highlight_id`. Fails without changing
+/// anything if the PDF or any of the highlights doesn't exist.
+#[tauri::command]
+pub async fn reassign_highlights(
+  app: AppHandle,
+  highlight_ids: Vec<String>,
+  target_pdf_id: i64,
+) -> Result<usize, AppError> {
+  let mut highlight_ids = highlight_ids;
+  highlight_ids.sort_unstable();
+  highlight_ids.dedup();
+
+  let mut conn = db::open(&app)?;
+  let tx = conn.transaction()?;
+  if pdfs::get(&tx, target_pdf_id)?.is_none() {
+    return Err(AppError::NotFound(format!(
+      "PDF {} not found",
+      target_pdf_id
+    )));
+  }
+
+  let mut moved = 0;
+  for highlight_id in &highlight_ids {
+    let updated = tx.execute(
+      "UPDATE highlights SET pdf_id = ?1 WHERE highlight_id = ?2 AND deleted_at IS NULL",
+      params![target_pdf_id, highlight_id],
+    )?;
+    if updated == 0 {
+      // Dropping the transaction rolls back the highlights already moved
+      return Err(AppError::NotFound(format!(
+        "Highlight {} not found",
+        highlight_id
+      )));
+    }
+    moved += updated;
+  }
+  tx.commit()?;
+  Ok(moved)
+}
+
+/// Moves every highlight of a PDF, trashed ones included, to the page
+/// `mapping` gives for its current page. Returns how many highlights changed
+/// page; those left on the same page are untouched.
+fn remap_pages(
+  tx: &Transaction,
+  pdf_id: i64,
+  mapping: &HashMap<i64, i64>,
+) -> Result<usize, AppError> {
+  let rows = {
+    let mut stmt = tx.prepare(
+      "SELECT highlight_id, page_number, position_data FROM highlights WHERE pdf_id = ?1",
+    )?;
+    let rows = stmt.query_map(params![pdf_id], |row| {
+      Ok((
+        row.get::<_, String>(0)?,
+        row.get::<_, i64>(1)?,
+        row.get::<_, String>(2)?,
+      ))
+    })?;
+    rows.collect::<rusqlite::Result<Vec<_>>>()?
+  };
+
+  let mut unmapped: Vec<i64> = rows
+    .iter()
+    .map(|(_, page, _)| *page)
+    .filter(|page| !mapping.contains_key(page))
+    .collect();
+  if !unmapped.is_empty() {
+    unmapped.sort_unstable();
+    unmapped.dedup();
+    let pages = unmapped
+      .iter()
+      .map(|page| page.to_string())
+      .collect::<Vec<_>>()
+      .join(", ");
+    return Err(AppError::InvalidInput(format!(
+      "mapping has no entry for pages {}",
+      pages
+    )));
+  }
+
+  let mut update = tx.prepare(
+    "UPDATE highlights SET page_number = ?1, position_data = ?2 WHERE highlight_id = ?3",
+  )?;
+  let mut remapped = 0;
+  for (highlight_id, page, position_data) in rows {
+    let new_page = mapping[&page];
+    if new_page == page {
+      continue;
+    }
+    let position_data = position::move_to_page(&position_data, new_page).map_err(|e| {
+      AppError::Corrupt(format!(
+        "Highlight {} has invalid position data: {}",
+        highlight_id, e
+      ))
+    })?;
+    update.execute(params![new_page, position_data, highlight_id])?;
+    remapped += 1;
+  }
+  Ok(remapped)
+}
+
+/// Renumbers the pages of a PDF's highlights after its pages were reordered,
+/// with `mapping` from old to new page numbers. Every page that has
+/// highlights must be mapped, and no two pages may map to the same page.
+/// Nothing changes if any highlight can't be moved.
+#[tauri::command]
+pub async fn remap_highlight_pages(
+  app: AppHandle,
+  pdf_id: i64,
+  mapping: HashMap<i64, i64>,
+) -> Result<usize, AppError> {
+  if mapping.iter().any(|(&old, &new)| old < 1 || new < 1) {
+    return Err(AppError::InvalidInput(
+      "Page numbers must be 1 or greater".to_string(),
+    ));
+  }
+  let targets: HashSet<i64> = mapping.values().copied().collect();
+  if targets.len() != mapping.len() {
+    return Err(AppError::InvalidInput(
+      "mapping sends two pages to the same page".to_string(),
+    ));
+  }
+
+  let mut conn = db::open(&app)?;
+  let pdf = pdfs::get(&conn, pdf_id)?
+    .ok_or_else(|| AppError::NotFound(format!("PDF {} not found", pdf_id)))?;
+  // A document that can't be read right now only loses the page range check
+  if let Ok(page_count) = render::page_count(&app, &pdf.path) {
+    if let Some(page) = targets.iter().find(|&&page| page > i64::from(page_count)) {
+      return Err(AppError::InvalidInput(format!(
+        "Page {} is past the end of the document ({} pages)",
+        page, page_count
+      )));
+    }
+  }
+
+  let tx = conn.transaction()?;
+  let remapped = remap_pages(&tx, pdf_id, &mapping)?;
+  tx.commit()?;
+  log::info!("Remapped {} highlights of PDF {}", remapped, pdf_id);
+  Ok(remapped)
+}
+
+/// Which end of a date range a timestamp bounds. A bare date covers the
+/// whole day, so it means midnight as `From` and the last second as `To`.
+#[derive(Clone, Copy)]
+pub enum RangeBound {
+  From,
+  To,
+}
+
+/// Parses an ISO-8601 date or date-time into the `YYYY-MM-DD HH:MM:SS` UTC
+/// form SQLite's `CURRENT_TIMESTAMP` writes to `created_at`. Times without an
+/// offset are taken as UTC.
+pub fn sqlite_timestamp(input: &str, bound: RangeBound) -> Result<String, String> {
+  let input = input.trim();
+  let utc = if let Ok(datetime) = chrono::DateTime::parse_from_rfc3339(input) {
+    datetime.naive_utc()
+  } else if let Ok(datetime) = chrono::NaiveDateTime::parse_from_str(input, "%Y-%m-%dT%H:%M:%S%.f")
+  {
+    datetime
+  } else if let Ok(datetime) = chrono::NaiveDateTime::parse_from_str(input, "%Y-%m-%d %H:%M:%S%.f")
+  {
+    datetime
+  } else if let Ok(date) = chrono::NaiveDate::parse_from_str(input, "%Y-%m-%d") {
+    match bound {
+      RangeBound::From => date.and_hms_opt(0, 0, 0),
+      RangeBound::To => date.and_hms_opt(23, 59, 59),
+    }
+    .ok_or_else(|| format!("Invalid date {:?}", input))?
+  } else {
+    return Err(format!(
+      "Invalid date {:?}: expected an ISO-8601 date or date-time",
+      input
+    ));
+  };
+  Ok(utc.format("%Y-%m-%d %H:%M:%S").to_string())
+}
+
+fn created_between(
+  conn: &Connection,
+  from: &str,
+  to: &str,
+  pdf_id: Option<i64>,
+) -> rusqlite::Result<Vec<HighlightRecord>> {
+  let mut stmt = conn.prepare(&format!(
+    "SELECT {} FROM highlights h
+     WHERE h.created_at BETWEEN ?1 AND ?2
+       AND h.deleted_at IS NULL
+       AND (?3 IS NULL OR h.pdf_id = ?3)
+     ORDER BY h.created_at, h.id",
+    COLUMNS
+  ))?;
+  let rows = stmt.query_map(params![from, to, pdf_id], HighlightRecord::from_row)?;
+  rows.collect()
+}
+
+/// Highlights created between `from` and `to` (inclusive, ISO-8601), oldest
+/// first, optionally limited to one PDF.
+#[tauri::command]
+pub async fn highlights_in_range(
+  app: AppHandle,
+  from: String,
+  to: String,
+  pdf_id: Option<i64>,
+) -> Result<Vec<HighlightRecord>, AppError> {
+  let from = sqlite_timestamp(&from, RangeBound::From).map_err(AppError::InvalidInput)?;
+  let to = sqlite_timestamp(&to, RangeBound::To).map_err(AppError::InvalidInput)?;
+  if from > to {
+    return Err(AppError::InvalidInput(format!(
+      "Range start {} is after its end {}",
+      from, to
+    )));
+  }
+  let conn = db::open(&app)?;
+  Ok(created_between(&conn, &from, &to, pdf_id)?)
+}
+
+#[derive(Debug, Clone, Copy, Deserialize)]
+pub enum NavDirection {
+  Next,
+  Previous,
+}
+
+/// The highlight after or before `current_highlight_id` in reading order, for
+/// stepping through a PDF's highlights. `None` past the first or last one.
+#[tauri::command]
+pub async fn adjacent_highlight(
+  app: AppHandle,
+  pdf_id: i64,
+  current_highlight_id: String,
+  direction: NavDirection,
+) -> Result<Option<HighlightRecord>, AppError> {
+  let conn = db::open(&app)?;
+  let rows = in_reading_order(&conn, pdf_id)?;
+  let current = rows
+    .iter()
+    .position(|row| row.highlight_id == current_highlight_id)
+    .ok_or_else(|| {
+      AppError::NotFound(format!(
+        "Highlight {} not found in PDF {}",
+        current_highlight_id, pdf_id
+      ))
+    })?;
+  let adjacent = match direction {
+    NavDirection::Next => current + 1,
+    NavDirection::Previous => match current.checked_sub(1) {
+      Some(index) => index,
+      None => return Ok(None),
+    },
+  };
+  Ok(rows.into_iter().nth(adjacent))
+}
+
+/// Counts one more view of a highlight, for `most_viewed_highlights`.
+#[tauri::command]
+pub async fn record_highlight_view(app: AppHandle, highlight_id: String) -> Result<(), AppError> {
+  let conn = db::open(&app)?;
+  let updated = conn.execute(
+    "UPDATE highlights SET view_count = view_count + 1
+     WHERE highlight_id = ?1 AND deleted_at IS NULL",
+    params![highlight_id],
+  )?;
+  if updated == 0 {
+    return Err(AppError::NotFound(format!(
+      "Highlight {} not found",
+      highlight_id
+    )));
+  }
+  Ok(())
+}
+
+fn most_viewed(conn: &Connection, limit: i64) -> rusqlite::Result<Vec<HighlightRecord>> {
+  let mut stmt = conn.prepare(&format!(
+    "SELECT {} FROM highlights h
+     WHERE h.deleted_at IS NULL AND h.view_count > 0
+     ORDER BY h.view_count DESC, h.id
+     LIMIT ?1",
+    COLUMNS
+  ))?;
+  let rows = stmt.query_map(params![limit], HighlightRecord::from_row)?;
+  rows.collect()
+}
+
+/// The `limit` highlights viewed most often, across all PDFs. Highlights that
+/// were never viewed are left out.
+#[tauri::command]
+pub async fn most_viewed_highlights(
+  app: AppHandle,
+  limit: i64,
+) -> Result<Vec<HighlightRecord>, AppError> {
+  if limit < 1 {
+    return Err(AppError::InvalidInput(
+      "limit must be 1 or greater".to_string(),
+    ));
+  }
+  let conn = db::open(&app)?;
+  Ok(most_viewed(&conn, limit)?)
+}
+
+/// A highlight with its PDF's name and path and its tags, for a detail view.
+#[derive(Debug, Serialize)]
+pub struct HighlightDetail {
+  #[serde(flatten)]
+  pub highlight: HighlightRecord,
+  pub pdf_name: String,
+  pub pdf_path: String,
+  pub tags: Vec<TagRecord>,
+}
+
+fn detail(conn: &Connection, highlight_id: &str) -> rusqlite::Result<Option<HighlightDetail>> {
+  let row = conn
+    .query_row(
+      &format!(
+        "SELECT {}, p.name AS pdf_name, p.path AS pdf_path FROM highlights h
+         JOIN pdfs p ON p.id = h.pdf_id
+         WHERE h.highlight_id = ?1 AND h.deleted_at IS NULL",
+        COLUMNS
+      ),
+      params![highlight_id],
+      |row| {
+        Ok((
+          HighlightRecord::from_row(row)?,
+          row.get("pdf_name")?,
+          row.get("pdf_path")?,
+        ))
+      },
+    )
+    .optional()?;
+  let Some((highlight, pdf_name, pdf_path)) = row else {
+    return Ok(None);
+  };
+  Ok(Some(HighlightDetail {
+    tags: tags::for_highlight(conn, highlight_id)?,
+    highlight,
+    pdf_name,
+    pdf_path,
+  }))
+}
+
+/// Everything a detail view shows about one highlight, in a single call.
+#[tauri::command]
+pub async fn get_highlight_detail(
+  app: AppHandle,
+  highlight_id: String,
+) -> Result<HighlightDetail, AppError> {
+  let conn = db::open(&app)?;
+  detail(&conn, &highlight_id)?
+    .ok_or_else(|| AppError::NotFound(format!("Highlight {} not found", highlight_id)))
+}
+
+#[derive(Debug, Serialize)]
+pub struct PositionIssue {
+  pub highlight_id: String,
+  pub issue: position::Issue,
+}
+
+/// Checks the stored position of every highlight of a PDF outside the trash
+/// against the document's page count, as a worklist of highlights to repair.
+/// A highlight with several problems is listed once per problem.
+#[tauri::command]
+pub async fn audit_positions(app: AppHandle, pdf_id: i64) -> Result<Vec<PositionIssue>, AppError> {
+  let conn = db::open(&app)?;
+  let pdf = pdfs::get(&conn, pdf_id)?
+    .ok_or_else(|| AppError::NotFound(format!("PDF {} not found", pdf_id)))?;
+  let rows = list_for_pdf(&conn, pdf_id)?;
+  drop(conn);
+  let page_count = render::page_count(&app, &pdf.path)?;
+
+  let issues: Vec<PositionIssue> = rows
+    .into_iter()
+    .flat_map(|row| {
+      position::audit(&row.position_data, page_count)
+        .into_iter()
+        .map(move |issue| PositionIssue {
+          highlight_id: row.highlight_id.clone(),
+          issue,
+        })
+    })
+    .collect();
+  if !issues.is_empty() {
+    log::warn!(
+      "Found {} position issues in the highlights of PDF {}",
+      issues.len(),
+      pdf_id
+    );
+  }
+  Ok(issues)
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use rusqlite::{params, Connection};
+  use tauri_plugin_sql::MigrationKind;
+
+  use crate::migrations;
+
+  /// An empty database at the latest version.
+  fn migrated(foreign_keys: bool) -> Connection {
+    let conn = Connection::open_in_memory().unwrap();
+    conn
+      .pragma_update(None, "foreign_keys", foreign_keys)
+      .unwrap();
+    for migration in migrations::all()
+      .iter()
+      .filter(|m| matches!(m.kind, MigrationKind::Up))
+    {
+      conn.execute_batch(migration.sql).unwrap();
+    }
+    conn
+  }
+
+  /// Adds a PDF with a highlight tagged `tag`, and returns the PDF's id.
+  fn add_tagged_pdf(conn: &Connection, name: &str, tag: &str) -> i64 {
+    conn
+      .execute(
+        "INSERT INTO pdfs (name, path) VALUES (?1, ?2)",
+        params![name, format!("/pdfs/{}.pdf", name)],
+      )
+      .unwrap();
+    let pdf_id = conn.last_insert_rowid();
+    let highlight_id = format!("{}-highlight", name);
+    conn
+      .execute(
+        "INSERT INTO highlights (pdf_id, highlight_id, content_text, position_data, page_number)
+         VALUES (?1, ?2, 'text', '{}', 1)",
+        params![pdf_id, highlight_id],
+      )
+      .unwrap();
+    conn
+      .execute("INSERT INTO tags (name) VALUES (?1)", params![tag])
+      .unwrap();
+    let tag_id = conn.last_insert_rowid();
+    conn
+      .execute(
+        "INSERT INTO highlight_tags (highlight_id, tag_id) VALUES (?1, ?2)",
+        params![highlight_id, tag_id],
+      )
+      .unwrap();
+    conn
+      .execute(
+        "INSERT INTO tag_usage_history (tag_id, highlight_id) VALUES (?1, ?2)",
+        params![tag_id, highlight_id],
+      )
+      .unwrap();
+    pdf_id
+  }
+
+  fn count(conn: &Connection, query: &str) -> i64 {
+    conn
+      .query_row(&format!("SELECT COUNT(*) FROM {}", query), [], |row| {
+        row.get(0)
+      })
+      .unwrap()
+  }
+
+  /// Checks that only the kept PDF's highlight and tag rows remain.
+  fn assert_only_kept_rows(conn: &Connection) {
+    assert_eq!(count(conn, "highlights"), 1);
+    for table in ["highlight_tags", "tag_usage_history"] {
+      let orphans = format!(
+        "{} WHERE highlight_id NOT IN (SELECT highlight_id FROM highlights)",
+        table
+      );
+      assert_eq!(count(conn, &orphans), 0, "orphan rows in {}", table);
+      assert_eq!(count(conn, table), 1, "rows of the kept PDF in {}", table);
+    }
+  }
+
+  fn delete_for_pdf_leaves_no_orphans(foreign_keys: bool) {
+    let mut conn = migrated(foreign_keys);
+    let pdf_id = add_tagged_pdf(&conn, "cleared", "first");
+    add_tagged_pdf(&conn, "kept", "second");
+
+    let tx = conn.transaction().unwrap();
+    assert_eq!(delete_for_pdf(&tx, pdf_id).unwrap(), 1);
+    tx.commit().unwrap();
+
+    assert_only_kept_rows(&conn);
+  }
+
+  #[test]
+  fn delete_for_pdf_leaves_no_orphans_with_foreign_keys() {
+    delete_for_pdf_leaves_no_orphans(true);
+  }
+
+  /// Connections that never enabled foreign keys get no cascades, so the tag
+  /// rows have to be deleted explicitly.
+  #[test]
+  fn delete_for_pdf_leaves_no_orphans_without_foreign_keys() {
+    delete_for_pdf_leaves_no_orphans(false);
+  }
+
+  #[test]
+  fn deleting_highlights_cascades_to_their_tag_rows() {
+    let conn = migrated(true);
+    let pdf_id = add_tagged_pdf(&conn, "cleared", "first");
+    add_tagged_pdf(&conn, "kept", "second");
+
+    conn
+      .execute("DELETE FROM highlights WHERE pdf_id = ?1", params![pdf_id])
+      .unwrap();
+
+    assert_only_kept_rows(&conn);
+  }
+}
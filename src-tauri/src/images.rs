@@ -0,0 +1,297 @@
+//! Where the images of area highlights live. The frontend stores them inline,
+//! as base64 in `content_image`; `externalize_images` moves them to PNG files
+//! in a folder of the user's choice, leaving the file name in
+//! `content_image_path`, and `inline_images` moves them back. The viewer loads
+//! externalized images through `resolve_highlight_images`.
+//!
+//! Library bundles always carry images inline, so exports and sync don't
+//! depend on the folder.
+
+use rusqlite::{params, Connection, OptionalExtension, Params};
+use serde::Serialize;
+use std::collections::HashMap;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use tauri::AppHandle;
+
+use crate::error::AppError;
+use crate::highlights::{self, HighlightRecord};
+use crate::{db, render, settings};
+
+/// Setting holding the folder externalized images are written to.
+pub const IMAGES_DIR_KEY: &str = "images_dir";
+
+#[derive(Debug, Default, Serialize)]
+pub struct MigrationReport {
+  /// Highlights whose image was moved.
+  pub moved: usize,
+  /// Bytes of PNG data moved.
+  pub bytes: u64,
+  /// Highlights left as they were, each with the reason.
+  pub skipped: Vec<String>,
+}
+
+/// The folder images were last externalized to.
+pub fn images_dir(conn: &Connection) -> rusqlite::Result<Option<PathBuf>> {
+  Ok(settings::get(conn, IMAGES_DIR_KEY)?.map(PathBuf::from))
+}
+
+fn read_file(images_dir: Option<&Path>, file: &str) -> Result<Vec<u8>, String> {
+  let dir = images_dir.ok_or_else(|| "No images folder is set".to_string())?;
+  let path = dir.join(file);
+  fs::read(&path).map_err(|e| format!("Cannot read {}: {}", path.display(), e))
+}
+
+/// The image file of a highlight, wherever it is stored, or `None` for a text
+/// highlight.
+pub fn load(
+  images_dir: Option<&Path>,
+  record: &HighlightRecord,
+) -> Result<Option<Vec<u8>>, String> {
+  match (&record.content_image, &record.content_image_path) {
+    (Some(image), _) if !image.is_empty() => highlights::decode_image(image).map(Some),
+    (_, Some(file)) => read_file(images_dir, file).map(Some),
+    _ => Ok(None),
+  }
+}
+
+/// An externalized image as the data URL `content_image` would hold.
+pub fn inline(images_dir: Option<&Path>, file: &str) -> Result<String, String> {
+  read_file(images_dir, file).map(|png| render::data_url(&png))
+}
+
+/// The image files of the highlights matching `condition`, an SQL expression
+/// over `highlights`. Collected before the highlights are deleted, so the
+/// files can be removed with `remove_files` once the deletion commits.
+pub fn files_where<P: Params>(
+  conn: &Connection,
+  condition: &str,
+  params: P,
+) -> rusqlite::Result<Vec<PathBuf>> {
+  let Some(dir) = images_dir(conn)? else {
+    return Ok(Vec::new());
+  };
+  let files = conn
+    .prepare(&format!(
+      "SELECT content_image_path FROM highlights
+       WHERE content_image_path IS NOT NULL AND ({})",
+      condition
+    ))?
+    .query_map(params, |row| row.get::<_, String>(0))?
+    .map(|file| file.map(|file| dir.join(file)))
+    .collect::<rusqlite::Result<Vec<_>>>()?;
+  Ok(files)
+}
+
+/// Deletes image files whose highlights are gone. Failures are only logged,
+/// since the database change they follow is already committed.
+pub fn remove_files(paths: &[PathBuf]) {
+  for path in paths {
+    if let Err(e) = fs::remove_file(path) {
+      if e.kind() != io::ErrorKind::NotFound {
+        log::warn!("Cannot remove {}: {}", path.display(), e);
+      }
+    }
+  }
+}
+
+/// The externalized images of `highlight_ids` as data URLs, by highlight.
+/// The viewer only reads `content_image`, so it asks for these when a
+/// highlight has a `content_image_path` instead. Images that can't be read
+/// are left out.
+#[tauri::command]
+pub async fn resolve_highlight_images(
+  app: AppHandle,
+  highlight_ids: Vec<String>,
+) -> Result<HashMap<String, String>, AppError> {
+  let conn = db::open(&app)?;
+  let dir = images_dir(&conn)?;
+  let mut stmt = conn.prepare(
+    "SELECT content_image_path FROM highlights
+     WHERE highlight_id = ?1 AND content_image_path IS NOT NULL",
+  )?;
+  let mut images = HashMap::new();
+  for highlight_id in highlight_ids {
+    let Some(file) = stmt
+      .query_row(params![highlight_id], |row| row.get::<_, String>(0))
+      .optional()?
+    else {
+      continue;
+    };
+    match inline(dir.as_deref(), &file) {
+      Ok(image) => {
+        images.insert(highlight_id, image);
+      }
+      Err(e) => log::warn!("Image of highlight {} not loaded: {}", highlight_id, e),
+    }
+  }
+  Ok(images)
+}
+
+fn has_external(conn: &Connection) -> rusqlite::Result<bool> {
+  conn
+    .prepare("SELECT 1 FROM highlights WHERE content_image_path IS NOT NULL")?
+    .exists([])
+}
+
+/// Moves inline images to `<row id>.png` in `dir`, pushing every file written
+/// to `written` so they can be removed if the transaction is rolled back.
+fn externalize(
+  conn: &Connection,
+  dir: &Path,
+  written: &mut Vec<PathBuf>,
+) -> Result<MigrationReport, AppError> {
+  if let Some(current) = images_dir(conn)? {
+    if current != dir && has_external(conn)? {
+      return Err(AppError::Conflict(format!(
+        "Images are already stored in {}; inline them before choosing another folder",
+        current.display()
+      )));
+    }
+  }
+
+  let ids = conn
+    .prepare(
+      "SELECT id, highlight_id FROM highlights
+       WHERE content_image IS NOT NULL AND content_image != '' ORDER BY id",
+    )?
+    .query_map([], |row| {
+      Ok((row.get::<_, i64>(0)?, row.get::<_, String>(1)?))
+    })?
+    .collect::<rusqlite::Result<Vec<_>>>()?;
+
+  let mut report = MigrationReport::default();
+  for (id, highlight_id) in ids {
+    let content_image: String = conn.query_row(
+      "SELECT content_image FROM highlights WHERE id = ?1",
+      params![id],
+      |row| row.get(0),
+    )?;
+    let png = match highlights::decode_image(&content_image) {
+      Ok(png) => png,
+      Err(e) => {
+        report.skipped.push(format!("{} ({})", highlight_id, e));
+        continue;
+      }
+    };
+    let file = format!("{}.png", id);
+    let path = dir.join(&file);
+    fs::write(&path, &png)
+      .map_err(|e| AppError::Io(format!("Cannot write {}: {}", path.display(), e)))?;
+    written.push(path);
+    conn.execute(
+      "UPDATE highlights SET content_image = NULL, content_image_path = ?1 WHERE id = ?2",
+      params![file, id],
+    )?;
+    report.moved += 1;
+    report.bytes += png.len() as u64;
+  }
+  settings::set(conn, IMAGES_DIR_KEY, &dir.to_string_lossy())?;
+  Ok(report)
+}
+
+/// Moves the image of every area highlight, trashed ones included, out of the
+/// database into PNG files in `images_dir`, which is created if needed.
+/// Images that can't be decoded stay inline and are listed in the report. On
+/// failure the database is left as it was and the files written are removed.
+#[tauri::command]
+pub async fn externalize_images(
+  app: AppHandle,
+  images_dir: String,
+) -> Result<MigrationReport, AppError> {
+  let dir = PathBuf::from(images_dir);
+  fs::create_dir_all(&dir)
+    .map_err(|e| AppError::Io(format!("Cannot create {}: {}", dir.display(), e)))?;
+  let dir = fs::canonicalize(&dir)
+    .map_err(|e| AppError::Io(format!("Cannot resolve {}: {}", dir.display(), e)))?;
+
+  let mut conn = db::open(&app)?;
+  let tx = conn.transaction()?;
+  let mut written = Vec::new();
+  let result = externalize(&tx, &dir, &mut written).and_then(|report| {
+    tx.commit()?;
+    Ok(report)
+  });
+  match result {
+    Ok(report) => {
+      log::info!(
+        "Moved {} highlight images ({} bytes) to {}",
+        report.moved,
+        report.bytes,
+        dir.display()
+      );
+      Ok(report)
+    }
+    Err(e) => {
+      for path in written {
+        let _ = fs::remove_file(path);
+      }
+      Err(e)
+    }
+  }
+}
+
+/// Moves externalized images back into `content_image`, pushing the files
+/// read to `inlined` so they can be removed once the transaction commits.
+fn inline_all(
+  conn: &Connection,
+  dir: Option<&Path>,
+  inlined: &mut Vec<PathBuf>,
+) -> Result<MigrationReport, AppError> {
+  let rows = conn
+    .prepare(
+      "SELECT id, highlight_id, content_image_path FROM highlights
+       WHERE content_image_path IS NOT NULL ORDER BY id",
+    )?
+    .query_map([], |row| {
+      Ok((
+        row.get::<_, i64>(0)?,
+        row.get::<_, String>(1)?,
+        row.get::<_, String>(2)?,
+      ))
+    })?
+    .collect::<rusqlite::Result<Vec<_>>>()?;
+
+  let mut report = MigrationReport::default();
+  for (id, highlight_id, file) in rows {
+    let png = match read_file(dir, &file) {
+      Ok(png) => png,
+      Err(e) => {
+        report.skipped.push(format!("{} ({})", highlight_id, e));
+        continue;
+      }
+    };
+    conn.execute(
+      "UPDATE highlights SET content_image = ?1, content_image_path = NULL WHERE id = ?2",
+      params![render::data_url(&png), id],
+    )?;
+    if let Some(dir) = dir {
+      inlined.push(dir.join(&file));
+    }
+    report.moved += 1;
+    report.bytes += png.len() as u64;
+  }
+  Ok(report)
+}
+
+/// The reverse of `externalize_images`: puts every externalized image back
+/// into the database and deletes its file. Highlights whose file can't be
+/// read keep pointing at it and are listed in the report.
+#[tauri::command]
+pub async fn inline_images(app: AppHandle) -> Result<MigrationReport, AppError> {
+  let mut conn = db::open(&app)?;
+  let tx = conn.transaction()?;
+  let dir = images_dir(&tx)?;
+  let mut inlined = Vec::new();
+  let report = inline_all(&tx, dir.as_deref(), &mut inlined)?;
+  tx.commit()?;
+
+  remove_files(&inlined);
+  log::info!(
+    "Moved {} highlight images ({} bytes) back into the database",
+    report.moved,
+    report.bytes
+  );
+  Ok(report)
+}
@@ -1,127 +1,43 @@
-use tauri_plugin_sql::{Migration, MigrationKind};
 use std::env;
 
+mod actions;
+mod archive;
+mod backup;
+mod clipboard;
+mod color;
+mod db;
+mod deeplink;
+mod duplicates;
+mod encryption;
+mod error;
+mod export;
+mod flatten;
+mod geometry;
+mod goals;
+mod highlights;
+mod images;
+mod library;
+mod logging;
+mod maintenance;
+mod migrations;
+mod optimize;
+mod pdfs;
+mod position;
+mod protocol;
+mod render;
+mod search;
+mod settings;
+mod stats;
+mod sync;
+mod tags;
+mod watcher;
+mod web;
+
 #[cfg_attr(mobile, tauri::mobile_entry_point)]
 pub fn run() {
-  let migrations = vec![
-    Migration {
-      version: 1,
-      description: "create_pdfs_table",
-      sql: "CREATE TABLE IF NOT EXISTS pdfs (
-        id INTEGER PRIMARY KEY AUTOINCREMENT,
-        name TEXT NOT NULL,
-        path TEXT NOT NULL,
-        date_added DATETIME DEFAULT CURRENT_TIMESTAMP,
-        last_opened DATETIME DEFAULT CURRENT_TIMESTAMP
-      );",
-      kind: MigrationKind::Up,
-    },
-    Migration {
-      version: 2,
-      description: "create_highlights_table",
-      sql: "CREATE TABLE IF NOT EXISTS highlights (
-        id INTEGER PRIMARY KEY AUTOINCREMENT,
-        pdf_id INTEGER NOT NULL,
-        highlight_id TEXT NOT NULL,
-        content_text TEXT,
-        content_image TEXT,
-        comment_text TEXT,
-        comment_emoji TEXT,
-        position_data TEXT NOT NULL,
-        page_number INTEGER NOT NULL,
-        created_at DATETIME DEFAULT CURRENT_TIMESTAMP,
-        FOREIGN KEY (pdf_id) REFERENCES pdfs(id) ON DELETE CASCADE
-      );",
-      kind: MigrationKind::Up,
-    },
-    Migration {
-      version: 3,
-      description: "create_tags_table",
-      sql: "CREATE TABLE IF NOT EXISTS tags (
-        id INTEGER PRIMARY KEY AUTOINCREMENT,
-        name TEXT NOT NULL UNIQUE,
-        created_at DATETIME DEFAULT CURRENT_TIMESTAMP
-      );",
-      kind: MigrationKind::Up,
-    },
-    Migration {
-      version: 4,
-      description: "create_highlight_tags_table",
-      sql: "CREATE TABLE IF NOT EXISTS highlight_tags (
-        highlight_id TEXT NOT NULL,
-        tag_id INTEGER NOT NULL,
-        PRIMARY KEY (highlight_id, tag_id),
-        FOREIGN KEY (highlight_id) REFERENCES highlights(highlight_id) ON DELETE CASCADE,
-        FOREIGN KEY (tag_id) REFERENCES tags(id) ON DELETE CASCADE
-      );",
-      kind: MigrationKind::Up,
-    },
-    Migration {
-      version: 5,
-      description: "add_unique_constraint_to_highlight_id",
-      sql: "
-        -- Create new highlights table with UNIQUE constraint on highlight_id
-        CREATE TABLE highlights_new (
-          id INTEGER PRIMARY KEY AUTOINCREMENT,
-          pdf_id INTEGER NOT NULL,
-          highlight_id TEXT NOT NULL UNIQUE,
-          content_text TEXT,
-          content_image TEXT,
-          comment_text TEXT,
-          comment_emoji TEXT,
-          position_data TEXT NOT NULL,
-          page_number INTEGER NOT NULL,
-          created_at DATETIME DEFAULT CURRENT_TIMESTAMP,
-          FOREIGN KEY (pdf_id) REFERENCES pdfs(id) ON DELETE CASCADE
-        );
-        
-        -- Copy all data from old highlights table
-        INSERT INTO highlights_new 
-        SELECT * FROM highlights;
-        
-        -- Drop old highlight_tags table (will be recreated with proper foreign key)
-        DROP TABLE IF EXISTS highlight_tags;
-        
-        -- Drop old highlights table
-        DROP TABLE highlights;
-        
-        -- Rename new table to highlights
-        ALTER TABLE highlights_new RENAME TO highlights;
-        
-        -- Recreate highlight_tags with correct foreign key referencing the UNIQUE column
-        CREATE TABLE highlight_tags (
-          highlight_id TEXT NOT NULL,
-          tag_id INTEGER NOT NULL,
-          PRIMARY KEY (highlight_id, tag_id),
-          FOREIGN KEY (highlight_id) REFERENCES highlights(highlight_id) ON DELETE CASCADE,
-          FOREIGN KEY (tag_id) REFERENCES tags(id) ON DELETE CASCADE
-        );
-      ",
-      kind: MigrationKind::Up,
-    },
-    Migration {
-      version: 6,
-      description: "create_tag_usage_history_table",
-      sql: "CREATE TABLE IF NOT EXISTS tag_usage_history (
-        id INTEGER PRIMARY KEY AUTOINCREMENT,
-        tag_id INTEGER NOT NULL,
-        highlight_id TEXT NOT NULL,
-        used_at DATETIME DEFAULT CURRENT_TIMESTAMP,
-        FOREIGN KEY (tag_id) REFERENCES tags(id) ON DELETE CASCADE,
-        FOREIGN KEY (highlight_id) REFERENCES highlights(highlight_id) ON DELETE CASCADE
-      );
-      CREATE INDEX IF NOT EXISTS idx_tag_usage_tag_id ON tag_usage_history(tag_id);
-      CREATE INDEX IF NOT EXISTS idx_tag_usage_used_at ON tag_usage_history(used_at);",
-      kind: MigrationKind::Up,
-    },
-  ];
+  let migrations = migrations::all();
 
-  // Use different database names for dev and prod to maintain separation
-  let db_name = if cfg!(debug_assertions) {
-    "sqlite:pdf_highlighter_dev.db"
-  } else {
-    "sqlite:pdf_highlighter.db"
-  };
+  let db_name = db::db_url();
 
   println!("🗃️ Using database: {}", db_name);
   println!("📁 Database will be stored in Tauri app data directory");
@@ -133,21 +49,138 @@ pub fn run() {
   }
 
   tauri::Builder::default()
+    .manage(db::ConnectionPool::default())
     .plugin(tauri_plugin_dialog::init())
     .plugin(tauri_plugin_fs::init())
+    .plugin(tauri_plugin_clipboard_manager::init())
+    .plugin(tauri_plugin_opener::init())
+    .plugin(tauri_plugin_deep_link::init())
+    .register_asynchronous_uri_scheme_protocol(protocol::SCHEME, protocol::handle)
     .plugin(
       tauri_plugin_sql::Builder::default()
-        .add_migrations(db_name, migrations)
+        .add_migrations(&db_name, migrations)
         .build(),
     )
+    .invoke_handler(tauri::generate_handler![
+      search::search_highlights,
+      export::export_highlights_markdown,
+      backup::backup_database,
+      backup::restore_database,
+      maintenance::vacuum_database,
+      highlights::update_highlight_color,
+      highlights::soft_delete_highlight,
+      highlights::restore_highlight,
+      highlights::purge_trash,
+      pdfs::register_pdf,
+      tags::rename_tag,
+      tags::merge_tags,
+      library::export_library,
+      library::import_library,
+      stats::get_library_statistics,
+      highlights::find_highlights_by_tags,
+      render::render_page_thumbnail,
+      render::extract_page_text,
+      watcher::set_watch_directory,
+      encryption::unlock_database,
+      encryption::change_passphrase,
+      pdfs::verify_pdf_paths,
+      pdfs::relink_pdf,
+      highlights::clone_highlight,
+      migrations::get_schema_version,
+      highlights::list_highlights_page,
+      highlights::update_highlight_comment,
+      export::export_anki,
+      clipboard::copy_highlight_image,
+      highlights::delete_highlights_for_pdf,
+      maintenance::checkpoint_wal,
+      pdfs::list_recent_pdfs,
+      pdfs::touch_pdf,
+      tags::create_tag,
+      tags::set_tag_color,
+      highlights::annotated_pages,
+      settings::get_setting,
+      settings::set_setting,
+      settings::get_all_settings,
+      search::advanced_search,
+      pdfs::set_last_page,
+      duplicates::find_duplicate_highlights,
+      highlights::save_highlight,
+      migrations::check_migration_integrity,
+      highlights::reassign_highlights,
+      render::get_pdf_page_count,
+      render::set_render_concurrency,
+      export::export_highlight_region_pdf,
+      logging::get_log_path,
+      logging::set_log_level,
+      tags::normalize_tag_names,
+      highlights::highlights_in_range,
+      export::export_tags_csv,
+      highlights::upsert_highlight,
+      tags::add_tag_to_highlights,
+      tags::remove_tag_from_highlights,
+      sync::sync_push,
+      sync::sync_pull,
+      stats::annotation_streak,
+      export::export_notes_for_citation,
+      pdfs::open_external,
+      pdfs::list_pdfs,
+      pdfs::merge_pdfs,
+      highlights::highlight_density,
+      maintenance::repair_orphans,
+      render::render_page_with_highlights,
+      web::import_web_highlights,
+      web::export_web_highlights,
+      pdfs::set_pdf_metadata,
+      pdfs::get_pdf_metadata,
+      pdfs::search_pdfs,
+      highlights::save_highlight_with_tags,
+      tags::tags_for_highlights,
+      optimize::optimize_pdf,
+      highlights::list_highlights_with_comments,
+      pdfs::get_open_history,
+      pdfs::prune_open_history,
+      maintenance::rebuild_search_index,
+      archive::export_bundle,
+      archive::import_bundle,
+      highlights::adjacent_highlight,
+      tags::tag_usage_counts,
+      pdfs::list_pdfs_without_highlights,
+      pdfs::delete_pdf,
+      maintenance::clear_position_cache,
+      search::search_highlights_regex,
+      maintenance::database_integrity_check,
+      goals::set_reading_goal,
+      goals::get_reading_goal,
+      goals::reading_goal_progress,
+      deeplink::highlight_deep_link,
+      deeplink::take_pending_deep_link,
+      export::export_by_tag,
+      highlights::record_highlight_view,
+      highlights::most_viewed_highlights,
+      export::export_highlight_images,
+      actions::undo_last_action,
+      tags::recolor_by_tag,
+      highlights::get_highlight_detail,
+      highlights::remap_highlight_pages,
+      maintenance::storage_usage,
+      images::externalize_images,
+      images::inline_images,
+      tags::list_tags,
+      export::export_html_report,
+      highlights::audit_positions,
+      highlights::save_highlights,
+      stats::take_stats_snapshot,
+      stats::stats_history,
+      export::export_highlights_csv,
+      pdfs::validate_pdf_file,
+      flatten::export_flattened_pdf,
+      images::resolve_highlight_images,
+    ])
     .setup(|app| {
+      app.handle().plugin(logging::plugin())?;
+      log::set_max_level(logging::default_level());
+
       if cfg!(debug_assertions) {
-        app.handle().plugin(
-          tauri_plugin_log::Builder::default()
-            .level(log::LevelFilter::Info)
-            .build(),
-        )?;
-        
         // Check for custom dev server URL from environment
         if let Ok(dev_url) = env::var("TAURI_DEV_SERVER_URL") {
           println!("🌐 Using custom dev server URL: {}", dev_url);
@@ -156,6 +189,15 @@ pub fn run() {
           println!("🌐 Using dev server port: {} -> {}", dev_port, dev_url);
         }
       }
+
+      if let Err(e) = db::enable_wal(app.handle()) {
+        log::warn!("Cannot enable WAL journaling: {}", e);
+      }
+      if let Err(e) = stats::snapshot_if_stale(app.handle()) {
+        log::warn!("Cannot take a statistics snapshot: {}", e);
+      }
+      watcher::start(app.handle());
+      deeplink::init(app.handle());
       Ok(())
     })
     .run(tauri::generate_context!())
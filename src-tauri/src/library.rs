@@ -0,0 +1,773 @@
+use rusqlite::{params, Connection, OptionalExtension, Row};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs::{self, File};
+use std::io::{BufReader, BufWriter, Write};
+use std::path::{Path, PathBuf};
+use tauri::{AppHandle, Emitter};
+
+use crate::error::AppError;
+use crate::{db, highlights, images, migrations};
+
+/// Identifies a library bundle so arbitrary JSON files are rejected on import.
+pub const BUNDLE_FORMAT: &str = "pdf-highlighter-library";
+
+/// Event emitted with an `ImportProgress` while `import_library` runs.
+pub const IMPORT_PROGRESS_EVENT: &str = "import-progress";
+
+/// Maximum number of rows imported between two progress events.
+const PROGRESS_INTERVAL: usize = 500;
+
+// Row shapes of the bundle. These are part of the file format: add fields as
+// `Option` with `#[serde(default)]` so older bundles stay importable.
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PdfRow {
+  pub id: i64,
+  pub name: String,
+  pub path: String,
+  pub date_added: Option<String>,
+  pub last_opened: Option<String>,
+  #[serde(default)]
+  pub sha256: Option<String>,
+  /// JSON object, exported as stored.
+  #[serde(default)]
+  pub metadata: Option<String>,
+  #[serde(default)]
+  pub last_page: Option<i64>,
+  #[serde(default)]
+  pub reading_goal: Option<i64>,
+  #[serde(default)]
+  pub goal_deadline: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct HighlightRow {
+  pub id: i64,
+  pub pdf_id: i64,
+  pub highlight_id: String,
+  pub content_text: Option<String>,
+  /// Base64 data URL, exported as stored. Externalized images are read back
+  /// from the images folder, so bundles are self-contained.
+  pub content_image: Option<String>,
+  pub comment_text: Option<String>,
+  pub comment_emoji: Option<String>,
+  pub position_data: String,
+  pub page_number: i64,
+  pub created_at: Option<String>,
+  #[serde(default)]
+  pub color: Option<String>,
+  #[serde(default)]
+  pub deleted_at: Option<String>,
+  #[serde(default)]
+  pub updated_at: Option<String>,
+  #[serde(default)]
+  pub view_count: Option<i64>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TagRow {
+  pub id: i64,
+  pub name: String,
+  pub created_at: Option<String>,
+  #[serde(default)]
+  pub color: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct HighlightTagRow {
+  pub highlight_id: String,
+  pub tag_id: i64,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct LibraryBundle {
+  pub format: String,
+  pub schema_version: i64,
+  #[serde(default)]
+  pub exported_at: Option<String>,
+  pub pdfs: Vec<PdfRow>,
+  pub highlights: Vec<HighlightRow>,
+  pub tags: Vec<TagRow>,
+  pub highlight_tags: Vec<HighlightTagRow>,
+}
+
+const PDF_QUERY: &str = "SELECT id, name, path, date_added, last_opened, sha256, metadata,
+  last_page, reading_goal, goal_deadline
+  FROM pdfs ORDER BY id";
+
+const HIGHLIGHT_QUERY: &str = "SELECT id, pdf_id, highlight_id, content_text, content_image,
+  comment_text, comment_emoji, position_data, page_number, created_at, color, deleted_at,
+  updated_at, content_image_path, view_count
+  FROM highlights ORDER BY id";
+
+const TAG_QUERY: &str = "SELECT id, name, created_at, color FROM tags ORDER BY id";
+
+const HIGHLIGHT_TAG_QUERY: &str =
+  "SELECT highlight_id, tag_id FROM highlight_tags ORDER BY highlight_id, tag_id";
+
+fn pdf_row(row: &Row) -> rusqlite::Result<PdfRow> {
+  Ok(PdfRow {
+    id: row.get(0)?,
+    name: row.get(1)?,
+    path: row.get(2)?,
+    date_added: row.get(3)?,
+    last_opened: row.get(4)?,
+    sha256: row.get(5)?,
+    metadata: row.get(6)?,
+    last_page: row.get(7)?,
+    reading_goal: row.get(8)?,
+    goal_deadline: row.get(9)?,
+  })
+}
+
+fn highlight_row(row: &Row) -> rusqlite::Result<HighlightRow> {
+  Ok(HighlightRow {
+    id: row.get(0)?,
+    pdf_id: row.get(1)?,
+    highlight_id: row.get(2)?,
+    content_text: row.get(3)?,
+    content_image: row.get(4)?,
+    comment_text: row.get(5)?,
+    comment_emoji: row.get(6)?,
+    position_data: row.get(7)?,
+    page_number: row.get(8)?,
+    created_at: row.get(9)?,
+    color: row.get(10)?,
+    deleted_at: row.get(11)?,
+    updated_at: row.get(12)?,
+    view_count: row.get(14)?,
+  })
+}
+
+fn tag_row(row: &Row) -> rusqlite::Result<TagRow> {
+  Ok(TagRow {
+    id: row.get(0)?,
+    name: row.get(1)?,
+    created_at: row.get(2)?,
+    color: row.get(3)?,
+  })
+}
+
+fn highlight_tag_row(row: &Row) -> rusqlite::Result<HighlightTagRow> {
+  Ok(HighlightTagRow {
+    highlight_id: row.get(0)?,
+    tag_id: row.get(1)?,
+  })
+}
+
+/// Writes `"key":[...]` with one row serialized at a time, straight from the
+/// query cursor, so no table is ever fully materialized in memory.
+fn write_table<W, T, F>(
+  out: &mut W,
+  conn: &Connection,
+  key: &str,
+  sql: &str,
+  map: F,
+) -> Result<(), String>
+where
+  W: Write,
+  T: Serialize,
+  F: FnMut(&Row) -> rusqlite::Result<T>,
+{
+  let mut stmt = conn.prepare(sql).map_err(|e| e.to_string())?;
+  let rows = stmt.query_map([], map).map_err(|e| e.to_string())?;
+
+  write!(out, ",\"{}\":[", key).map_err(|e| e.to_string())?;
+  for (i, row) in rows.enumerate() {
+    let row = row.map_err(|e| e.to_string())?;
+    if i > 0 {
+      out.write_all(b",").map_err(|e| e.to_string())?;
+    }
+    serde_json::to_writer(&mut *out, &row).map_err(|e| e.to_string())?;
+  }
+  out.write_all(b"]").map_err(|e| e.to_string())
+}
+
+fn write_bundle(conn: &Connection, dest: &Path) -> Result<(), String> {
+  let file = File::create(dest).map_err(|e| format!("Cannot create {}: {}", dest.display(), e))?;
+  write_bundle_to(conn, BufWriter::new(file)).map(|_| ())
+}
+
+/// Writes the bundle to any writer and returns its `exported_at`. `conn`
+/// should be inside a transaction so the tables are read from one snapshot.
+pub fn write_bundle_to<W: Write>(conn: &Connection, out: W) -> Result<String, String> {
+  write_bundle_with(conn, out, |_| {})
+}
+
+/// Like `write_bundle_to`, passing every PDF row through `rewrite_pdf` before
+/// it is written.
+pub fn write_bundle_with<W, F>(
+  conn: &Connection,
+  mut out: W,
+  mut rewrite_pdf: F,
+) -> Result<String, String>
+where
+  W: Write,
+  F: FnMut(&mut PdfRow),
+{
+  let schema_version = migrations::applied_version(conn).map_err(|e| e.to_string())?;
+  let exported_at = chrono::Utc::now().to_rfc3339();
+  write!(
+    out,
+    "{{\"format\":\"{}\",\"schema_version\":{},\"exported_at\":{}",
+    BUNDLE_FORMAT,
+    schema_version,
+    serde_json::to_string(&exported_at).map_err(|e| e.to_string())?
+  )
+  .map_err(|e| e.to_string())?;
+
+  write_table(&mut out, conn, "pdfs", PDF_QUERY, |row| {
+    let mut pdf = pdf_row(row)?;
+    rewrite_pdf(&mut pdf);
+    Ok(pdf)
+  })?;
+  let images_dir = images::images_dir(conn).map_err(|e| e.to_string())?;
+  write_table(&mut out, conn, "highlights", HIGHLIGHT_QUERY, |row| {
+    let mut highlight = highlight_row(row)?;
+    if let Some(file) = row.get::<_, Option<String>>(13)? {
+      match images::inline(images_dir.as_deref(), &file) {
+        Ok(image) => highlight.content_image = Some(image),
+        Err(e) => log::warn!(
+          "Highlight {} exported without its image: {}",
+          highlight.highlight_id,
+          e
+        ),
+      }
+    }
+    Ok(highlight)
+  })?;
+  write_table(&mut out, conn, "tags", TAG_QUERY, tag_row)?;
+  write_table(
+    &mut out,
+    conn,
+    "highlight_tags",
+    HIGHLIGHT_TAG_QUERY,
+    highlight_tag_row,
+  )?;
+
+  out.write_all(b"}").map_err(|e| e.to_string())?;
+  out.flush().map_err(|e| e.to_string())?;
+  Ok(exported_at)
+}
+
+/// Writes the whole library (PDFs, highlights, tags and their links) to a
+/// versioned JSON bundle at `dest_path`.
+#[tauri::command]
+pub async fn export_library(app: AppHandle, dest_path: String) -> Result<(), AppError> {
+  let dest = PathBuf::from(dest_path);
+  let mut conn = db::open(&app)?;
+  // A read transaction keeps the four tables consistent with each other even
+  // if the frontend writes while the export runs.
+  let tx = conn.transaction()?;
+  if let Err(e) = write_bundle(&tx, &dest) {
+    let _ = fs::remove_file(&dest);
+    return Err(e.into());
+  }
+  tx.commit()?;
+  log::info!("Library exported to {}", dest.display());
+  Ok(())
+}
+
+/// What to do when a bundle row collides with an existing one. PDFs collide on
+/// content hash or path, highlights on `highlight_id`. Tags are identified by
+/// name, so a colliding tag is always reused rather than duplicated; with
+/// `Overwrite` and `Newer` it takes the bundle's color.
+#[derive(Debug, Clone, Copy, Deserialize)]
+pub enum ImportStrategy {
+  /// Keep the existing row and ignore the bundle's.
+  Skip,
+  /// Replace the existing row's fields with the bundle's.
+  Overwrite,
+  /// Import the bundle's row alongside the existing one, under a new name
+  /// (PDFs) or a new `highlight_id` (highlights).
+  Rename,
+  /// Keep whichever highlight was updated last. PDFs carry no edit time, so
+  /// existing ones are kept as with `Skip`, only filling in the metadata,
+  /// last page and reading goal they lack.
+  Newer,
+}
+
+#[derive(Debug, Default, Serialize)]
+pub struct TableCounts {
+  pub inserted: usize,
+  pub skipped: usize,
+  pub overwritten: usize,
+}
+
+#[derive(Debug, Default, Serialize)]
+pub struct ImportReport {
+  pub pdfs: TableCounts,
+  pub highlights: TableCounts,
+  pub tags: TableCounts,
+  pub highlight_tags: TableCounts,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ImportProgress {
+  /// Bundle rows handled so far, across all tables.
+  pub processed: usize,
+  pub total: usize,
+  pub current_table: &'static str,
+}
+
+/// Emits `IMPORT_PROGRESS_EVENT` whenever a table starts and at least every
+/// `PROGRESS_INTERVAL` rows.
+pub struct Progress<'a> {
+  app: &'a AppHandle,
+  processed: usize,
+  total: usize,
+  current_table: &'static str,
+  last_emitted: usize,
+}
+
+impl<'a> Progress<'a> {
+  pub fn new(app: &'a AppHandle, bundle: &LibraryBundle) -> Self {
+    Progress {
+      app,
+      processed: 0,
+      total: bundle.pdfs.len()
+        + bundle.highlights.len()
+        + bundle.tags.len()
+        + bundle.highlight_tags.len(),
+      current_table: "pdfs",
+      last_emitted: 0,
+    }
+  }
+
+  fn start_table(&mut self, table: &'static str) {
+    self.current_table = table;
+    self.emit();
+  }
+
+  fn row(&mut self) {
+    self.processed += 1;
+    if self.processed - self.last_emitted >= PROGRESS_INTERVAL {
+      self.emit();
+    }
+  }
+
+  fn emit(&mut self) {
+    self.last_emitted = self.processed;
+    let payload = ImportProgress {
+      processed: self.processed,
+      total: self.total,
+      current_table: self.current_table,
+    };
+    // Progress is cosmetic; a lost event must not fail the import.
+    if let Err(e) = self.app.emit(IMPORT_PROGRESS_EVENT, &payload) {
+      log::warn!("Cannot emit import progress: {}", e);
+    }
+  }
+}
+
+fn read_bundle(path: &Path) -> Result<LibraryBundle, String> {
+  let file = File::open(path).map_err(|e| format!("Cannot open {}: {}", path.display(), e))?;
+  let bundle: LibraryBundle = serde_json::from_reader(BufReader::new(file))
+    .map_err(|e| format!("{} is not a valid library bundle: {}", path.display(), e))?;
+  check_bundle(&bundle).map_err(|e| format!("{}: {}", path.display(), e))?;
+  Ok(bundle)
+}
+
+/// Rejects bundles that aren't ours or come from a newer schema.
+pub fn check_bundle(bundle: &LibraryBundle) -> Result<(), String> {
+  if bundle.format != BUNDLE_FORMAT {
+    return Err(format!("Not a library bundle (format {:?})", bundle.format));
+  }
+  let latest = migrations::latest_version();
+  if bundle.schema_version > latest {
+    return Err(format!(
+      "Bundle was exported by a newer version of the app (schema version {}, this app supports up to {})",
+      bundle.schema_version, latest
+    ));
+  }
+  Ok(())
+}
+
+fn insert_pdf(conn: &Connection, pdf: &PdfRow, name: &str) -> rusqlite::Result<i64> {
+  conn.execute(
+    "INSERT INTO pdfs (name, path, date_added, last_opened, sha256, metadata, last_page,
+       reading_goal, goal_deadline)
+     VALUES (?1, ?2, COALESCE(?3, CURRENT_TIMESTAMP), COALESCE(?4, CURRENT_TIMESTAMP), ?5, ?6,
+             ?7, ?8, ?9)",
+    params![
+      name,
+      pdf.path,
+      pdf.date_added,
+      pdf.last_opened,
+      pdf.sha256,
+      pdf.metadata,
+      pdf.last_page,
+      pdf.reading_goal,
+      pdf.goal_deadline
+    ],
+  )?;
+  Ok(conn.last_insert_rowid())
+}
+
+fn import_pdfs(
+  conn: &Connection,
+  pdfs: &[PdfRow],
+  strategy: ImportStrategy,
+  counts: &mut TableCounts,
+  progress: &mut Progress,
+) -> rusqlite::Result<HashMap<i64, i64>> {
+  progress.start_table("pdfs");
+  let mut ids = HashMap::new();
+  for pdf in pdfs {
+    progress.row();
+    let existing: Option<i64> = conn
+      .query_row(
+        "SELECT id FROM pdfs
+         WHERE (?1 IS NOT NULL AND sha256 = ?1) OR path = ?2
+         ORDER BY sha256 IS NULL, id
+         LIMIT 1",
+        params![pdf.sha256, pdf.path],
+        |row| row.get(0),
+      )
+      .optional()?;
+
+    let id = match (existing, strategy) {
+      (None, _) => {
+        counts.inserted += 1;
+        insert_pdf(conn, pdf, &pdf.name)?
+      }
+      (Some(id), ImportStrategy::Skip) => {
+        counts.skipped += 1;
+        id
+      }
+      (Some(id), ImportStrategy::Newer) => {
+        conn.execute(
+          "UPDATE pdfs SET metadata = COALESCE(metadata, ?1),
+             last_page = COALESCE(last_page, ?2),
+             reading_goal = COALESCE(reading_goal, ?3),
+             goal_deadline = COALESCE(goal_deadline, ?4)
+           WHERE id = ?5",
+          params![
+            pdf.metadata,
+            pdf.last_page,
+            pdf.reading_goal,
+            pdf.goal_deadline,
+            id
+          ],
+        )?;
+        counts.skipped += 1;
+        id
+      }
+      (Some(id), ImportStrategy::Overwrite) => {
+        conn.execute(
+          "UPDATE pdfs SET name = ?1, path = ?2,
+             date_added = COALESCE(?3, date_added),
+             last_opened = COALESCE(?4, last_opened),
+             sha256 = COALESCE(?5, sha256),
+             metadata = COALESCE(?6, metadata),
+             last_page = COALESCE(?7, last_page),
+             reading_goal = COALESCE(?8, reading_goal),
+             goal_deadline = COALESCE(?9, goal_deadline)
+           WHERE id = ?10",
+          params![
+            pdf.name,
+            pdf.path,
+            pdf.date_added,
+            pdf.last_opened,
+            pdf.sha256,
+            pdf.metadata,
+            pdf.last_page,
+            pdf.reading_goal,
+            pdf.goal_deadline,
+            id
+          ],
+        )?;
+        counts.overwritten += 1;
+        id
+      }
+      (Some(_), ImportStrategy::Rename) => {
+        counts.inserted += 1;
+        insert_pdf(conn, pdf, &format!("{} (imported)", pdf.name))?
+      }
+    };
+    ids.insert(pdf.id, id);
+  }
+  Ok(ids)
+}
+
+fn import_tags(
+  conn: &Connection,
+  tags: &[TagRow],
+  strategy: ImportStrategy,
+  counts: &mut TableCounts,
+  progress: &mut Progress,
+) -> rusqlite::Result<HashMap<i64, i64>> {
+  progress.start_table("tags");
+  let mut ids = HashMap::new();
+  for tag in tags {
+    progress.row();
+    let existing: Option<i64> = conn
+      .query_row(
+        "SELECT id FROM tags WHERE name = ?1",
+        params![tag.name],
+        |row| row.get(0),
+      )
+      .optional()?;
+    let id = match (existing, strategy) {
+      (Some(id), ImportStrategy::Overwrite | ImportStrategy::Newer) if tag.color.is_some() => {
+        let changed = conn.execute(
+          "UPDATE tags SET color = ?1 WHERE id = ?2 AND color IS NOT ?1",
+          params![tag.color, id],
+        )?;
+        if changed > 0 {
+          counts.overwritten += 1;
+        } else {
+          counts.skipped += 1;
+        }
+        id
+      }
+      (Some(id), _) => {
+        counts.skipped += 1;
+        id
+      }
+      (None, _) => {
+        conn.execute(
+          "INSERT INTO tags (name, created_at, color)
+           VALUES (?1, COALESCE(?2, CURRENT_TIMESTAMP), COALESCE(?3, '#9e9e9e'))",
+          params![tag.name, tag.created_at, tag.color],
+        )?;
+        counts.inserted += 1;
+        conn.last_insert_rowid()
+      }
+    };
+    ids.insert(tag.id, id);
+  }
+  Ok(ids)
+}
+
+fn insert_highlight(
+  conn: &Connection,
+  h: &HighlightRow,
+  highlight_id: &str,
+  pdf_id: i64,
+) -> rusqlite::Result<()> {
+  conn.execute(
+    "INSERT INTO highlights
+       (pdf_id, highlight_id, content_text, content_image, comment_text, comment_emoji,
+        position_data, page_number, created_at, color, deleted_at, updated_at, view_count)
+     VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, COALESCE(?9, CURRENT_TIMESTAMP),
+             COALESCE(?10, '#ffff00'), ?11, ?12, COALESCE(?13, 0))",
+    params![
+      pdf_id,
+      highlight_id,
+      h.content_text,
+      h.content_image,
+      h.comment_text,
+      h.comment_emoji,
+      h.position_data,
+      h.page_number,
+      h.created_at,
+      h.color,
+      h.deleted_at,
+      h.updated_at,
+      h.view_count
+    ],
+  )?;
+  Ok(())
+}
+
+/// Replaces the existing row for `h.highlight_id` with the bundle's, tag
+/// links included.
+fn overwrite_highlight(conn: &Connection, h: &HighlightRow, pdf_id: i64) -> rusqlite::Result<()> {
+  // Keeping the bundle's updated_at stops the update trigger from stamping
+  // the import time, which would make the row look locally edited.
+  conn.execute(
+    "UPDATE highlights SET pdf_id = ?1, content_text = ?2, content_image = ?3,
+       content_image_path = NULL, comment_text = ?4, comment_emoji = ?5, position_data = ?6,
+       page_number = ?7,
+       created_at = COALESCE(?8, created_at), color = COALESCE(?9, color),
+       deleted_at = ?10, updated_at = COALESCE(?11, updated_at),
+       view_count = COALESCE(?12, view_count)
+     WHERE highlight_id = ?13",
+    params![
+      pdf_id,
+      h.content_text,
+      h.content_image,
+      h.comment_text,
+      h.comment_emoji,
+      h.position_data,
+      h.page_number,
+      h.created_at,
+      h.color,
+      h.deleted_at,
+      h.updated_at,
+      h.view_count,
+      h.highlight_id
+    ],
+  )?;
+  // The bundle's tag links replace the existing ones
+  conn.execute(
+    "DELETE FROM highlight_tags WHERE highlight_id = ?1",
+    params![h.highlight_id],
+  )?;
+  Ok(())
+}
+
+/// Whether the bundle's copy of a highlight was edited after the local one.
+/// Timestamps are compared as `YYYY-MM-DD HH:MM:SS` strings.
+fn bundle_is_newer(h: &HighlightRow, local_updated_at: Option<&str>) -> bool {
+  match (h.updated_at.as_deref(), local_updated_at) {
+    (Some(bundle), Some(local)) => bundle > local,
+    (Some(_), None) => true,
+    (None, _) => false,
+  }
+}
+
+/// Imports highlights and returns the bundle `highlight_id` -> local
+/// `highlight_id` mapping for the rows whose tag links should be imported.
+fn import_highlights(
+  conn: &Connection,
+  rows: &[HighlightRow],
+  pdf_ids: &HashMap<i64, i64>,
+  strategy: ImportStrategy,
+  counts: &mut TableCounts,
+  progress: &mut Progress,
+) -> rusqlite::Result<HashMap<String, String>> {
+  progress.start_table("highlights");
+  let mut ids = HashMap::new();
+  for h in rows {
+    progress.row();
+    let Some(&pdf_id) = pdf_ids.get(&h.pdf_id) else {
+      // Highlight of a PDF that isn't in the bundle
+      counts.skipped += 1;
+      continue;
+    };
+
+    // Checking first instead of letting the UNIQUE constraint on
+    // highlight_id fail keeps one collision from aborting the transaction.
+    let existing: Option<Option<String>> = conn
+      .query_row(
+        "SELECT updated_at FROM highlights WHERE highlight_id = ?1",
+        params![h.highlight_id],
+        |row| row.get(0),
+      )
+      .optional()?;
+
+    let id = match (existing, strategy) {
+      (None, _) => {
+        insert_highlight(conn, h, &h.highlight_id, pdf_id)?;
+        counts.inserted += 1;
+        h.highlight_id.clone()
+      }
+      (Some(_), ImportStrategy::Skip) => {
+        counts.skipped += 1;
+        continue;
+      }
+      (Some(local), ImportStrategy::Newer) if !bundle_is_newer(h, local.as_deref()) => {
+        counts.skipped += 1;
+        continue;
+      }
+      (Some(_), ImportStrategy::Overwrite | ImportStrategy::Newer) => {
+        overwrite_highlight(conn, h, pdf_id)?;
+        counts.overwritten += 1;
+        h.highlight_id.clone()
+      }
+      (Some(_), ImportStrategy::Rename) => {
+        let new_id = highlights::new_highlight_id();
+        insert_highlight(conn, h, &new_id, pdf_id)?;
+        counts.inserted += 1;
+        new_id
+      }
+    };
+    ids.insert(h.highlight_id.clone(), id);
+  }
+  Ok(ids)
+}
+
+fn import_highlight_tags(
+  conn: &Connection,
+  links: &[HighlightTagRow],
+  highlight_ids: &HashMap<String, String>,
+  tag_ids: &HashMap<i64, i64>,
+  counts: &mut TableCounts,
+  progress: &mut Progress,
+) -> rusqlite::Result<()> {
+  progress.start_table("highlight_tags");
+  let mut stmt =
+    conn.prepare("INSERT OR IGNORE INTO highlight_tags (highlight_id, tag_id) VALUES (?1, ?2)")?;
+  for link in links {
+    progress.row();
+    let (Some(highlight_id), Some(tag_id)) = (
+      highlight_ids.get(&link.highlight_id),
+      tag_ids.get(&link.tag_id),
+    ) else {
+      counts.skipped += 1;
+      continue;
+    };
+    if stmt.execute(params![highlight_id, tag_id])? == 1 {
+      counts.inserted += 1;
+    } else {
+      counts.skipped += 1;
+    }
+  }
+  Ok(())
+}
+
+/// Imports a bundle into `conn`, which must be inside a transaction.
+pub fn import(
+  conn: &Connection,
+  bundle: &LibraryBundle,
+  strategy: ImportStrategy,
+  progress: &mut Progress,
+) -> rusqlite::Result<ImportReport> {
+  let mut report = ImportReport::default();
+  let pdf_ids = import_pdfs(conn, &bundle.pdfs, strategy, &mut report.pdfs, progress)?;
+  let tag_ids = import_tags(conn, &bundle.tags, strategy, &mut report.tags, progress)?;
+  let highlight_ids = import_highlights(
+    conn,
+    &bundle.highlights,
+    &pdf_ids,
+    strategy,
+    &mut report.highlights,
+    progress,
+  )?;
+  import_highlight_tags(
+    conn,
+    &bundle.highlight_tags,
+    &highlight_ids,
+    &tag_ids,
+    &mut report.highlight_tags,
+    progress,
+  )?;
+  progress.emit();
+  Ok(report)
+}
+
+fn import_file(
+  app: &AppHandle,
+  src_path: &str,
+  strategy: ImportStrategy,
+) -> Result<ImportReport, AppError> {
+  let bundle = read_bundle(Path::new(src_path)).map_err(AppError::InvalidInput)?;
+  let mut progress = Progress::new(app, &bundle);
+  let mut conn = db::open(app)?;
+  let tx = conn.transaction()?;
+  let report = import(&tx, &bundle, strategy, &mut progress)?;
+  tx.commit()?;
+  Ok(report)
+}
+
+/// Imports a bundle written by `export_library`. Everything happens in one
+/// transaction, so a failure leaves the library untouched. Progress is
+/// reported through `IMPORT_PROGRESS_EVENT`.
+#[tauri::command]
+pub async fn import_library(
+  app: AppHandle,
+  src_path: String,
+  strategy: ImportStrategy,
+) -> Result<ImportReport, AppError> {
+  // Parsing and inserting a large bundle blocks for a long time; keep it off
+  // the async runtime's worker threads.
+  let path = src_path.clone();
+  let report = tauri::async_runtime::spawn_blocking(move || import_file(&app, &path, strategy))
+    .await
+    .map_err(|e| format!("Import failed: {}", e))??;
+  log::info!("Library imported from {}: {:?}", src_path, report);
+  Ok(report)
+}
@@ -0,0 +1,67 @@
+use std::path::PathBuf;
+use tauri::{AppHandle, Manager, Runtime};
+use tauri_plugin_log::{RotationStrategy, Target, TargetKind};
+
+use crate::error::AppError;
+
+/// Stem of the log file in the app log directory.
+const LOG_FILE_NAME: &str = "pdf-highlighter";
+
+/// Log files are rotated once they reach this size.
+const MAX_LOG_FILE_BYTES: u128 = 5 * 1024 * 1024;
+
+/// Rotated log files kept besides the current one.
+const ROTATED_LOG_FILES: usize = 3;
+
+pub fn default_level() -> log::LevelFilter {
+  if cfg!(debug_assertions) {
+    log::LevelFilter::Info
+  } else {
+    log::LevelFilter::Warn
+  }
+}
+
+/// The log plugin, writing to stdout and a size-capped rotating file. It lets
+/// everything through; the effective level is the `log` crate's global max
+/// level, which `set_log_level` changes at runtime.
+pub fn plugin<R: Runtime>() -> tauri::plugin::TauriPlugin<R> {
+  tauri_plugin_log::Builder::default()
+    .targets([
+      Target::new(TargetKind::Stdout),
+      Target::new(TargetKind::LogDir {
+        file_name: Some(LOG_FILE_NAME.to_string()),
+      }),
+    ])
+    .level(log::LevelFilter::Trace)
+    .max_file_size(MAX_LOG_FILE_BYTES)
+    .rotation_strategy(RotationStrategy::KeepSome(ROTATED_LOG_FILES))
+    .build()
+}
+
+fn log_path<R: Runtime>(app: &AppHandle<R>) -> Result<PathBuf, String> {
+  let dir = app
+    .path()
+    .app_log_dir()
+    .map_err(|e| format!("Could not resolve app log directory: {}", e))?;
+  Ok(dir.join(format!("{}.log", LOG_FILE_NAME)))
+}
+
+/// Location of the current log file, to attach to bug reports.
+#[tauri::command]
+pub async fn get_log_path(app: AppHandle) -> Result<String, AppError> {
+  Ok(log_path(&app)?.to_string_lossy().into_owned())
+}
+
+/// Changes the log level until the app quits. Accepts `off`, `error`, `warn`,
+/// `info`, `debug` and `trace`, in any case.
+#[tauri::command]
+pub async fn set_log_level(level: String) -> Result<(), AppError> {
+  let level: log::LevelFilter = level
+    .trim()
+    .parse()
+    .map_err(|_| AppError::InvalidInput(format!("Unknown log level {:?}", level)))?;
+
+  log::set_max_level(level);
+  log::info!("Log level set to {}", level);
+  Ok(())
+}
@@ -0,0 +1,257 @@
+use rusqlite::Connection;
+use serde::Serialize;
+use std::fs;
+use std::path::Path;
+use tauri::AppHandle;
+
+use crate::error::AppError;
+use crate::{backup, db, images, position};
+
+#[derive(Debug, Serialize)]
+pub struct VacuumStats {
+  pub bytes_before: u64,
+  pub bytes_after: u64,
+}
+
+fn file_size(path: &Path) -> Result<u64, String> {
+  fs::metadata(path)
+    .map(|m| m.len())
+    .map_err(|e| format!("Cannot read size of {}: {}", path.display(), e))
+}
+
+/// Rebuilds the database file to reclaim the space left behind by deleted
+/// rows.
+#[tauri::command]
+pub async fn vacuum_database(app: AppHandle) -> Result<VacuumStats, AppError> {
+  let path = db::db_path(&app)?;
+  let conn = db::open(&app)?;
+
+  // VACUUM fails inside a transaction. A fresh connection is always in
+  // autocommit mode, but check so the error says what went wrong.
+  if !conn.is_autocommit() {
+    return Err(AppError::Conflict(
+      "Cannot vacuum while a transaction is open".to_string(),
+    ));
+  }
+
+  let bytes_before = file_size(&path).map_err(AppError::Io)?;
+  conn.execute_batch("VACUUM;")?;
+  // In WAL mode the rebuilt pages land in the WAL first; fold them back into
+  // the main file so the size below is meaningful. This is a no-op otherwise.
+  conn.query_row("PRAGMA wal_checkpoint(TRUNCATE)", [], |_| Ok(()))?;
+  let bytes_after = file_size(&path).map_err(AppError::Io)?;
+
+  log::info!(
+    "Vacuumed database: {} -> {} bytes ({} reclaimed)",
+    bytes_before,
+    bytes_after,
+    bytes_before.saturating_sub(bytes_after)
+  );
+  Ok(VacuumStats {
+    bytes_before,
+    bytes_after,
+  })
+}
+
+/// Copies the pages in the WAL back into the main database file and empties
+/// the WAL. Readers still open on an older snapshot make this fail rather
+/// than wait.
+#[tauri::command]
+pub async fn checkpoint_wal(app: AppHandle) -> Result<(), AppError> {
+  let conn = db::open(&app)?;
+  let (busy, log_pages, checkpointed): (i64, i64, i64) =
+    conn.query_row("PRAGMA wal_checkpoint(TRUNCATE)", [], |row| {
+      Ok((row.get(0)?, row.get(1)?, row.get(2)?))
+    })?;
+  if busy != 0 {
+    return Err(AppError::Conflict(
+      "Checkpoint was blocked by another connection, try again later".to_string(),
+    ));
+  }
+
+  log::info!(
+    "Checkpointed WAL: {} of {} pages written back",
+    checkpointed,
+    log_pages
+  );
+  Ok(())
+}
+
+#[derive(Debug, Serialize)]
+pub struct RepairReport {
+  /// `highlight_tags` rows whose highlight no longer exists.
+  pub missing_highlight: usize,
+  /// `highlight_tags` rows whose tag no longer exists (and whose highlight
+  /// does).
+  pub missing_tag: usize,
+}
+
+fn delete_orphans(conn: &Connection) -> rusqlite::Result<RepairReport> {
+  let missing_highlight = conn.execute(
+    "DELETE FROM highlight_tags
+     WHERE highlight_id NOT IN (SELECT highlight_id FROM highlights)",
+    [],
+  )?;
+  let missing_tag = conn.execute(
+    "DELETE FROM highlight_tags WHERE tag_id NOT IN (SELECT id FROM tags)",
+    [],
+  )?;
+  Ok(RepairReport {
+    missing_highlight,
+    missing_tag,
+  })
+}
+
+/// Deletes tag links left pointing at a highlight or tag that is gone, from
+/// the time foreign keys weren't enforced, and reports how many were found.
+#[tauri::command]
+pub async fn repair_orphans(app: AppHandle) -> Result<RepairReport, AppError> {
+  let mut conn = db::open(&app)?;
+  let tx = conn.transaction()?;
+  let report = delete_orphans(&tx)?;
+  tx.commit()?;
+  log::info!("Repaired orphaned tag links: {:?}", report);
+  Ok(report)
+}
+
+/// Rebuilds the highlight search index from the `highlights` table, for when
+/// it no longer matches, e.g. after a bulk import into a database without the
+/// triggers. Returns the number of highlights indexed, trashed ones included.
+#[tauri::command]
+pub async fn rebuild_search_index(app: AppHandle) -> Result<usize, AppError> {
+  let mut conn = db::open(&app)?;
+  let tx = conn.transaction()?;
+  tx.execute(
+    "INSERT INTO highlights_fts(highlights_fts) VALUES('rebuild')",
+    [],
+  )?;
+  let indexed: i64 = tx.query_row("SELECT COUNT(*) FROM highlights", [], |row| row.get(0))?;
+  tx.commit()?;
+  log::info!("Rebuilt the search index over {} highlights", indexed);
+  Ok(indexed as usize)
+}
+
+/// Forgets every cached highlight position, for debugging.
+#[tauri::command]
+pub async fn clear_position_cache() -> Result<(), AppError> {
+  position::clear_cache();
+  Ok(())
+}
+
+#[derive(Debug, Serialize)]
+pub struct IntegrityReport {
+  pub ok: bool,
+  /// What `integrity_check` and `foreign_key_check` found, one line each.
+  pub problems: Vec<String>,
+}
+
+fn check_integrity(conn: &Connection) -> rusqlite::Result<Vec<String>> {
+  let mut problems = conn
+    .prepare("PRAGMA integrity_check")?
+    .query_map([], |row| row.get::<_, String>(0))?
+    .filter(|line| !matches!(line.as_deref(), Ok("ok")))
+    .collect::<rusqlite::Result<Vec<_>>>()?;
+
+  let mut stmt = conn.prepare("PRAGMA foreign_key_check")?;
+  let violations = stmt.query_map([], |row| {
+    let table: String = row.get(0)?;
+    // NULL for WITHOUT ROWID tables, which this schema doesn't have
+    let rowid: Option<i64> = row.get(1)?;
+    let parent: String = row.get(2)?;
+    Ok(format!(
+      "{} row {} references a missing {} row",
+      table,
+      rowid.unwrap_or_default(),
+      parent
+    ))
+  })?;
+
+  for violation in violations {
+    problems.push(violation?);
+  }
+  Ok(problems)
+}
+
+/// Runs SQLite's `integrity_check` and `foreign_key_check`, e.g. before a
+/// backup, so corruption is noticed while an older backup still exists.
+#[tauri::command]
+pub async fn database_integrity_check(app: AppHandle) -> Result<IntegrityReport, AppError> {
+  let conn = db::open(&app)?;
+  let problems = check_integrity(&conn)?;
+  if problems.is_empty() {
+    log::info!("Database integrity check passed");
+  } else {
+    log::warn!(
+      "Database integrity check found {} problems: {:?}",
+      problems.len(),
+      problems
+    );
+  }
+  Ok(IntegrityReport {
+    ok: problems.is_empty(),
+    problems,
+  })
+}
+
+#[derive(Debug, Serialize)]
+pub struct StorageUsage {
+  /// Size of the database file in bytes.
+  pub database_bytes: u64,
+  /// Size of its write-ahead log, 0 when there is none.
+  pub wal_bytes: u64,
+  /// Bytes of base64 image data of area highlights, trashed ones included,
+  /// plus the size of the files of externalized ones.
+  pub image_bytes: u64,
+  /// Combined size of the library's PDF files that still exist.
+  pub pdf_bytes: u64,
+  /// Library entries whose file is gone.
+  pub missing_pdfs: usize,
+}
+
+/// Where the library's space goes, in bytes, for a storage overview.
+#[tauri::command]
+pub async fn storage_usage(app: AppHandle) -> Result<StorageUsage, AppError> {
+  let path = db::db_path(&app)?;
+  let conn = db::open(&app)?;
+  let image_bytes: i64 = conn.query_row(
+    "SELECT COALESCE(SUM(LENGTH(content_image)), 0) FROM highlights",
+    [],
+    |row| row.get(0),
+  )?;
+  let image_files = match images::images_dir(&conn)? {
+    Some(dir) => conn
+      .prepare("SELECT content_image_path FROM highlights WHERE content_image_path IS NOT NULL")?
+      .query_map([], |row| Ok(dir.join(row.get::<_, String>(0)?)))?
+      .collect::<rusqlite::Result<Vec<_>>>()?,
+    None => Vec::new(),
+  };
+  let pdf_paths = conn
+    .prepare("SELECT DISTINCT path FROM pdfs")?
+    .query_map([], |row| row.get::<_, String>(0))?
+    .collect::<rusqlite::Result<Vec<_>>>()?;
+  drop(conn);
+
+  let mut pdf_bytes = 0;
+  let mut missing_pdfs = 0;
+  for pdf_path in pdf_paths {
+    match fs::metadata(&pdf_path) {
+      Ok(metadata) => pdf_bytes += metadata.len(),
+      Err(_) => missing_pdfs += 1,
+    }
+  }
+
+  Ok(StorageUsage {
+    database_bytes: file_size(&path).map_err(AppError::Io)?,
+    wal_bytes: fs::metadata(backup::sidecar(&path, "-wal"))
+      .map(|metadata| metadata.len())
+      .unwrap_or(0),
+    image_bytes: image_bytes as u64
+      + image_files
+        .iter()
+        .filter_map(|file| fs::metadata(file).ok())
+        .map(|metadata| metadata.len())
+        .sum::<u64>(),
+    pdf_bytes,
+    missing_pdfs,
+  })
+}
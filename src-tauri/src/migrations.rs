@@ -0,0 +1,639 @@
+use serde::Serialize;
+use sqlx::error::BoxDynError;
+use sqlx::migrate::{Migration as SqlxMigration, MigrationSource, MigrationType, Migrator};
+use sqlx::sqlite::{SqliteConnectOptions, SqlitePoolOptions};
+use std::collections::BTreeMap;
+use std::future::Future;
+use std::path::Path;
+use std::pin::Pin;
+use tauri::AppHandle;
+use tauri_plugin_sql::{Migration, MigrationKind};
+
+use crate::db;
+use crate::error::AppError;
+
+// NOTE: sqlx checksums the SQL of every applied migration, so the text of an
+// existing migration (whitespace included) must never change.
+
+/// Schema migrations for the app database, applied by tauri-plugin-sql the
+/// first time the frontend loads it. Versions are recorded by sqlx in the
+/// `_sqlx_migrations` table.
+pub fn all() -> Vec<Migration> {
+  vec![
+    Migration {
+      version: 1,
+      description: "create_pdfs_table",
+      sql: "CREATE TABLE IF NOT EXISTS pdfs (
+        id INTEGER PRIMARY KEY AUTOINCREMENT,
+        name TEXT NOT NULL,
+        path TEXT NOT NULL,
+        date_added DATETIME DEFAULT CURRENT_TIMESTAMP,
+        last_opened DATETIME DEFAULT CURRENT_TIMESTAMP
+      );",
+      kind: MigrationKind::Up,
+    },
+    Migration {
+      version: 2,
+      description: "create_highlights_table",
+      sql: "CREATE TABLE IF NOT EXISTS highlights (
+        id INTEGER PRIMARY KEY AUTOINCREMENT,
+        pdf_id INTEGER NOT NULL,
+        highlight_id TEXT NOT NULL,
+        content_text TEXT,
+        content_image TEXT,
+        comment_text TEXT,
+        comment_emoji TEXT,
+        position_data TEXT NOT NULL,
+        page_number INTEGER NOT NULL,
+        created_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+        FOREIGN KEY (pdf_id) REFERENCES pdfs(id) ON DELETE CASCADE
+      );",
+      kind: MigrationKind::Up,
+    },
+    Migration {
+      version: 3,
+      description: "create_tags_table",
+      sql: "CREATE TABLE IF NOT EXISTS tags (
+        id INTEGER PRIMARY KEY AUTOINCREMENT,
+        name TEXT NOT NULL UNIQUE,
+        created_at DATETIME DEFAULT CURRENT_TIMESTAMP
+      );",
+      kind: MigrationKind::Up,
+    },
+    Migration {
+      version: 4,
+      description: "create_highlight_tags_table",
+      sql: "CREATE TABLE IF NOT EXISTS highlight_tags (
+        highlight_id TEXT NOT NULL,
+        tag_id INTEGER NOT NULL,
+        PRIMARY KEY (highlight_id, tag_id),
+        FOREIGN KEY (highlight_id) REFERENCES highlights(highlight_id) ON DELETE CASCADE,
+        FOREIGN KEY (tag_id) REFERENCES tags(id) ON DELETE CASCADE
+      );",
+      kind: MigrationKind::Up,
+    },
+    Migration {
+      version: 5,
+      description: "add_unique_constraint_to_highlight_id",
+      sql: "
+        -- Create new highlights table with UNIQUE constraint on highlight_id
+        CREATE TABLE highlights_new (
+          id INTEGER PRIMARY KEY AUTOINCREMENT,
+          pdf_id INTEGER NOT NULL,
+          highlight_id TEXT NOT NULL UNIQUE,
+          content_text TEXT,
+          content_image TEXT,
+          comment_text TEXT,
+          comment_emoji TEXT,
+          position_data TEXT NOT NULL,
+          page_number INTEGER NOT NULL,
+          created_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+          FOREIGN KEY (pdf_id) REFERENCES pdfs(id) ON DELETE CASCADE
+        );
+        
+        -- Copy all data from old highlights table
+        INSERT INTO highlights_new 
+        SELECT * FROM highlights;
+        
+        -- Drop old highlight_tags table (will be recreated with proper foreign key)
+        DROP TABLE IF EXISTS highlight_tags;
+        
+        -- Drop old highlights table
+        DROP TABLE highlights;
+        
+        -- Rename new table to highlights
+        ALTER TABLE highlights_new RENAME TO highlights;
+        
+        -- Recreate highlight_tags with correct foreign key referencing the UNIQUE column
+        CREATE TABLE highlight_tags (
+          highlight_id TEXT NOT NULL,
+          tag_id INTEGER NOT NULL,
+          PRIMARY KEY (highlight_id, tag_id),
+          FOREIGN KEY (highlight_id) REFERENCES highlights(highlight_id) ON DELETE CASCADE,
+          FOREIGN KEY (tag_id) REFERENCES tags(id) ON DELETE CASCADE
+        );
+      ",
+      kind: MigrationKind::Up,
+    },
+    Migration {
+      version: 6,
+      description: "create_tag_usage_history_table",
+      sql: "CREATE TABLE IF NOT EXISTS tag_usage_history (
+        id INTEGER PRIMARY KEY AUTOINCREMENT,
+        tag_id INTEGER NOT NULL,
+        highlight_id TEXT NOT NULL,
+        used_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+        FOREIGN KEY (tag_id) REFERENCES tags(id) ON DELETE CASCADE,
+        FOREIGN KEY (highlight_id) REFERENCES highlights(highlight_id) ON DELETE CASCADE
+      );
+      CREATE INDEX IF NOT EXISTS idx_tag_usage_tag_id ON tag_usage_history(tag_id);
+      CREATE INDEX IF NOT EXISTS idx_tag_usage_used_at ON tag_usage_history(used_at);",
+      kind: MigrationKind::Up,
+    },
+    Migration {
+      version: 7,
+      description: "create_highlights_fts_table",
+      sql: "
+        -- External-content FTS5 index over the searchable highlight columns
+        CREATE VIRTUAL TABLE IF NOT EXISTS highlights_fts USING fts5(
+          content_text,
+          comment_text,
+          content='highlights',
+          content_rowid='id'
+        );
+
+        -- Backfill existing highlights so they are searchable immediately
+        INSERT INTO highlights_fts(rowid, content_text, comment_text)
+        SELECT id, content_text, comment_text FROM highlights;
+
+        -- Keep the index in sync with the highlights table
+        CREATE TRIGGER IF NOT EXISTS highlights_fts_insert AFTER INSERT ON highlights BEGIN
+          INSERT INTO highlights_fts(rowid, content_text, comment_text)
+          VALUES (new.id, new.content_text, new.comment_text);
+        END;
+
+        CREATE TRIGGER IF NOT EXISTS highlights_fts_delete AFTER DELETE ON highlights BEGIN
+          INSERT INTO highlights_fts(highlights_fts, rowid, content_text, comment_text)
+          VALUES ('delete', old.id, old.content_text, old.comment_text);
+        END;
+
+        CREATE TRIGGER IF NOT EXISTS highlights_fts_update AFTER UPDATE ON highlights BEGIN
+          INSERT INTO highlights_fts(highlights_fts, rowid, content_text, comment_text)
+          VALUES ('delete', old.id, old.content_text, old.comment_text);
+          INSERT INTO highlights_fts(rowid, content_text, comment_text)
+          VALUES (new.id, new.content_text, new.comment_text);
+        END;
+      ",
+      kind: MigrationKind::Up,
+    },
+    Migration {
+      version: 8,
+      description: "add_color_to_highlights",
+      sql: "ALTER TABLE highlights ADD COLUMN color TEXT DEFAULT '#ffff00';",
+      kind: MigrationKind::Up,
+    },
+    Migration {
+      version: 9,
+      description: "add_deleted_at_to_highlights",
+      sql: "ALTER TABLE highlights ADD COLUMN deleted_at DATETIME;
+        CREATE INDEX IF NOT EXISTS idx_highlights_deleted_at ON highlights(deleted_at);",
+      kind: MigrationKind::Up,
+    },
+    Migration {
+      version: 10,
+      description: "add_sha256_to_pdfs",
+      sql: "ALTER TABLE pdfs ADD COLUMN sha256 TEXT;
+        CREATE INDEX IF NOT EXISTS idx_pdfs_sha256 ON pdfs(sha256);",
+      kind: MigrationKind::Up,
+    },
+    Migration {
+      version: 11,
+      description: "add_updated_at_to_highlights",
+      sql: "
+        -- SQLite only allows constant defaults in ADD COLUMN, so existing and
+        -- new rows get updated_at = created_at from the backfill and a trigger
+        ALTER TABLE highlights ADD COLUMN updated_at DATETIME;
+
+        UPDATE highlights SET updated_at = created_at;
+
+        CREATE TRIGGER IF NOT EXISTS highlights_updated_at_insert AFTER INSERT ON highlights
+        WHEN new.updated_at IS NULL BEGIN
+          UPDATE highlights SET updated_at = new.created_at WHERE id = new.id;
+        END;
+
+        -- Any change bumps updated_at, unless the statement sets it itself
+        CREATE TRIGGER IF NOT EXISTS highlights_updated_at_update AFTER UPDATE ON highlights
+        WHEN new.updated_at IS old.updated_at BEGIN
+          UPDATE highlights SET updated_at = CURRENT_TIMESTAMP WHERE id = new.id;
+        END;
+
+        CREATE INDEX IF NOT EXISTS idx_highlights_updated_at ON highlights(updated_at);
+      ",
+      kind: MigrationKind::Up,
+    },
+    Migration {
+      version: 12,
+      description: "add_color_to_tags",
+      sql: "ALTER TABLE tags ADD COLUMN color TEXT DEFAULT '#9e9e9e';",
+      kind: MigrationKind::Up,
+    },
+    Migration {
+      version: 13,
+      description: "create_settings_table",
+      sql: "CREATE TABLE IF NOT EXISTS settings (
+        key TEXT PRIMARY KEY,
+        value TEXT
+      );",
+      kind: MigrationKind::Up,
+    },
+    Migration {
+      version: 14,
+      description: "add_last_page_to_pdfs",
+      sql: "ALTER TABLE pdfs ADD COLUMN last_page INTEGER;",
+      kind: MigrationKind::Up,
+    },
+    Migration {
+      version: 15,
+      description: "add_name_index_to_pdfs",
+      sql: "CREATE INDEX IF NOT EXISTS idx_pdfs_name ON pdfs(name COLLATE NOCASE);",
+      kind: MigrationKind::Up,
+    },
+    Migration {
+      version: 16,
+      description: "add_metadata_to_pdfs",
+      sql: "ALTER TABLE pdfs ADD COLUMN metadata TEXT;",
+      kind: MigrationKind::Up,
+    },
+    Migration {
+      version: 17,
+      description: "create_pdf_open_events_table",
+      sql: "CREATE TABLE IF NOT EXISTS pdf_open_events (
+        id INTEGER PRIMARY KEY AUTOINCREMENT,
+        pdf_id INTEGER NOT NULL,
+        opened_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+        FOREIGN KEY (pdf_id) REFERENCES pdfs(id) ON DELETE CASCADE
+      );
+      CREATE INDEX IF NOT EXISTS idx_pdf_open_events_pdf_id ON pdf_open_events(pdf_id);
+      CREATE INDEX IF NOT EXISTS idx_pdf_open_events_opened_at ON pdf_open_events(opened_at);",
+      kind: MigrationKind::Up,
+    },
+    Migration {
+      version: 18,
+      description: "add_reading_goal_to_pdfs",
+      sql: "ALTER TABLE pdfs ADD COLUMN reading_goal INTEGER;
+        ALTER TABLE pdfs ADD COLUMN goal_deadline DATETIME;",
+      kind: MigrationKind::Up,
+    },
+    Migration {
+      version: 19,
+      description: "add_view_count_to_highlights",
+      sql: "ALTER TABLE highlights ADD COLUMN view_count INTEGER DEFAULT 0;
+        CREATE INDEX IF NOT EXISTS idx_highlights_view_count ON highlights(view_count);
+
+        -- Counting a view is not an edit, so it leaves updated_at alone
+        DROP TRIGGER IF EXISTS highlights_updated_at_update;
+        CREATE TRIGGER highlights_updated_at_update AFTER UPDATE ON highlights
+        WHEN new.updated_at IS old.updated_at AND new.view_count IS old.view_count BEGIN
+          UPDATE highlights SET updated_at = CURRENT_TIMESTAMP WHERE id = new.id;
+        END;
+
+        -- Only re-index when indexed text is written
+        DROP TRIGGER IF EXISTS highlights_fts_update;
+        CREATE TRIGGER highlights_fts_update
+        AFTER UPDATE OF content_text, comment_text ON highlights BEGIN
+          INSERT INTO highlights_fts(highlights_fts, rowid, content_text, comment_text)
+          VALUES ('delete', old.id, old.content_text, old.comment_text);
+          INSERT INTO highlights_fts(rowid, content_text, comment_text)
+          VALUES (new.id, new.content_text, new.comment_text);
+        END;",
+      kind: MigrationKind::Up,
+    },
+    Migration {
+      version: 20,
+      description: "create_action_log_table",
+      sql: "CREATE TABLE IF NOT EXISTS action_log (
+        id INTEGER PRIMARY KEY AUTOINCREMENT,
+        kind TEXT NOT NULL,
+        payload TEXT NOT NULL,
+        reversible INTEGER NOT NULL DEFAULT 1,
+        undone_at DATETIME,
+        created_at DATETIME DEFAULT CURRENT_TIMESTAMP
+      );",
+      kind: MigrationKind::Up,
+    },
+    Migration {
+      version: 21,
+      description: "add_content_image_path_to_highlights",
+      sql: "ALTER TABLE highlights ADD COLUMN content_image_path TEXT;
+
+        -- Moving an image between the database and a file is not an edit either
+        DROP TRIGGER IF EXISTS highlights_updated_at_update;
+        CREATE TRIGGER highlights_updated_at_update AFTER UPDATE ON highlights
+        WHEN new.updated_at IS old.updated_at AND new.view_count IS old.view_count
+          AND new.content_image_path IS old.content_image_path BEGIN
+          UPDATE highlights SET updated_at = CURRENT_TIMESTAMP WHERE id = new.id;
+        END;",
+      kind: MigrationKind::Up,
+    },
+    Migration {
+      version: 22,
+      description: "add_name_index_to_tags",
+      // The UNIQUE constraint's index is case-sensitive, so it can't serve the
+      // case-insensitive prefix search of list_tags
+      sql: "CREATE INDEX IF NOT EXISTS idx_tags_name ON tags(name COLLATE NOCASE);",
+      kind: MigrationKind::Up,
+    },
+    Migration {
+      version: 23,
+      description: "create_stats_snapshots_table",
+      sql: "CREATE TABLE IF NOT EXISTS stats_snapshots (
+        id INTEGER PRIMARY KEY AUTOINCREMENT,
+        taken_at DATETIME NOT NULL DEFAULT CURRENT_TIMESTAMP,
+        pdf_count INTEGER NOT NULL,
+        highlight_count INTEGER NOT NULL,
+        tag_count INTEGER NOT NULL
+      );
+
+      CREATE INDEX IF NOT EXISTS idx_stats_snapshots_taken_at ON stats_snapshots(taken_at);",
+      kind: MigrationKind::Up,
+    },
+  ]
+}
+
+/// Schema version the database has been migrated to, as recorded by sqlx.
+pub fn applied_version(conn: &rusqlite::Connection) -> rusqlite::Result<i64> {
+  conn.query_row(
+    "SELECT COALESCE(MAX(version), 0) FROM _sqlx_migrations WHERE success = 1",
+    [],
+    |row| row.get(0),
+  )
+}
+
+/// Highest schema version this build of the app knows about.
+pub fn latest_version() -> i64 {
+  all().iter().map(|m| m.version).max().unwrap_or(0)
+}
+
+/// Our migrations converted the same way tauri-plugin-sql converts them, so
+/// the version history and checksums sqlx records are identical.
+#[derive(Debug)]
+struct AppMigrations;
+
+impl MigrationSource<'static> for AppMigrations {
+  fn resolve(
+    self,
+  ) -> Pin<Box<dyn Future<Output = Result<Vec<SqlxMigration>, BoxDynError>> + Send>> {
+    Box::pin(async move {
+      Ok(
+        all()
+          .into_iter()
+          .filter(|m| matches!(m.kind, MigrationKind::Up))
+          .map(|m| {
+            SqlxMigration::new(
+              m.version,
+              m.description.into(),
+              MigrationType::ReversibleUp,
+              m.sql.into(),
+              false,
+            )
+          })
+          .collect(),
+      )
+    })
+  }
+}
+
+/// Applies any pending migrations to a database file outside the plugin's
+/// control. sqlx refuses to run if the file has migrations this build doesn't
+/// know or whose SQL differs from ours.
+pub async fn run_on_file(path: &Path) -> Result<(), String> {
+  run_on_file_with_key(path, None).await
+}
+
+/// `run_on_file` for a SQLCipher database, keyed with `passphrase` before
+/// anything else touches it.
+pub async fn run_on_file_with_key(path: &Path, passphrase: Option<&str>) -> Result<(), String> {
+  // sqlx's default, spelled out since deletes rely on the cascades
+  let mut options = SqliteConnectOptions::new()
+    .filename(path)
+    .foreign_keys(true);
+  if let Some(passphrase) = passphrase {
+    options = options.pragma("key", format!("'{}'", passphrase.replace('\'', "''")));
+  }
+  let pool = SqlitePoolOptions::new()
+    .max_connections(1)
+    .connect_with(options)
+    .await
+    .map_err(|e| e.to_string())?;
+  let migrator = Migrator::new(AppMigrations)
+    .await
+    .map_err(|e| e.to_string())?;
+  let result = migrator.run(&pool).await.map_err(|e| e.to_string());
+  pool.close().await;
+  result
+}
+
+#[derive(Debug, Serialize)]
+pub struct AppliedMigration {
+  pub version: i64,
+  pub description: String,
+  pub installed_on: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct SchemaVersion {
+  /// Highest migration applied to the live database.
+  pub version: i64,
+  /// Highest migration this build ships; the database is up to date when the
+  /// two are equal.
+  pub latest: i64,
+  pub applied: Vec<AppliedMigration>,
+}
+
+fn schema_version(conn: &rusqlite::Connection) -> rusqlite::Result<SchemaVersion> {
+  let applied = conn
+    .prepare(
+      "SELECT version, description, installed_on FROM _sqlx_migrations
+       WHERE success = 1
+       ORDER BY version",
+    )?
+    .query_map([], |row| {
+      Ok(AppliedMigration {
+        version: row.get(0)?,
+        description: row.get(1)?,
+        installed_on: row.get(2)?,
+      })
+    })?
+    .collect::<rusqlite::Result<Vec<_>>>()?;
+
+  Ok(SchemaVersion {
+    version: applied_version(conn)?,
+    latest: latest_version(),
+    applied,
+  })
+}
+
+#[tauri::command]
+pub async fn get_schema_version(app: AppHandle) -> Result<SchemaVersion, AppError> {
+  let conn = db::open(&app)?;
+  Ok(schema_version(&conn)?)
+}
+
+/// Table name -> column names of a database, leaving out SQLite's own tables.
+type Schema = BTreeMap<String, Vec<String>>;
+
+fn read_schema(conn: &rusqlite::Connection) -> rusqlite::Result<Schema> {
+  let tables = conn
+    .prepare(
+      "SELECT name FROM sqlite_master
+       WHERE type = 'table' AND name NOT LIKE 'sqlite_%' AND name != '_sqlx_migrations'",
+    )?
+    .query_map([], |row| row.get::<_, String>(0))?
+    .collect::<rusqlite::Result<Vec<_>>>()?;
+
+  let mut schema = Schema::new();
+  for table in tables {
+    let columns = conn
+      .prepare("SELECT name FROM pragma_table_info(?1) ORDER BY cid")?
+      .query_map([&table], |row| row.get::<_, String>(0))?
+      .collect::<rusqlite::Result<Vec<_>>>()?;
+    schema.insert(table, columns);
+  }
+  Ok(schema)
+}
+
+/// The schema a database should have after migrating to `version`, obtained
+/// by running the migrations on an empty in-memory database.
+fn expected_schema(version: i64) -> rusqlite::Result<Schema> {
+  let conn = rusqlite::Connection::open_in_memory()?;
+  for migration in all()
+    .iter()
+    .filter(|m| matches!(m.kind, MigrationKind::Up) && m.version <= version)
+  {
+    conn.execute_batch(migration.sql)?;
+  }
+  read_schema(&conn)
+}
+
+#[derive(Debug, Serialize)]
+pub struct MissingColumn {
+  pub table: String,
+  pub column: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct MigrationStatus {
+  /// Highest migration recorded as applied to the live database.
+  pub version: i64,
+  pub latest: i64,
+  /// Tables the recorded migrations should have created but that don't exist.
+  pub missing_tables: Vec<String>,
+  /// Columns missing from tables that do exist.
+  pub missing_columns: Vec<MissingColumn>,
+  /// True when the live schema has everything the recorded version implies.
+  pub consistent: bool,
+}
+
+fn migration_status(conn: &rusqlite::Connection) -> rusqlite::Result<MigrationStatus> {
+  let version = applied_version(conn)?;
+  let expected = expected_schema(version)?;
+  let live = read_schema(conn)?;
+
+  let mut missing_tables = Vec::new();
+  let mut missing_columns = Vec::new();
+  for (table, columns) in &expected {
+    match live.get(table) {
+      None => missing_tables.push(table.clone()),
+      Some(live_columns) => missing_columns.extend(
+        columns
+          .iter()
+          .filter(|column| !live_columns.contains(column))
+          .map(|column| MissingColumn {
+            table: table.clone(),
+            column: column.clone(),
+          }),
+      ),
+    }
+  }
+
+  Ok(MigrationStatus {
+    version,
+    latest: latest_version(),
+    consistent: missing_tables.is_empty() && missing_columns.is_empty(),
+    missing_tables,
+    missing_columns,
+  })
+}
+
+/// Compares the live schema with what the applied migrations should have
+/// produced, to diagnose a partially applied upgrade.
+#[tauri::command]
+pub async fn check_migration_integrity(app: AppHandle) -> Result<MigrationStatus, AppError> {
+  let conn = db::open(&app)?;
+  Ok(migration_status(&conn)?)
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use rusqlite::{params, Connection};
+
+  /// An empty database at the latest version.
+  fn migrated(foreign_keys: bool) -> Connection {
+    let conn = Connection::open_in_memory().unwrap();
+    conn
+      .pragma_update(None, "foreign_keys", foreign_keys)
+      .unwrap();
+    for migration in all().iter().filter(|m| matches!(m.kind, MigrationKind::Up)) {
+      conn.execute_batch(migration.sql).unwrap();
+    }
+    conn
+  }
+
+  /// Adds a PDF with a highlight tagged `tag`, and returns the PDF's id.
+  fn add_tagged_pdf(conn: &Connection, name: &str, tag: &str) -> i64 {
+    conn
+      .execute(
+        "INSERT INTO pdfs (name, path) VALUES (?1, ?2)",
+        params![name, format!("/pdfs/{}.pdf", name)],
+      )
+      .unwrap();
+    let pdf_id = conn.last_insert_rowid();
+    let highlight_id = format!("{}-highlight", name);
+    conn
+      .execute(
+        "INSERT INTO highlights (pdf_id, highlight_id, content_text, position_data, page_number)
+         VALUES (?1, ?2, 'text', '{}', 1)",
+        params![pdf_id, highlight_id],
+      )
+      .unwrap();
+    conn
+      .execute("INSERT INTO tags (name) VALUES (?1)", params![tag])
+      .unwrap();
+    let tag_id = conn.last_insert_rowid();
+    conn
+      .execute(
+        "INSERT INTO highlight_tags (highlight_id, tag_id) VALUES (?1, ?2)",
+        params![highlight_id, tag_id],
+      )
+      .unwrap();
+    conn
+      .execute(
+        "INSERT INTO tag_usage_history (tag_id, highlight_id) VALUES (?1, ?2)",
+        params![tag_id, highlight_id],
+      )
+      .unwrap();
+    pdf_id
+  }
+
+  fn count(conn: &Connection, table: &str) -> i64 {
+    conn
+      .query_row(&format!("SELECT COUNT(*) FROM {}", table), [], |row| {
+        row.get(0)
+      })
+      .unwrap()
+  }
+
+  #[test]
+  fn deleting_a_pdf_cascades_to_its_highlights_and_tags() {
+    let conn = migrated(true);
+    let pdf_id = add_tagged_pdf(&conn, "deleted", "first");
+    add_tagged_pdf(&conn, "kept", "second");
+
+    conn
+      .execute("DELETE FROM pdfs WHERE id = ?1", params![pdf_id])
+      .unwrap();
+
+    let highlights: Vec<String> = conn
+      .prepare("SELECT highlight_id FROM highlights")
+      .unwrap()
+      .query_map([], |row| row.get(0))
+      .unwrap()
+      .collect::<rusqlite::Result<_>>()
+      .unwrap();
+    assert_eq!(highlights, ["kept-highlight"]);
+    assert_eq!(count(&conn, "highlight_tags"), 1);
+    assert_eq!(count(&conn, "tag_usage_history"), 1);
+    assert_eq!(count(&conn, "tags"), 2);
+  }
+}
@@ -0,0 +1,91 @@
+//! Rewriting PDFs into a smaller file, for scans that take more space than
+//! they need. pdfium can only save documents as they are, so this uses lopdf.
+
+use lopdf::Document;
+use serde::Serialize;
+use std::fs;
+use std::path::{Path, PathBuf};
+use tauri::AppHandle;
+
+use crate::error::AppError;
+use crate::{db, pdfs};
+
+#[derive(Debug, Serialize)]
+pub struct OptimizeResult {
+  /// Size of the library's file in bytes.
+  pub original_size: u64,
+  /// Size of the file written to `dest_path` in bytes.
+  pub optimized_size: u64,
+}
+
+pub fn same_file(a: &Path, b: &Path) -> bool {
+  match (fs::canonicalize(a), fs::canonicalize(b)) {
+    (Ok(a), Ok(b)) => a == b,
+    // A path that doesn't exist yet can't be the other file
+    _ => false,
+  }
+}
+
+/// Drops unreferenced objects and empty streams, compresses every stream and
+/// packs the objects into object streams.
+fn optimize(source: &Path, dest: &Path) -> Result<(), AppError> {
+  let mut document = Document::load(source)
+    .map_err(|e| AppError::Corrupt(format!("Cannot parse {}: {}", source.display(), e)))?;
+  // Rewriting would silently drop the encryption
+  if document.is_encrypted() {
+    return Err(AppError::Encrypted(format!(
+      "{} is encrypted",
+      source.display()
+    )));
+  }
+
+  document.prune_objects();
+  document.delete_zero_length_streams();
+  document.renumber_objects();
+  document.compress();
+  document
+    .save_modern(dest)
+    .map_err(|e| AppError::Io(format!("Cannot write {}: {}", dest.display(), e)))?;
+  Ok(())
+}
+
+/// Writes a smaller copy of a PDF to `dest_path`. The library's file is
+/// never modified, so `dest_path` must be another file.
+#[tauri::command]
+pub async fn optimize_pdf(
+  app: AppHandle,
+  pdf_id: i64,
+  dest_path: String,
+) -> Result<OptimizeResult, AppError> {
+  let conn = db::open(&app)?;
+  let pdf = pdfs::get(&conn, pdf_id)?
+    .ok_or_else(|| AppError::NotFound(format!("PDF {} not found", pdf_id)))?;
+  drop(conn);
+
+  let source = PathBuf::from(&pdf.path);
+  let dest = PathBuf::from(&dest_path);
+  if same_file(&source, &dest) {
+    return Err(AppError::InvalidInput(
+      "dest_path must not be the PDF itself".to_string(),
+    ));
+  }
+  let original_size = fs::metadata(&source)?.len();
+
+  // Parsing and compressing a large scan takes a while
+  tauri::async_runtime::spawn_blocking(move || optimize(&source, &dest))
+    .await
+    .map_err(|e| format!("Optimizing failed: {}", e))??;
+  let optimized_size = fs::metadata(&dest_path)?.len();
+
+  log::info!(
+    "Optimized {} into {} ({} -> {} bytes)",
+    pdf.path,
+    dest_path,
+    original_size,
+    optimized_size
+  );
+  Ok(OptimizeResult {
+    original_size,
+    optimized_size,
+  })
+}
@@ -0,0 +1,938 @@
+use rusqlite::{params, Connection, OptionalExtension, Row};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::fs::{self, File};
+use std::io::{self, Read};
+use std::path::{Path, PathBuf};
+use std::sync::{Mutex, OnceLock};
+use std::time::SystemTime;
+use tauri::AppHandle;
+use tauri_plugin_opener::OpenerExt;
+
+use crate::actions::{self, Action};
+use crate::error::AppError;
+use crate::{db, geometry, highlights, images, render};
+
+/// A row of the `pdfs` table, mirroring `PdfRecord` in
+/// example/src/services/database.ts.
+#[derive(Debug, Clone, Serialize)]
+pub struct PdfRecord {
+  pub id: i64,
+  pub name: String,
+  pub path: String,
+  pub date_added: String,
+  pub last_opened: String,
+  pub sha256: Option<String>,
+  /// Page the document was last viewed at, 1 if it never was.
+  pub last_page: i64,
+}
+
+/// Column list matching `PdfRecord::from_row`, prefixed with `p.` so it can be
+/// used in joins.
+pub const COLUMNS: &str = "p.id, p.name, p.path, p.date_added, p.last_opened, p.sha256,
+  p.last_page";
+
+impl PdfRecord {
+  pub fn from_row(row: &Row) -> rusqlite::Result<Self> {
+    Ok(PdfRecord {
+      id: row.get(0)?,
+      name: row.get(1)?,
+      path: row.get(2)?,
+      date_added: row.get(3)?,
+      last_opened: row.get(4)?,
+      sha256: row.get(5)?,
+      last_page: row.get::<_, Option<i64>>(6)?.unwrap_or(1),
+    })
+  }
+}
+
+pub fn get(conn: &Connection, pdf_id: i64) -> rusqlite::Result<Option<PdfRecord>> {
+  conn
+    .query_row(
+      &format!("SELECT {} FROM pdfs p WHERE p.id = ?1", COLUMNS),
+      params![pdf_id],
+      PdfRecord::from_row,
+    )
+    .optional()
+}
+
+/// Hex encoded SHA-256 of a file, read in fixed-size chunks so large PDFs are
+/// never held in memory at once.
+pub fn sha256_file(path: &Path) -> io::Result<String> {
+  let mut file = File::open(path)?;
+  let mut hasher = Sha256::new();
+  let mut buf = vec![0u8; 64 * 1024];
+  loop {
+    let n = file.read(&mut buf)?;
+    if n == 0 {
+      break;
+    }
+    hasher.update(&buf[..n]);
+  }
+  Ok(format!("{:x}", hasher.finalize()))
+}
+
+/// File size and modification time a memoized hash was computed for.
+type HashStamp = (u64, SystemTime);
+
+/// Like `sha256_file`, but remembers the hash for as long as the file's size
+/// and modification time stay the same, so hot paths (render caches, page
+/// count lookups) don't re-read whole PDFs.
+pub fn sha256_cached(path: &Path) -> io::Result<String> {
+  static HASHES: OnceLock<Mutex<HashMap<PathBuf, (HashStamp, String)>>> = OnceLock::new();
+  let hashes = HASHES.get_or_init(Default::default);
+
+  let metadata = fs::metadata(path)?;
+  let stamp = (metadata.len(), metadata.modified()?);
+  if let Some((seen, hash)) = hashes.lock().unwrap().get(path) {
+    if *seen == stamp {
+      return Ok(hash.clone());
+    }
+  }
+
+  let hash = sha256_file(path)?;
+  hashes
+    .lock()
+    .unwrap()
+    .insert(path.to_path_buf(), (stamp, hash.clone()));
+  Ok(hash)
+}
+
+/// Finds the row for an already registered document: either a row with the
+/// same content hash, or a row for the same path that predates hashing.
+pub fn find_existing(conn: &Connection, path: &str, sha256: &str) -> rusqlite::Result<Option<i64>> {
+  conn
+    .query_row(
+      "SELECT id FROM pdfs
+       WHERE sha256 = ?1 OR (sha256 IS NULL AND path = ?2)
+       ORDER BY sha256 IS NULL, id
+       LIMIT 1",
+      params![sha256, path],
+      |row| row.get(0),
+    )
+    .optional()
+}
+
+/// Characters of PDFDocEncoding's bytes 0x80 to 0x9F, where it departs from
+/// Latin-1. 0x9F is undefined.
+const PDF_DOC_HIGH: [char; 32] = [
+  '\u{2022}', '\u{2020}', '\u{2021}', '\u{2026}', '\u{2014}', '\u{2013}', '\u{0192}', '\u{2044}',
+  '\u{2039}', '\u{203a}', '\u{2212}', '\u{2030}', '\u{201e}', '\u{201c}', '\u{201d}', '\u{2018}',
+  '\u{2019}', '\u{201a}', '\u{2122}', '\u{fb01}', '\u{fb02}', '\u{0141}', '\u{0152}', '\u{0160}',
+  '\u{0178}', '\u{017d}', '\u{0131}', '\u{0142}', '\u{0153}', '\u{0161}', '\u{017e}', '\u{fffd}',
+];
+
+/// Decodes a PDF text string: UTF-16BE or UTF-8 after a byte order mark,
+/// PDFDocEncoding otherwise.
+fn decode_text(bytes: &[u8]) -> String {
+  if let Some(utf16) = bytes.strip_prefix(&[0xfe, 0xff]) {
+    let units: Vec<u16> = utf16
+      .chunks_exact(2)
+      .map(|pair| u16::from_be_bytes([pair[0], pair[1]]))
+      .collect();
+    return String::from_utf16_lossy(&units);
+  }
+  if let Some(utf8) = bytes.strip_prefix(&[0xef, 0xbb, 0xbf]) {
+    return String::from_utf8_lossy(utf8).into_owned();
+  }
+  bytes
+    .iter()
+    .map(|&byte| match byte {
+      0x80..=0x9f => PDF_DOC_HIGH[usize::from(byte - 0x80)],
+      0xad => '\u{fffd}',
+      byte => char::from(byte),
+    })
+    .collect()
+}
+
+/// Cleans up an Info dictionary string, which is often padded, NUL
+/// terminated or mis-decoded. Unusable values become `None`.
+fn info_text(value: &str) -> Option<String> {
+  let value: String = value
+    .chars()
+    .filter(|c| !c.is_control() || c.is_whitespace())
+    .collect();
+  let value = value.trim();
+  if value.is_empty() || value.contains('\u{fffd}') {
+    return None;
+  }
+  Some(value.to_string())
+}
+
+/// Title, author, subject and keywords from the PDF's Info dictionary, keyed
+/// in lowercase. Fields that are missing or unreadable are left out.
+pub fn document_info(path: &Path) -> Result<serde_json::Map<String, serde_json::Value>, AppError> {
+  let document = lopdf::Document::load(path)
+    .map_err(|e| AppError::Corrupt(format!("Cannot parse {}: {}", path.display(), e)))?;
+  // The strings of an encrypted document are encrypted too
+  if document.is_encrypted() {
+    return Err(AppError::Encrypted(format!(
+      "{} is encrypted",
+      path.display()
+    )));
+  }
+
+  let mut info = serde_json::Map::new();
+  let dictionary = document
+    .trailer
+    .get(b"Info")
+    .ok()
+    .and_then(|object| geometry::resolve(&document, object).as_dict().ok());
+  let Some(dictionary) = dictionary else {
+    return Ok(info);
+  };
+  for (key, field) in [
+    ("title", &b"Title"[..]),
+    ("author", b"Author"),
+    ("subject", b"Subject"),
+    ("keywords", b"Keywords"),
+  ] {
+    let value = dictionary
+      .get(field)
+      .ok()
+      .and_then(|value| geometry::resolve(&document, value).as_str().ok())
+      .and_then(|bytes| info_text(&decode_text(bytes)));
+    if let Some(value) = value {
+      info.insert(key.to_string(), value.into());
+    }
+  }
+  Ok(info)
+}
+
+/// Registers a document, or refreshes the row it already has. `info` is what
+/// `document_info` read from the file: its title names new rows,
+/// which are otherwise named after the file, and it becomes the metadata of
+/// rows that have none yet.
+pub fn register(
+  conn: &Connection,
+  path: &str,
+  sha256: &str,
+  info: &serde_json::Map<String, serde_json::Value>,
+) -> rusqlite::Result<i64> {
+  let metadata = (!info.is_empty()).then(|| serde_json::Value::Object(info.clone()).to_string());
+  if let Some(id) = find_existing(conn, path, sha256)? {
+    conn.execute(
+      "UPDATE pdfs SET last_opened = datetime('now'), sha256 = ?1,
+         metadata = COALESCE(metadata, ?2)
+       WHERE id = ?3",
+      params![sha256, metadata, id],
+    )?;
+    return Ok(id);
+  }
+  let name = match info.get("title").and_then(|title| title.as_str()) {
+    Some(title) => title.to_string(),
+    None => Path::new(path)
+      .file_name()
+      .map(|name| name.to_string_lossy().into_owned())
+      .unwrap_or_else(|| path.to_string()),
+  };
+  conn.execute(
+    "INSERT INTO pdfs (name, path, date_added, last_opened, sha256, metadata)
+     VALUES (?1, ?2, datetime('now'), datetime('now'), ?3, ?4)",
+    params![name, path, sha256, metadata],
+  )?;
+  Ok(conn.last_insert_rowid())
+}
+
+/// Adds a PDF to the library, or returns the existing record if the same
+/// document (by content) was registered before, possibly under another name.
+/// New records are named after the document's title, or its file name if it
+/// has none.
+#[tauri::command]
+pub async fn register_pdf(app: AppHandle, path: String) -> Result<PdfRecord, AppError> {
+  let sha256 = sha256_file(Path::new(&path))
+    .map_err(|e| AppError::Io(format!("Cannot read PDF {}: {}", path, e)))?;
+  // Registering doesn't need the metadata to be readable
+  let info = document_info(Path::new(&path)).unwrap_or_else(|e| {
+    log::warn!("Cannot read the metadata of {}: {}", path, e);
+    Default::default()
+  });
+  let conn = db::open(&app)?;
+  let id = register(&conn, &path, &sha256, &info)?;
+  get(&conn, id)?.ok_or_else(|| AppError::NotFound(format!("PDF {} not found", id)))
+}
+
+/// What `validate_pdf_file` learned about a file.
+#[derive(Debug, Serialize)]
+pub struct PdfInfo {
+  pub page_count: u32,
+  pub sha256: String,
+  /// File size in bytes.
+  pub size: u64,
+  /// Whether the document is encrypted; only ones that open without a
+  /// password are accepted, e.g. with just printing or copying restricted.
+  pub encrypted: bool,
+}
+
+/// Every PDF starts with this, give or take some leading junk readers skip.
+const PDF_MAGIC: &[u8] = b"%PDF-";
+
+/// How far into a file `PDF_MAGIC` is looked for, as Acrobat does.
+const MAGIC_SEARCH_LEN: u64 = 1024;
+
+fn has_pdf_magic(path: &Path) -> io::Result<bool> {
+  let mut head = Vec::new();
+  File::open(path)?
+    .take(MAGIC_SEARCH_LEN)
+    .read_to_end(&mut head)?;
+  Ok(
+    head
+      .windows(PDF_MAGIC.len())
+      .any(|window| window == PDF_MAGIC),
+  )
+}
+
+/// Checks a file before `register_pdf` without adding it to the library, so
+/// the viewer can reject a bad pick right away. A missing file yields
+/// `AppError::NotFound`, a file that isn't a PDF `AppError::InvalidInput`,
+/// a password-protected one `AppError::Encrypted` and a damaged one
+/// `AppError::Corrupt`.
+#[tauri::command]
+pub async fn validate_pdf_file(app: AppHandle, path: String) -> Result<PdfInfo, AppError> {
+  let file = Path::new(&path);
+  let metadata = match fs::metadata(file) {
+    Ok(metadata) => metadata,
+    Err(e) if e.kind() == io::ErrorKind::NotFound => {
+      return Err(AppError::NotFound(format!("{} does not exist", path)));
+    }
+    Err(e) => return Err(AppError::Io(format!("Cannot read {}: {}", path, e))),
+  };
+  if !metadata.is_file() {
+    return Err(AppError::InvalidInput(format!("{} is not a file", path)));
+  }
+  let is_pdf =
+    has_pdf_magic(file).map_err(|e| AppError::Io(format!("Cannot read {}: {}", path, e)))?;
+  if !is_pdf {
+    return Err(AppError::InvalidInput(format!("{} is not a PDF", path)));
+  }
+
+  let (page_count, encrypted) = render::inspect(&app, &path)?;
+  let sha256 =
+    sha256_file(file).map_err(|e| AppError::Io(format!("Cannot read {}: {}", path, e)))?;
+  Ok(PdfInfo {
+    page_count,
+    sha256,
+    size: metadata.len(),
+    encrypted,
+  })
+}
+
+#[derive(Debug, Serialize)]
+pub struct PdfHealth {
+  pub id: i64,
+  pub path: String,
+  pub exists: bool,
+  /// File size in bytes, when the file exists.
+  pub size: Option<u64>,
+}
+
+/// Stats every library file so the UI can flag entries whose PDF has moved or
+/// been deleted. File contents are never read.
+#[tauri::command]
+pub async fn verify_pdf_paths(app: AppHandle) -> Result<Vec<PdfHealth>, AppError> {
+  let conn = db::open(&app)?;
+  let mut stmt = conn.prepare("SELECT id, path FROM pdfs ORDER BY id")?;
+  let rows = stmt
+    .query_map([], |row| {
+      Ok((row.get::<_, i64>(0)?, row.get::<_, String>(1)?))
+    })?
+    .collect::<rusqlite::Result<Vec<_>>>()?;
+
+  Ok(
+    rows
+      .into_iter()
+      .map(|(id, path)| {
+        let size = fs::metadata(&path)
+          .ok()
+          .filter(|metadata| metadata.is_file())
+          .map(|metadata| metadata.len());
+        PdfHealth {
+          id,
+          path,
+          exists: size.is_some(),
+          size,
+        }
+      })
+      .collect(),
+  )
+}
+
+/// Points a library entry at the file's new location. When the entry has a
+/// stored hash the new file must have the same content, so highlights can't
+/// end up attached to a different document; otherwise the hash is recorded
+/// now.
+#[tauri::command]
+pub async fn relink_pdf(app: AppHandle, pdf_id: i64, new_path: String) -> Result<(), AppError> {
+  let conn = db::open(&app)?;
+  let pdf =
+    get(&conn, pdf_id)?.ok_or_else(|| AppError::NotFound(format!("PDF {} not found", pdf_id)))?;
+
+  let sha256 = sha256_file(Path::new(&new_path))
+    .map_err(|e| AppError::Io(format!("Cannot read PDF {}: {}", new_path, e)))?;
+  if let Some(stored) = &pdf.sha256 {
+    if *stored != sha256 {
+      return Err(AppError::Conflict(format!(
+        "{} is not the same document as \"{}\"",
+        new_path, pdf.name
+      )));
+    }
+  }
+
+  conn.execute(
+    "UPDATE pdfs SET path = ?1, sha256 = ?2 WHERE id = ?3",
+    params![new_path, sha256, pdf_id],
+  )?;
+  Ok(())
+}
+
+#[derive(Debug, Serialize)]
+pub struct RecentPdf {
+  #[serde(flatten)]
+  pub pdf: PdfRecord,
+  /// Highlights of the PDF, not counting the trash.
+  pub highlight_count: i64,
+}
+
+fn recent(conn: &Connection, limit: i64) -> rusqlite::Result<Vec<RecentPdf>> {
+  let mut stmt = conn.prepare(&format!(
+    "SELECT {}, COUNT(h.id)
+     FROM pdfs p
+     LEFT JOIN highlights h ON h.pdf_id = p.id AND h.deleted_at IS NULL
+     GROUP BY p.id
+     ORDER BY p.last_opened DESC, p.id DESC
+     LIMIT ?1",
+    COLUMNS
+  ))?;
+  let rows = stmt.query_map(params![limit], |row| {
+    Ok(RecentPdf {
+      pdf: PdfRecord::from_row(row)?,
+      highlight_count: row.get(7)?,
+    })
+  })?;
+  rows.collect()
+}
+
+/// The `limit` most recently opened PDFs with their highlight counts.
+#[tauri::command]
+pub async fn list_recent_pdfs(app: AppHandle, limit: i64) -> Result<Vec<RecentPdf>, AppError> {
+  if limit < 1 {
+    return Err(AppError::InvalidInput(
+      "limit must be 1 or greater".to_string(),
+    ));
+  }
+  let conn = db::open(&app)?;
+  Ok(recent(&conn, limit)?)
+}
+
+/// Records that a PDF was opened just now, both as its `last_opened` and in
+/// the open history. Returns false if there is no such PDF.
+fn record_open(conn: &Connection, pdf_id: i64) -> rusqlite::Result<bool> {
+  let updated = conn.execute(
+    "UPDATE pdfs SET last_opened = CURRENT_TIMESTAMP WHERE id = ?1",
+    params![pdf_id],
+  )?;
+  if updated == 0 {
+    return Ok(false);
+  }
+  conn.execute(
+    "INSERT INTO pdf_open_events (pdf_id) VALUES (?1)",
+    params![pdf_id],
+  )?;
+  Ok(true)
+}
+
+/// Marks a PDF as opened just now, moving it to the top of the recent list
+/// and adding an entry to its open history.
+#[tauri::command]
+pub async fn touch_pdf(app: AppHandle, pdf_id: i64) -> Result<(), AppError> {
+  let mut conn = db::open(&app)?;
+  let tx = conn.transaction()?;
+  if !record_open(&tx, pdf_id)? {
+    return Err(AppError::NotFound(format!("PDF {} not found", pdf_id)));
+  }
+  tx.commit()?;
+  Ok(())
+}
+
+/// Remembers the page a document is viewed at, so it reopens there. The page
+/// is clamped to the document's page count when the file can be read, and the
+/// stored page is returned.
+#[tauri::command]
+pub async fn set_last_page(app: AppHandle, pdf_id: i64, page: i64) -> Result<i64, AppError> {
+  if page < 1 {
+    return Err(AppError::InvalidInput(
+      "page must be 1 or greater".to_string(),
+    ));
+  }
+  let conn = db::open(&app)?;
+  let pdf =
+    get(&conn, pdf_id)?.ok_or_else(|| AppError::NotFound(format!("PDF {} not found", pdf_id)))?;
+
+  let page = match render::page_count(&app, &pdf.path) {
+    Ok(count) => page.min(i64::from(count.max(1))),
+    Err(e) => {
+      log::warn!("Cannot count pages of {}: {}", pdf.path, e);
+      page
+    }
+  };
+  conn.execute(
+    "UPDATE pdfs SET last_page = ?1 WHERE id = ?2",
+    params![page, pdf_id],
+  )?;
+  Ok(page)
+}
+
+/// Opens a PDF in the system's default viewer, as a fallback when the
+/// built-in viewer can't render it. Counts as opening the document.
+#[tauri::command]
+pub async fn open_external(app: AppHandle, pdf_id: i64) -> Result<(), AppError> {
+  let mut conn = db::open(&app)?;
+  let pdf =
+    get(&conn, pdf_id)?.ok_or_else(|| AppError::NotFound(format!("PDF {} not found", pdf_id)))?;
+  if !Path::new(&pdf.path).is_file() {
+    return Err(AppError::NotFound(format!(
+      "\"{}\" is no longer at {}; relink it to open it",
+      pdf.name, pdf.path
+    )));
+  }
+
+  app
+    .opener()
+    .open_path(&pdf.path, None::<&str>)
+    .map_err(|e| AppError::Failed(format!("Cannot open {}: {}", pdf.path, e)))?;
+  let tx = conn.transaction()?;
+  record_open(&tx, pdf_id)?;
+  tx.commit()?;
+  Ok(())
+}
+
+#[derive(Debug, Clone, Copy, Deserialize)]
+pub enum PdfSort {
+  /// Alphabetical, ignoring case.
+  Name,
+  /// Most recently added first.
+  DateAdded,
+  /// Most recently opened first.
+  LastOpened,
+}
+
+impl PdfSort {
+  fn order_by(self) -> &'static str {
+    match self {
+      PdfSort::Name => "p.name COLLATE NOCASE, p.id",
+      PdfSort::DateAdded => "p.date_added DESC, p.id DESC",
+      PdfSort::LastOpened => "p.last_opened DESC, p.id DESC",
+    }
+  }
+}
+
+#[derive(Debug, Serialize)]
+pub struct PdfPage {
+  pub pdfs: Vec<PdfRecord>,
+  /// Number of PDFs matching the query across all pages of the listing.
+  pub total: i64,
+}
+
+/// Escapes `%`, `_` and the escape character itself for a `LIKE ... ESCAPE '\'`
+/// pattern, so they match literally.
+pub fn escape_like(text: &str) -> String {
+  let mut escaped = String::with_capacity(text.len());
+  for c in text.chars() {
+    if matches!(c, '%' | '_' | '\\') {
+      escaped.push('\\');
+    }
+    escaped.push(c);
+  }
+  escaped
+}
+
+/// A `LIKE` pattern matching names that contain `query`.
+fn like_pattern(query: &str) -> String {
+  format!("%{}%", escape_like(query))
+}
+
+fn list_page(
+  conn: &Connection,
+  query: Option<&str>,
+  sort: PdfSort,
+  limit: i64,
+  offset: i64,
+) -> rusqlite::Result<PdfPage> {
+  let pattern = query.map(like_pattern);
+  // A leading wildcard can't seek the name index, but scanning the index is
+  // still cheaper than scanning the table.
+  let filter = "?1 IS NULL OR p.name LIKE ?1 ESCAPE '\\'";
+  let total = conn.query_row(
+    &format!("SELECT COUNT(*) FROM pdfs p WHERE {}", filter),
+    params![pattern],
+    |row| row.get(0),
+  )?;
+  let mut stmt = conn.prepare(&format!(
+    "SELECT {} FROM pdfs p
+     WHERE {}
+     ORDER BY {}
+     LIMIT ?2 OFFSET ?3",
+    COLUMNS,
+    filter,
+    sort.order_by()
+  ))?;
+  let pdfs = stmt
+    .query_map(params![pattern, limit, offset], PdfRecord::from_row)?
+    .collect::<rusqlite::Result<Vec<_>>>()?;
+  Ok(PdfPage { pdfs, total })
+}
+
+/// One page of the library, optionally narrowed to PDFs whose name contains
+/// `query` (case-insensitive).
+#[tauri::command]
+pub async fn list_pdfs(
+  app: AppHandle,
+  query: Option<String>,
+  sort: PdfSort,
+  limit: i64,
+  offset: i64,
+) -> Result<PdfPage, AppError> {
+  if limit < 1 {
+    return Err(AppError::InvalidInput(
+      "limit must be 1 or greater".to_string(),
+    ));
+  }
+  if offset < 0 {
+    return Err(AppError::InvalidInput(
+      "offset must not be negative".to_string(),
+    ));
+  }
+  let query = query.as_deref().map(str::trim).filter(|q| !q.is_empty());
+  let conn = db::open(&app)?;
+  Ok(list_page(&conn, query, sort, limit, offset)?)
+}
+
+/// Folds `source_pdf_id` into `target_pdf_id` for libraries where the same
+/// document was imported twice: every highlight of the source, trashed ones
+/// included, moves to the target and the source row is deleted. Highlights
+/// keep their ids, so their tag links and tag history stay attached. Returns
+/// the number of highlights moved.
+#[tauri::command]
+pub async fn merge_pdfs(
+  app: AppHandle,
+  source_pdf_id: i64,
+  target_pdf_id: i64,
+) -> Result<usize, AppError> {
+  if source_pdf_id == target_pdf_id {
+    return Err(AppError::InvalidInput(
+      "Cannot merge a PDF into itself".to_string(),
+    ));
+  }
+
+  let mut conn = db::open(&app)?;
+  let tx = conn.transaction()?;
+  let source = get(&tx, source_pdf_id)?
+    .ok_or_else(|| AppError::NotFound(format!("PDF {} not found", source_pdf_id)))?;
+  if get(&tx, target_pdf_id)?.is_none() {
+    return Err(AppError::NotFound(format!(
+      "PDF {} not found",
+      target_pdf_id
+    )));
+  }
+
+  let moved = tx.execute(
+    "UPDATE highlights SET pdf_id = ?1 WHERE pdf_id = ?2",
+    params![target_pdf_id, source_pdf_id],
+  )?;
+  // Keep the merged entry's place in the recent list, and the source's
+  // metadata if the target has none.
+  tx.execute(
+    "UPDATE pdfs SET last_opened = MAX(last_opened, ?1),
+       metadata = COALESCE(metadata, (SELECT metadata FROM pdfs WHERE id = ?3))
+     WHERE id = ?2",
+    params![source.last_opened, target_pdf_id, source_pdf_id],
+  )?;
+  tx.execute(
+    "UPDATE pdf_open_events SET pdf_id = ?1 WHERE pdf_id = ?2",
+    params![target_pdf_id, source_pdf_id],
+  )?;
+  // Everything referencing the source has moved, so deleting it orphans nothing.
+  tx.execute("DELETE FROM pdfs WHERE id = ?1", params![source_pdf_id])?;
+  actions::record(
+    &tx,
+    &Action::MergePdfs {
+      source_pdf_id,
+      target_pdf_id,
+      moved,
+    },
+  )?;
+  tx.commit()?;
+
+  log::info!(
+    "Merged PDF {} into {} ({} highlights moved)",
+    source_pdf_id,
+    target_pdf_id,
+    moved
+  );
+  Ok(moved)
+}
+
+/// Stores free-form metadata for a PDF, e.g. author, year or DOI, replacing
+/// what was stored before. Must be a JSON object.
+#[tauri::command]
+pub async fn set_pdf_metadata(
+  app: AppHandle,
+  pdf_id: i64,
+  metadata: serde_json::Value,
+) -> Result<(), AppError> {
+  if !metadata.is_object() {
+    return Err(AppError::InvalidInput(
+      "metadata must be a JSON object".to_string(),
+    ));
+  }
+  let conn = db::open(&app)?;
+  let updated = conn.execute(
+    "UPDATE pdfs SET metadata = ?1 WHERE id = ?2",
+    params![metadata.to_string(), pdf_id],
+  )?;
+  if updated == 0 {
+    return Err(AppError::NotFound(format!("PDF {} not found", pdf_id)));
+  }
+  Ok(())
+}
+
+/// Metadata stored by `set_pdf_metadata`, or an empty object if there is none.
+#[tauri::command]
+pub async fn get_pdf_metadata(app: AppHandle, pdf_id: i64) -> Result<serde_json::Value, AppError> {
+  let conn = db::open(&app)?;
+  let metadata: Option<String> = conn
+    .query_row(
+      "SELECT metadata FROM pdfs WHERE id = ?1",
+      params![pdf_id],
+      |row| row.get(0),
+    )
+    .optional()?
+    .ok_or_else(|| AppError::NotFound(format!("PDF {} not found", pdf_id)))?;
+  match metadata {
+    Some(metadata) => serde_json::from_str(&metadata)
+      .map_err(|e| AppError::Corrupt(format!("PDF {} has unreadable metadata: {}", pdf_id, e))),
+    None => Ok(serde_json::json!({})),
+  }
+}
+
+fn search(conn: &Connection, query: &str) -> rusqlite::Result<Vec<PdfRecord>> {
+  let query = query.trim();
+  if query.is_empty() {
+    return Ok(Vec::new());
+  }
+  // CASE keeps json_extract away from metadata that isn't valid JSON, which
+  // would fail the whole query.
+  let mut stmt = conn.prepare(&format!(
+    "SELECT {} FROM pdfs p
+     WHERE p.name LIKE ?1 ESCAPE '\\'
+        OR CASE WHEN json_valid(p.metadata) THEN
+             json_extract(p.metadata, '$.title') LIKE ?1 ESCAPE '\\'
+             OR json_extract(p.metadata, '$.author') LIKE ?1 ESCAPE '\\'
+           END
+     ORDER BY p.name LIKE ?1 ESCAPE '\\' DESC, p.name COLLATE NOCASE, p.id",
+    COLUMNS
+  ))?;
+  let rows = stmt.query_map(params![like_pattern(query)], PdfRecord::from_row)?;
+  rows.collect()
+}
+
+/// PDFs whose name, or title or author in their metadata, contains `query`
+/// (case-insensitive). Name matches come first.
+#[tauri::command]
+pub async fn search_pdfs(app: AppHandle, query: String) -> Result<Vec<PdfRecord>, AppError> {
+  let conn = db::open(&app)?;
+  Ok(search(&conn, &query)?)
+}
+
+#[derive(Debug, Serialize)]
+pub struct OpenEvent {
+  pub id: i64,
+  pub pdf_id: i64,
+  pub opened_at: String,
+}
+
+fn open_history(
+  conn: &Connection,
+  pdf_id: Option<i64>,
+  limit: i64,
+) -> rusqlite::Result<Vec<OpenEvent>> {
+  let mut stmt = conn.prepare(
+    "SELECT id, pdf_id, opened_at FROM pdf_open_events
+     WHERE ?1 IS NULL OR pdf_id = ?1
+     ORDER BY opened_at DESC, id DESC
+     LIMIT ?2",
+  )?;
+  let rows = stmt.query_map(params![pdf_id, limit], |row| {
+    Ok(OpenEvent {
+      id: row.get(0)?,
+      pdf_id: row.get(1)?,
+      opened_at: row.get(2)?,
+    })
+  })?;
+  rows.collect()
+}
+
+/// The `limit` most recent times a PDF, or any PDF if `pdf_id` is `None`,
+/// was opened, newest first.
+#[tauri::command]
+pub async fn get_open_history(
+  app: AppHandle,
+  pdf_id: Option<i64>,
+  limit: i64,
+) -> Result<Vec<OpenEvent>, AppError> {
+  if limit < 1 {
+    return Err(AppError::InvalidInput(
+      "limit must be 1 or greater".to_string(),
+    ));
+  }
+  let conn = db::open(&app)?;
+  Ok(open_history(&conn, pdf_id, limit)?)
+}
+
+/// Deletes open history older than `older_than_days` days and returns how
+/// many entries were removed. `last_opened` is not affected.
+#[tauri::command]
+pub async fn prune_open_history(app: AppHandle, older_than_days: i64) -> Result<usize, AppError> {
+  if older_than_days < 0 {
+    return Err(AppError::InvalidInput(
+      "older_than_days must not be negative".to_string(),
+    ));
+  }
+  let conn = db::open(&app)?;
+  let pruned = conn.execute(
+    "DELETE FROM pdf_open_events WHERE opened_at <= datetime('now', ?1)",
+    params![format!("-{} days", older_than_days)],
+  )?;
+  log::info!("Pruned {} entries from the open history", pruned);
+  Ok(pruned)
+}
+
+fn without_highlights(
+  conn: &Connection,
+  older_than_days: Option<i64>,
+) -> rusqlite::Result<Vec<PdfRecord>> {
+  let cutoff = older_than_days.map(|days| format!("-{} days", days));
+  let mut stmt = conn.prepare(&format!(
+    "SELECT {} FROM pdfs p
+     LEFT JOIN highlights h ON h.pdf_id = p.id
+     WHERE h.id IS NULL
+       AND (?1 IS NULL OR p.date_added <= datetime('now', ?1))
+     ORDER BY p.date_added, p.id",
+    COLUMNS
+  ))?;
+  let rows = stmt.query_map(params![cutoff], PdfRecord::from_row)?;
+  rows.collect()
+}
+
+/// PDFs without a single highlight, oldest first, for decluttering the
+/// library. Highlights in the trash count, since they can still be restored.
+/// With `older_than_days`, only PDFs added at least that long ago are listed.
+#[tauri::command]
+pub async fn list_pdfs_without_highlights(
+  app: AppHandle,
+  older_than_days: Option<i64>,
+) -> Result<Vec<PdfRecord>, AppError> {
+  if older_than_days.is_some_and(|days| days < 0) {
+    return Err(AppError::InvalidInput(
+      "older_than_days must not be negative".to_string(),
+    ));
+  }
+  let conn = db::open(&app)?;
+  Ok(without_highlights(&conn, older_than_days)?)
+}
+
+#[derive(Debug, Serialize)]
+pub struct DeleteReport {
+  /// Highlights deleted, trashed ones included.
+  pub highlights_deleted: usize,
+  /// Tag links of those highlights that were removed.
+  pub tags_unlinked: usize,
+  pub file_deleted: bool,
+  /// Why the file was kept although `delete_file` was set.
+  pub file_error: Option<String>,
+}
+
+/// Deletes the file of a PDF that was just removed from the library, unless
+/// another library entry still points at it.
+fn remove_pdf_file(conn: &Connection, path: &str) -> Result<(), String> {
+  let shared: bool = conn
+    .query_row(
+      "SELECT EXISTS(SELECT 1 FROM pdfs WHERE path = ?1)",
+      params![path],
+      |row| row.get(0),
+    )
+    .map_err(|e| e.to_string())?;
+  if shared {
+    return Err(format!("{} is still used by another library entry", path));
+  }
+  fs::remove_file(path).map_err(|e| format!("Cannot delete {}: {}", path, e))
+}
+
+/// Removes a PDF from the library along with its highlights, their tag links
+/// and its open history, and with `delete_file` its file too. The file is
+/// only deleted once the library change is committed; failing to delete it
+/// is reported in `file_error` rather than failing the command.
+#[tauri::command]
+pub async fn delete_pdf(
+  app: AppHandle,
+  pdf_id: i64,
+  delete_file: bool,
+) -> Result<DeleteReport, AppError> {
+  let mut conn = db::open(&app)?;
+  let tx = conn.transaction()?;
+  let pdf =
+    get(&tx, pdf_id)?.ok_or_else(|| AppError::NotFound(format!("PDF {} not found", pdf_id)))?;
+
+  // Without the file there is nothing to restore the entry to
+  let action = if delete_file {
+    Action::DeletePdfAndFile {
+      pdf_id,
+      name: pdf.name.clone(),
+    }
+  } else {
+    let (row, open_events) = actions::snapshot_pdf(&tx, pdf_id)?;
+    Action::DeletePdf {
+      pdf: row,
+      open_events,
+      snapshot: actions::snapshot_highlights(&tx, pdf_id)?,
+    }
+  };
+  let tags_unlinked: i64 = tx.query_row(
+    "SELECT COUNT(*) FROM highlight_tags
+     WHERE highlight_id IN (SELECT highlight_id FROM highlights WHERE pdf_id = ?1)",
+    params![pdf_id],
+    |row| row.get(0),
+  )?;
+  let image_files = images::files_where(&tx, "pdf_id = ?1", params![pdf_id])?;
+  let highlights_deleted = highlights::delete_for_pdf(&tx, pdf_id)?;
+  tx.execute(
+    "DELETE FROM pdf_open_events WHERE pdf_id = ?1",
+    params![pdf_id],
+  )?;
+  tx.execute("DELETE FROM pdfs WHERE id = ?1", params![pdf_id])?;
+  actions::record(&tx, &action)?;
+  tx.commit()?;
+  images::remove_files(&image_files);
+
+  let file_error = if delete_file {
+    remove_pdf_file(&conn, &pdf.path).err()
+  } else {
+    None
+  };
+  if let Some(e) = &file_error {
+    log::warn!("Kept the file of deleted PDF {}: {}", pdf_id, e);
+  }
+
+  log::info!(
+    "Deleted PDF {} with {} highlights",
+    pdf_id,
+    highlights_deleted
+  );
+  Ok(DeleteReport {
+    highlights_deleted,
+    tags_unlinked: tags_unlinked as usize,
+    file_deleted: delete_file && file_error.is_none(),
+    file_error,
+  })
+}
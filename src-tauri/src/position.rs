@@ -0,0 +1,304 @@
+use lru::LruCache;
+use serde::{Deserialize, Serialize};
+use std::num::NonZeroUsize;
+use std::sync::{Mutex, OnceLock};
+
+// Mirrors of the `Scaled`/`ScaledPosition` interfaces in src/types.ts, which
+// is the shape the frontend stores as JSON in `highlights.position_data`.
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Scaled {
+  pub x1: f64,
+  pub y1: f64,
+  pub x2: f64,
+  pub y2: f64,
+  pub width: f64,
+  pub height: f64,
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub page_number: Option<i64>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ScaledPosition {
+  pub bounding_rect: Scaled,
+  pub rects: Vec<Scaled>,
+  pub page_number: i64,
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub use_pdf_coordinates: Option<bool>,
+}
+
+pub fn parse(position_data: &str) -> serde_json::Result<ScaledPosition> {
+  serde_json::from_str(position_data)
+}
+
+/// Number of highlights whose parsed position `parse_cached` keeps.
+const CACHE_CAPACITY: usize = 4096;
+
+type PositionCache = LruCache<String, (String, ScaledPosition)>;
+
+fn cache() -> &'static Mutex<PositionCache> {
+  static CACHE: OnceLock<Mutex<PositionCache>> = OnceLock::new();
+  CACHE.get_or_init(|| Mutex::new(LruCache::new(NonZeroUsize::new(CACHE_CAPACITY).unwrap())))
+}
+
+/// Like `parse`, but remembers the result by `highlight_id` for paths that
+/// parse the same highlights over and over. An entry is only used while the
+/// stored `position_data` is the one it was parsed from, so updating a
+/// highlight in any way invalidates it; entries of deleted highlights are
+/// never asked for again and get evicted.
+pub fn parse_cached(highlight_id: &str, position_data: &str) -> serde_json::Result<ScaledPosition> {
+  if let Some((source, position)) = cache().lock().unwrap().get(highlight_id) {
+    if source == position_data {
+      return Ok(position.clone());
+    }
+  }
+  let position = parse(position_data)?;
+  cache().lock().unwrap().put(
+    highlight_id.to_string(),
+    (position_data.to_string(), position.clone()),
+  );
+  Ok(position)
+}
+
+/// Empties the `parse_cached` cache.
+pub fn clear_cache() {
+  cache().lock().unwrap().clear();
+}
+
+fn validate_rect(field: &str, rect: &Scaled, page_number: i64) -> Result<(), String> {
+  let coords = [
+    ("x1", rect.x1),
+    ("y1", rect.y1),
+    ("x2", rect.x2),
+    ("y2", rect.y2),
+    ("width", rect.width),
+    ("height", rect.height),
+  ];
+  for (name, value) in coords {
+    if !value.is_finite() {
+      return Err(format!("{}.{} must be a finite number", field, name));
+    }
+    if value < 0.0 {
+      return Err(format!("{}.{} must not be negative", field, name));
+    }
+  }
+  if rect.width == 0.0 || rect.height == 0.0 {
+    return Err(format!("{} must have a non-zero width and height", field));
+  }
+  if rect.x2 < rect.x1 || rect.y2 < rect.y1 {
+    return Err(format!("{} has its corners swapped", field));
+  }
+  if let Some(rect_page) = rect.page_number {
+    if rect_page != page_number {
+      return Err(format!(
+        "{}.pageNumber is {} but the highlight is on page {}",
+        field, rect_page, page_number
+      ));
+    }
+  }
+  Ok(())
+}
+
+/// Parses and checks `position_data` from the frontend. Errors name the
+/// offending field in the frontend's camelCase, e.g. `rects[2].width`.
+/// `page_count` is checked against when known.
+pub fn validate(position_data: &str, page_count: Option<u32>) -> Result<ScaledPosition, String> {
+  let position = parse(position_data).map_err(|e| format!("Invalid position data: {}", e))?;
+
+  if position.page_number < 1 {
+    return Err("pageNumber must be 1 or greater".to_string());
+  }
+  if let Some(count) = page_count {
+    if position.page_number > i64::from(count) {
+      return Err(format!(
+        "pageNumber {} is out of range (document has {} pages)",
+        position.page_number, count
+      ));
+    }
+  }
+  validate_rect(
+    "boundingRect",
+    &position.bounding_rect,
+    position.page_number,
+  )?;
+  for (i, rect) in position.rects.iter().enumerate() {
+    validate_rect(&format!("rects[{}]", i), rect, position.page_number)?;
+  }
+  Ok(position)
+}
+
+/// Vertical offset of a highlight within its page, used for reading order.
+/// Unparseable positions sort to the top of the page.
+pub fn top(highlight_id: &str, position_data: &str) -> f64 {
+  parse_cached(highlight_id, position_data)
+    .map(|p| p.bounding_rect.y1)
+    .unwrap_or(0.0)
+}
+
+/// Rewrites `position_data` so the highlight and all of its rects belong to
+/// `page_number`. Coordinates are kept, so it lands on the same spot.
+pub fn move_to_page(position_data: &str, page_number: i64) -> serde_json::Result<String> {
+  let mut position = parse(position_data)?;
+  position.page_number = page_number;
+  for rect in std::iter::once(&mut position.bounding_rect).chain(position.rects.iter_mut()) {
+    if rect.page_number.is_some() {
+      rect.page_number = Some(page_number);
+    }
+  }
+  serde_json::to_string(&position)
+}
+
+impl Scaled {
+  /// The rectangle as fractions of the page size, so rects recorded at
+  /// different zoom levels can be compared. `None` for a degenerate page size.
+  pub fn normalized(&self) -> Option<[f64; 4]> {
+    if self.width <= 0.0 || self.height <= 0.0 {
+      return None;
+    }
+    Some([
+      self.x1.min(self.x2) / self.width,
+      self.y1.min(self.y2) / self.height,
+      self.x1.max(self.x2) / self.width,
+      self.y1.max(self.y2) / self.height,
+    ])
+  }
+}
+
+/// Intersection over union of two normalized rects, from 0 (disjoint) to 1
+/// (identical).
+pub fn iou(a: [f64; 4], b: [f64; 4]) -> f64 {
+  let area = |r: [f64; 4]| (r[2] - r[0]).max(0.0) * (r[3] - r[1]).max(0.0);
+  let overlap = area([
+    a[0].max(b[0]),
+    a[1].max(b[1]),
+    a[2].min(b[2]),
+    a[3].min(b[3]),
+  ]);
+  let union = area(a) + area(b) - overlap;
+  if union <= 0.0 {
+    0.0
+  } else {
+    overlap / union
+  }
+}
+
+/// A problem that keeps a stored highlight from rendering where it should.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub enum Issue {
+  /// `position_data` isn't a `ScaledPosition`.
+  Unparseable,
+  /// The page is below 1 or past the end of the document.
+  PageOutOfRange,
+  /// A rect has a negative coordinate or size, or its corners swapped.
+  NegativeRect,
+  /// There are no rects to draw.
+  EmptyRects,
+}
+
+fn is_negative(rect: &Scaled) -> bool {
+  [rect.x1, rect.y1, rect.x2, rect.y2, rect.width, rect.height]
+    .iter()
+    .any(|&value| value < 0.0)
+    || rect.x2 < rect.x1
+    || rect.y2 < rect.y1
+}
+
+/// Every issue of `position_data`, each reported once. Unlike `validate`, it
+/// is lenient about what the frontend renders fine, such as zero-sized rects.
+pub fn audit(position_data: &str, page_count: u32) -> Vec<Issue> {
+  let Ok(position) = parse(position_data) else {
+    return vec![Issue::Unparseable];
+  };
+  let mut issues = Vec::new();
+  if position.page_number < 1 || position.page_number > i64::from(page_count) {
+    issues.push(Issue::PageOutOfRange);
+  }
+  if std::iter::once(&position.bounding_rect)
+    .chain(&position.rects)
+    .any(is_negative)
+  {
+    issues.push(Issue::NegativeRect);
+  }
+  if position.rects.is_empty() {
+    issues.push(Issue::EmptyRects);
+  }
+  issues
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use serde_json::{json, Value};
+
+  fn rect(width: f64, height: f64) -> Value {
+    json!({
+      "x1": 10.0,
+      "y1": 20.0,
+      "x2": 110.0,
+      "y2": 40.0,
+      "width": width,
+      "height": height,
+    })
+  }
+
+  fn validate_json(position: Value, page_count: Option<u32>) -> Result<ScaledPosition, String> {
+    validate(&position.to_string(), page_count)
+  }
+
+  #[test]
+  fn accepts_a_well_formed_position() {
+    let position = json!({
+      "boundingRect": rect(800.0, 1200.0),
+      "rects": [rect(800.0, 1200.0)],
+      "pageNumber": 2,
+    });
+    assert!(validate_json(position, Some(3)).is_ok());
+  }
+
+  #[test]
+  fn rejects_a_missing_bounding_rect() {
+    let position = json!({ "rects": [rect(800.0, 1200.0)], "pageNumber": 1 });
+    let error = validate_json(position, None).unwrap_err();
+    assert!(
+      error.starts_with("Invalid position data: missing field `boundingRect`"),
+      "{}",
+      error
+    );
+  }
+
+  #[test]
+  fn rejects_negative_dimensions() {
+    let position = json!({ "boundingRect": rect(-800.0, 1200.0), "rects": [], "pageNumber": 1 });
+    assert_eq!(
+      validate_json(position, None).unwrap_err(),
+      "boundingRect.width must not be negative"
+    );
+
+    let position = json!({
+      "boundingRect": rect(800.0, 1200.0),
+      "rects": [rect(800.0, -1200.0)],
+      "pageNumber": 1,
+    });
+    assert_eq!(
+      validate_json(position, None).unwrap_err(),
+      "rects[0].height must not be negative"
+    );
+  }
+
+  #[test]
+  fn rejects_an_out_of_range_page_number() {
+    let position = json!({ "boundingRect": rect(800.0, 1200.0), "rects": [], "pageNumber": 5 });
+    assert_eq!(
+      validate_json(position, Some(3)).unwrap_err(),
+      "pageNumber 5 is out of range (document has 3 pages)"
+    );
+
+    let position = json!({ "boundingRect": rect(800.0, 1200.0), "rects": [], "pageNumber": 0 });
+    assert_eq!(
+      validate_json(position, Some(3)).unwrap_err(),
+      "pageNumber must be 1 or greater"
+    );
+  }
+}
@@ -0,0 +1,164 @@
+//! The `pdf` URI scheme, through which the viewer loads a library PDF by id
+//! instead of receiving its bytes over IPC. The URL is
+//! `pdf://localhost/<pdf_id>`, or `http://pdf.localhost/<pdf_id>` on Windows;
+//! `convertFileSrc(String(pdfId), "pdf")` builds the right one. Range
+//! requests are answered with just the requested bytes, so pdf.js can load
+//! big documents progressively.
+
+use std::fs::File;
+use std::io::{self, Read, Seek, SeekFrom};
+use tauri::http::{header, Method, Request, Response, StatusCode, Uri};
+use tauri::{AppHandle, Runtime, UriSchemeContext, UriSchemeResponder};
+
+use crate::{db, pdfs};
+
+pub const SCHEME: &str = "pdf";
+
+/// Most bytes returned for an open-ended range (`bytes=N-`), so a single
+/// request can't pull a whole large document into memory.
+const MAX_RANGE_LEN: u64 = 4 * 1024 * 1024;
+
+/// Registered with `register_asynchronous_uri_scheme_protocol`. Files are
+/// read on a blocking thread, so the webview's main thread never waits on
+/// the disk.
+pub fn handle<R: Runtime>(
+  ctx: UriSchemeContext<'_, R>,
+  request: Request<Vec<u8>>,
+  responder: UriSchemeResponder,
+) {
+  let app = ctx.app_handle().clone();
+  tauri::async_runtime::spawn_blocking(move || responder.respond(respond(&app, &request)));
+}
+
+/// The id is the first path segment, or the host for `pdf://<pdf_id>`.
+fn pdf_id(uri: &Uri) -> Option<i64> {
+  let path = uri.path().trim_matches('/');
+  let id = if path.is_empty() { uri.host()? } else { path };
+  id.parse().ok()
+}
+
+fn text(status: StatusCode, message: String) -> Response<Vec<u8>> {
+  Response::builder()
+    .status(status)
+    .header(header::CONTENT_TYPE, "text/plain")
+    .header(header::ACCESS_CONTROL_ALLOW_ORIGIN, "*")
+    .body(message.into_bytes())
+    .unwrap_or_default()
+}
+
+fn respond<R: Runtime>(app: &AppHandle<R>, request: &Request<Vec<u8>>) -> Response<Vec<u8>> {
+  // The viewer's origin differs from the scheme's, and `Range` may need a
+  // preflight
+  if request.method() == Method::OPTIONS {
+    return Response::builder()
+      .status(StatusCode::NO_CONTENT)
+      .header(header::ACCESS_CONTROL_ALLOW_ORIGIN, "*")
+      .header(header::ACCESS_CONTROL_ALLOW_METHODS, "GET, HEAD")
+      .header(header::ACCESS_CONTROL_ALLOW_HEADERS, "Range")
+      .body(Vec::new())
+      .unwrap_or_default();
+  }
+
+  let Some(pdf_id) = pdf_id(request.uri()) else {
+    return text(
+      StatusCode::BAD_REQUEST,
+      format!("{} is not a PDF URL", request.uri()),
+    );
+  };
+  let pdf = db::open(app).and_then(|conn| pdfs::get(&conn, pdf_id).map_err(|e| e.to_string()));
+  let path = match pdf {
+    Ok(Some(pdf)) => pdf.path,
+    Ok(None) => return text(StatusCode::NOT_FOUND, format!("PDF {} not found", pdf_id)),
+    Err(e) => {
+      log::warn!("Cannot look up PDF {}: {}", pdf_id, e);
+      return text(StatusCode::INTERNAL_SERVER_ERROR, e);
+    }
+  };
+
+  match serve(&path, request) {
+    Ok(response) => response,
+    Err(e) if e.kind() == io::ErrorKind::NotFound => text(
+      StatusCode::NOT_FOUND,
+      format!("PDF {} is missing from {}", pdf_id, path),
+    ),
+    Err(e) => {
+      log::warn!("Cannot serve PDF {} from {}: {}", pdf_id, path, e);
+      text(
+        StatusCode::INTERNAL_SERVER_ERROR,
+        format!("Cannot read {}: {}", path, e),
+      )
+    }
+  }
+}
+
+/// The byte range `[start, end)` a `Range` header asks of a file of `len`
+/// bytes, or `None` when it can't be satisfied. Only the first range of a
+/// multi-range request is served.
+fn parse_range(value: &str, len: u64) -> Option<(u64, u64)> {
+  let spec = value.trim().strip_prefix("bytes=")?;
+  let (start, end) = spec.split(',').next()?.trim().split_once('-')?;
+  let (start, end) = match (start.trim(), end.trim()) {
+    ("", suffix) => (len.saturating_sub(suffix.parse().ok()?), len),
+    (start, "") => {
+      let start: u64 = start.parse().ok()?;
+      (start, len.min(start.saturating_add(MAX_RANGE_LEN)))
+    }
+    (start, end) => {
+      let start: u64 = start.parse().ok()?;
+      let end: u64 = end.parse().ok()?;
+      if end < start {
+        return None;
+      }
+      (start, len.min(end.saturating_add(1)))
+    }
+  };
+  (start < end).then_some((start, end))
+}
+
+fn serve(path: &str, request: &Request<Vec<u8>>) -> io::Result<Response<Vec<u8>>> {
+  let mut file = File::open(path)?;
+  let len = file.metadata()?.len();
+  let builder = Response::builder()
+    .header(header::CONTENT_TYPE, "application/pdf")
+    .header(header::ACCEPT_RANGES, "bytes")
+    .header(header::ACCESS_CONTROL_ALLOW_ORIGIN, "*")
+    .header(
+      header::ACCESS_CONTROL_EXPOSE_HEADERS,
+      "Accept-Ranges, Content-Length, Content-Range",
+    );
+
+  let range = request
+    .headers()
+    .get(header::RANGE)
+    .and_then(|value| value.to_str().ok());
+  let (builder, start, end) = match range {
+    None => (builder.status(StatusCode::OK), 0, len),
+    Some(range) => match parse_range(range, len) {
+      Some((start, end)) => (
+        builder.status(StatusCode::PARTIAL_CONTENT).header(
+          header::CONTENT_RANGE,
+          format!("bytes {}-{}/{}", start, end - 1, len),
+        ),
+        start,
+        end,
+      ),
+      None => {
+        return builder
+          .status(StatusCode::RANGE_NOT_SATISFIABLE)
+          .header(header::CONTENT_RANGE, format!("bytes */{}", len))
+          .body(Vec::new())
+          .map_err(io::Error::other);
+      }
+    },
+  };
+
+  let mut body = Vec::new();
+  if request.method() != Method::HEAD {
+    file.seek(SeekFrom::Start(start))?;
+    file.take(end - start).read_to_end(&mut body)?;
+  }
+  builder
+    .header(header::CONTENT_LENGTH, end - start)
+    .body(body)
+    .map_err(io::Error::other)
+}
@@ -0,0 +1,502 @@
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+use image::{Pixel, Rgba, RgbaImage};
+use pdfium_render::prelude::*;
+use std::collections::HashMap;
+use std::fs;
+use std::io::Cursor;
+use std::panic::{self, AssertUnwindSafe};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Condvar, Mutex, OnceLock};
+use std::thread;
+use std::time::SystemTime;
+use tauri::{AppHandle, Manager, Runtime};
+
+use crate::error::AppError;
+use crate::geometry::{self, PdfBox};
+use crate::highlights::{self, HighlightRecord};
+use crate::{color, db, pdfs};
+
+/// Upper bound for the on-disk thumbnail cache. Least recently used files are
+/// evicted once it grows past this.
+const THUMBNAIL_CACHE_BYTES: u64 = 100 * 1024 * 1024;
+
+/// Thumbnails wider than this are refused; they would no longer be thumbnails.
+const MAX_THUMBNAIL_WIDTH: u32 = 4096;
+
+/// Largest zoom `render_page_with_highlights` renders at, relative to the
+/// page's size in points.
+const MAX_OVERLAY_SCALE: f32 = 8.0;
+
+/// Color and opacity of highlights drawn without a stored color or alpha,
+/// matching the viewer's defaults.
+const DEFAULT_HIGHLIGHT_COLOR: [u8; 3] = [0xff, 0xff, 0x00];
+const DEFAULT_HIGHLIGHT_ALPHA: u8 = 0x66;
+
+/// Maximum number of thumbnails rendered at once, set by
+/// `set_render_concurrency`. 0 means half the available cores.
+static MAX_RENDERS: AtomicUsize = AtomicUsize::new(0);
+
+fn max_renders() -> usize {
+  match MAX_RENDERS.load(Ordering::Relaxed) {
+    0 => thread::available_parallelism()
+      .map(|n| (n.get() / 2).max(1))
+      .unwrap_or(2),
+    n => n,
+  }
+}
+
+/// Content hash, page and width of a thumbnail.
+type RenderKey = (String, u32, u32);
+type RenderResult = Result<Vec<u8>, AppError>;
+
+/// A render in progress, shared by every request for the same thumbnail.
+#[derive(Default)]
+struct RenderJob {
+  result: Mutex<Option<RenderResult>>,
+  done: Condvar,
+}
+
+impl RenderJob {
+  fn wait(&self) -> RenderResult {
+    let mut result = self.result.lock().unwrap();
+    while result.is_none() {
+      result = self.done.wait(result).unwrap();
+    }
+    result.clone().unwrap()
+  }
+
+  fn finish(&self, result: RenderResult) {
+    *self.result.lock().unwrap() = Some(result);
+    self.done.notify_all();
+  }
+}
+
+#[derive(Default)]
+struct RenderQueue {
+  jobs: HashMap<RenderKey, Arc<RenderJob>>,
+  running: usize,
+}
+
+#[derive(Default)]
+struct RenderSlots {
+  queue: Mutex<RenderQueue>,
+  freed: Condvar,
+}
+
+fn render_slots() -> &'static RenderSlots {
+  static SLOTS: OnceLock<RenderSlots> = OnceLock::new();
+  SLOTS.get_or_init(Default::default)
+}
+
+/// Runs `render` for `key` with at most `max_renders()` renders in flight.
+/// A request for a thumbnail that is already queued or rendering waits for
+/// that render's result instead of starting another. Blocks the calling
+/// thread.
+fn render_single_flight(key: RenderKey, render: impl FnOnce() -> RenderResult) -> RenderResult {
+  let slots = render_slots();
+  let mut queue = slots.queue.lock().unwrap();
+  if let Some(job) = queue.jobs.get(&key).cloned() {
+    drop(queue);
+    return job.wait();
+  }
+
+  let job = Arc::new(RenderJob::default());
+  queue.jobs.insert(key.clone(), job.clone());
+  while queue.running >= max_renders() {
+    queue = slots.freed.wait(queue).unwrap();
+  }
+  queue.running += 1;
+  drop(queue);
+
+  // Waiters must always be released, even if pdfium panics
+  let result = panic::catch_unwind(AssertUnwindSafe(render))
+    .unwrap_or_else(|_| Err(AppError::Failed("Thumbnail rendering panicked".to_string())));
+  job.finish(result.clone());
+
+  let mut queue = slots.queue.lock().unwrap();
+  queue.running -= 1;
+  queue.jobs.remove(&key);
+  drop(queue);
+  slots.freed.notify_one();
+  result
+}
+
+/// Pdfium tears the library down when a `Pdfium` is dropped, so the process
+/// keeps exactly one bound instance around for its whole lifetime.
+pub fn pdfium<R: Runtime>(app: &AppHandle<R>) -> Result<&'static Pdfium, AppError> {
+  static PDFIUM: OnceLock<Pdfium> = OnceLock::new();
+  static INIT: Mutex<()> = Mutex::new(());
+
+  if let Some(pdfium) = PDFIUM.get() {
+    return Ok(pdfium);
+  }
+  let _guard = INIT.lock().unwrap();
+  if let Some(pdfium) = PDFIUM.get() {
+    return Ok(pdfium);
+  }
+
+  // Prefer a library shipped next to the app, then whatever the system has.
+  let bundled = app.path().resource_dir().ok().and_then(|dir| {
+    Pdfium::bind_to_library(Pdfium::pdfium_platform_library_name_at_path(&dir)).ok()
+  });
+  let bindings = match bundled {
+    Some(bindings) => bindings,
+    None => Pdfium::bind_to_system_library()
+      .map_err(|e| AppError::Unsupported(format!("Cannot load the pdfium library: {}", e)))?,
+  };
+  Ok(PDFIUM.get_or_init(|| Pdfium::new(bindings)))
+}
+
+/// Converts a 1-based page number into a pdfium page index, failing with a
+/// readable message when the document has no such page.
+pub fn page_index(document: &PdfDocument, page: u32) -> Result<PdfPageIndex, AppError> {
+  let count = document.pages().len();
+  if page == 0 || page > count as u32 {
+    return Err(AppError::InvalidInput(format!(
+      "Page {} is out of range (document has {} pages)",
+      page, count
+    )));
+  }
+  Ok((page - 1) as PdfPageIndex)
+}
+
+fn thumbnail_dir(app: &AppHandle) -> Result<PathBuf, AppError> {
+  let dir = app
+    .path()
+    .app_cache_dir()
+    .map_err(|e| AppError::Failed(format!("Cannot resolve the app cache directory: {}", e)))?
+    .join("thumbnails");
+  fs::create_dir_all(&dir)
+    .map_err(|e| AppError::Io(format!("Cannot create {}: {}", dir.display(), e)))?;
+  Ok(dir)
+}
+
+/// Deletes the oldest cached thumbnails until the cache fits its budget.
+/// Cache hits refresh a file's mtime, so age approximates last use.
+fn evict(dir: &Path) {
+  let Ok(entries) = fs::read_dir(dir) else {
+    return;
+  };
+  let mut files: Vec<(SystemTime, u64, PathBuf)> = entries
+    .flatten()
+    .filter_map(|entry| {
+      let metadata = entry.metadata().ok()?;
+      let modified = metadata.modified().ok()?;
+      Some((modified, metadata.len(), entry.path()))
+    })
+    .collect();
+
+  let mut total: u64 = files.iter().map(|(_, len, _)| len).sum();
+  files.sort();
+  for (_, len, path) in files {
+    if total <= THUMBNAIL_CACHE_BYTES {
+      break;
+    }
+    if fs::remove_file(&path).is_ok() {
+      total -= len;
+    }
+  }
+}
+
+fn render_png(app: &AppHandle, pdf_path: &str, page: u32, width: u32) -> RenderResult {
+  let pdfium = pdfium(app)?;
+  let document = load_document(pdfium, pdf_path)?;
+  let index = page_index(&document, page)?;
+  let config = PdfRenderConfig::new().set_target_width(width as i32);
+  let page = document.pages().get(index).map_err(|e| e.to_string())?;
+  let image = page
+    .render_with_config(&config)
+    .map_err(|e| e.to_string())?
+    .as_image();
+
+  let mut png = Cursor::new(Vec::new());
+  image
+    .write_to(&mut png, image::ImageFormat::Png)
+    .map_err(|e| e.to_string())?;
+  Ok(png.into_inner())
+}
+
+/// Opens a PDF, telling password-protected and malformed files apart from
+/// other failures so the UI can ask for a password instead of showing a
+/// generic error.
+fn load_document<'a>(pdfium: &'a Pdfium, pdf_path: &str) -> Result<PdfDocument<'a>, AppError> {
+  pdfium
+    .load_pdf_from_file(pdf_path, None)
+    .map_err(|e| match e {
+      PdfiumError::PdfiumLibraryInternalError(PdfiumInternalError::PasswordError) => {
+        AppError::Encrypted(format!("{} is password protected", pdf_path))
+      }
+      PdfiumError::PdfiumLibraryInternalError(PdfiumInternalError::FormatError) => {
+        AppError::Corrupt(format!("{} is not a valid PDF", pdf_path))
+      }
+      e => AppError::Failed(format!("Cannot open PDF {}: {}", pdf_path, e)),
+    })
+}
+
+fn page_text(app: &AppHandle, pdf_path: &str, page: u32) -> Result<String, AppError> {
+  let pdfium = pdfium(app)?;
+  let document = load_document(pdfium, pdf_path)?;
+  let index = page_index(&document, page)?;
+
+  let page = document.pages().get(index).map_err(|e| e.to_string())?;
+
+  // Scanned pages have no text layer at all; that is not an error for callers
+  // that just want whatever text there is.
+  let text = match page.text() {
+    Ok(text) => text.all(),
+    Err(_) => return Ok(String::new()),
+  };
+  if text.trim().is_empty() {
+    return Ok(String::new());
+  }
+  Ok(text)
+}
+
+pub fn data_url(png: &[u8]) -> String {
+  format!("data:image/png;base64,{}", STANDARD.encode(png))
+}
+
+/// Number of pages of the PDF at `pdf_path`. Counts are memoized per content
+/// hash, so asking again for the same document doesn't reopen it.
+pub fn page_count(app: &AppHandle, pdf_path: &str) -> Result<u32, AppError> {
+  static COUNTS: OnceLock<Mutex<HashMap<String, u32>>> = OnceLock::new();
+  let counts = COUNTS.get_or_init(Default::default);
+
+  let sha256 = pdfs::sha256_cached(Path::new(pdf_path))
+    .map_err(|e| AppError::Io(format!("Cannot read PDF {}: {}", pdf_path, e)))?;
+  if let Some(&count) = counts.lock().unwrap().get(&sha256) {
+    return Ok(count);
+  }
+
+  let pdfium = pdfium(app)?;
+  let document = load_document(pdfium, pdf_path)?;
+  let count = document.pages().len() as u32;
+  counts.lock().unwrap().insert(sha256, count);
+  Ok(count)
+}
+
+/// Opens the PDF at `pdf_path` uncached and returns its page count and
+/// whether it is encrypted. Only documents that open without a password get
+/// this far; others fail with `AppError::Encrypted`.
+pub fn inspect(app: &AppHandle, pdf_path: &str) -> Result<(u32, bool), AppError> {
+  let pdfium = pdfium(app)?;
+  let document = load_document(pdfium, pdf_path)?;
+  let encrypted = !matches!(
+    document.permissions().security_handler_revision(),
+    Ok(PdfSecurityHandlerRevision::Unprotected)
+  );
+  Ok((document.pages().len() as u32, encrypted))
+}
+
+fn thumbnail(app: &AppHandle, pdf_path: &str, page: u32, width: u32) -> RenderResult {
+  let sha256 = pdfs::sha256_cached(Path::new(pdf_path))
+    .map_err(|e| AppError::Io(format!("Cannot read PDF {}: {}", pdf_path, e)))?;
+  let dir = thumbnail_dir(app)?;
+  let cached = dir.join(format!("{}-{}-{}.png", sha256, page, width));
+
+  if let Ok(png) = fs::read(&cached) {
+    if let Ok(file) = fs::File::options().write(true).open(&cached) {
+      let _ = file.set_modified(SystemTime::now());
+    }
+    return Ok(png);
+  }
+
+  // The cache file is written before the job is released, so a request
+  // arriving after that finds it instead of rendering again.
+  render_single_flight((sha256, page, width), || {
+    let png = render_png(app, pdf_path, page, width)?;
+    // A failed cache write only costs a re-render next time.
+    if let Err(e) = fs::write(&cached, &png) {
+      log::warn!("Cannot cache thumbnail {}: {}", cached.display(), e);
+    }
+    evict(&dir);
+    Ok(png)
+  })
+}
+
+/// Rasterizes page `page` (1-based) of a PDF to a PNG `width` pixels wide and
+/// returns it as a data URL. Results are cached on disk per document content,
+/// so renamed or moved copies of a file share their thumbnails. Concurrent
+/// requests for the same thumbnail share one render.
+#[tauri::command]
+pub async fn render_page_thumbnail(
+  app: AppHandle,
+  pdf_path: String,
+  page: u32,
+  width: u32,
+) -> Result<String, AppError> {
+  if width == 0 || width > MAX_THUMBNAIL_WIDTH {
+    return Err(AppError::InvalidInput(format!(
+      "Thumbnail width must be between 1 and {} pixels",
+      MAX_THUMBNAIL_WIDTH
+    )));
+  }
+
+  // Waiting for a render slot blocks, so keep it off the async runtime
+  let png = tauri::async_runtime::spawn_blocking(move || thumbnail(&app, &pdf_path, page, width))
+    .await
+    .map_err(|e| format!("Thumbnail rendering failed: {}", e))??;
+  Ok(data_url(&png))
+}
+
+/// Caps how many thumbnails render at once. 0 restores the default of half
+/// the available cores.
+#[tauri::command]
+pub async fn set_render_concurrency(limit: usize) -> Result<(), AppError> {
+  MAX_RENDERS.store(limit, Ordering::Relaxed);
+  // Requests waiting for a slot re-check against the new limit
+  render_slots().freed.notify_all();
+  Ok(())
+}
+
+/// Returns the text content of page `page` (1-based) as pdfium extracts it,
+/// with ligatures resolved. Image-only pages yield an empty string.
+#[tauri::command]
+pub async fn extract_page_text(
+  app: AppHandle,
+  pdf_path: String,
+  page: u32,
+) -> Result<String, AppError> {
+  page_text(&app, &pdf_path, page)
+}
+
+#[tauri::command]
+pub async fn get_pdf_page_count(app: AppHandle, pdf_path: String) -> Result<u32, AppError> {
+  page_count(&app, &pdf_path)
+}
+
+fn pdf_box(rect: &PdfRect) -> PdfBox {
+  [
+    f64::from(rect.left.value),
+    f64::from(rect.bottom.value),
+    f64::from(rect.right.value),
+    f64::from(rect.top.value),
+  ]
+}
+
+/// The visible box and rotation of `page`, which positions saved in PDF
+/// coordinates are placed against.
+fn page_geometry(page: &PdfPage) -> Result<(PdfBox, i64), AppError> {
+  let boundaries = page.boundaries();
+  let media = boundaries.media().map_err(|e| e.to_string())?.bounds;
+  let crop = boundaries.crop().ok().map(|crop| pdf_box(&crop.bounds));
+  let rotate = match page.rotation().map_err(|e| e.to_string())? {
+    PdfPageRenderRotation::None => 0,
+    PdfPageRenderRotation::Degrees90 => 90,
+    PdfPageRenderRotation::Degrees180 => 180,
+    PdfPageRenderRotation::Degrees270 => 270,
+  };
+  Ok((geometry::visible_box(pdf_box(&media), crop), rotate))
+}
+
+/// Blends each highlight's rects onto `image` in its color. Stored positions
+/// are fractions of the viewport the page was displayed in, which shows the
+/// same area as pdfium's bitmap, so they scale straight onto it; those saved
+/// in PDF coordinates are mapped through `page` and `rotate` first.
+fn draw_highlights(image: &mut RgbaImage, rows: &[HighlightRecord], page: &PdfBox, rotate: i64) {
+  let (width, height) = image.dimensions();
+  for row in rows {
+    let position = match row.position() {
+      Ok(position) => position,
+      Err(e) => {
+        log::warn!("Skipping highlight {}: {}", row.highlight_id, e);
+        continue;
+      }
+    };
+    let (rgb, alpha) = row
+      .color
+      .as_deref()
+      .and_then(color::parse_hex)
+      .unwrap_or((DEFAULT_HIGHLIGHT_COLOR, None));
+    let [r, g, b] = rgb;
+    let fill = Rgba([r, g, b, alpha.unwrap_or(DEFAULT_HIGHLIGHT_ALPHA)]);
+
+    // Area highlights only have a bounding rect
+    let rects = if position.rects.is_empty() {
+      std::slice::from_ref(&position.bounding_rect)
+    } else {
+      &position.rects
+    };
+    let pdf_coordinates = position.use_pdf_coordinates == Some(true);
+    for rect in rects {
+      let Some([left, top, right, bottom]) =
+        geometry::fractions(rect, pdf_coordinates, page, rotate)
+      else {
+        continue;
+      };
+      let x = |v: f64| (v * f64::from(width)).round().clamp(0.0, f64::from(width)) as u32;
+      let y = |v: f64| {
+        (v * f64::from(height))
+          .round()
+          .clamp(0.0, f64::from(height)) as u32
+      };
+      for py in y(top)..y(bottom) {
+        for px in x(left)..x(right) {
+          image.get_pixel_mut(px, py).blend(&fill);
+        }
+      }
+    }
+  }
+}
+
+fn page_with_highlights(
+  app: &AppHandle,
+  pdf_path: &str,
+  page: u32,
+  scale: f32,
+  rows: &[HighlightRecord],
+) -> Result<Vec<u8>, AppError> {
+  let pdfium = pdfium(app)?;
+  let document = load_document(pdfium, pdf_path)?;
+  let index = page_index(&document, page)?;
+  let config = PdfRenderConfig::new().scale_page_by_factor(scale);
+  let pdf_page = document.pages().get(index).map_err(|e| e.to_string())?;
+  let (page_box, rotate) = page_geometry(&pdf_page)?;
+  let mut image = pdf_page
+    .render_with_config(&config)
+    .map_err(|e| e.to_string())?
+    .as_image()
+    .into_rgba8();
+  draw_highlights(&mut image, rows, &page_box, rotate);
+
+  let mut png = Cursor::new(Vec::new());
+  image
+    .write_to(&mut png, image::ImageFormat::Png)
+    .map_err(|e| e.to_string())?;
+  Ok(png.into_inner())
+}
+
+/// Renders page `page` (1-based) of a PDF at `scale` times its size in points
+/// with its highlights drawn on as translucent boxes, and returns it as a PNG
+/// data URL, e.g. for sharing a page with its annotations.
+#[tauri::command]
+pub async fn render_page_with_highlights(
+  app: AppHandle,
+  pdf_id: i64,
+  page: u32,
+  scale: f32,
+) -> Result<String, AppError> {
+  if !(scale > 0.0 && scale <= MAX_OVERLAY_SCALE) {
+    return Err(AppError::InvalidInput(format!(
+      "scale must be greater than 0 and at most {}",
+      MAX_OVERLAY_SCALE
+    )));
+  }
+  let conn = db::open(&app)?;
+  let pdf = pdfs::get(&conn, pdf_id)?
+    .ok_or_else(|| AppError::NotFound(format!("PDF {} not found", pdf_id)))?;
+  let rows: Vec<_> = highlights::list_for_pdf(&conn, pdf_id)?
+    .into_iter()
+    .filter(|row| row.page_number == i64::from(page))
+    .collect();
+  drop(conn);
+
+  let png = tauri::async_runtime::spawn_blocking(move || {
+    page_with_highlights(&app, &pdf.path, page, scale, &rows)
+  })
+  .await
+  .map_err(|e| format!("Rendering failed: {}", e))??;
+  Ok(data_url(&png))
+}
@@ -0,0 +1,305 @@
+use regex::{Regex, RegexBuilder};
+use rusqlite::types::Value;
+use rusqlite::{params, params_from_iter, Connection};
+use serde::Serialize;
+use tauri::AppHandle;
+
+use crate::error::AppError;
+use crate::{db, export};
+
+#[derive(Debug, Serialize)]
+pub struct HighlightMatch {
+  pub highlight_id: String,
+  pub pdf_id: i64,
+  pub page_number: i64,
+  /// HTML excerpt of the best matching column with hits wrapped in `<mark>`
+  /// tags. The text itself is escaped, so it can be rendered as is.
+  pub snippet: String,
+}
+
+/// FTS5 excerpt of the best matching column. Hits are set off with the
+/// control characters `HIT_START` and `HIT_END`, which highlight text doesn't
+/// contain, since the text still has to be escaped before they become
+/// `<mark>` tags.
+const FTS_SNIPPET: &str = "snippet(highlights_fts, -1, char(2), char(3), '…', 12)";
+const HIT_START: &str = "\u{2}";
+const HIT_END: &str = "\u{3}";
+
+/// A snippet from the database as HTML.
+fn marked_snippet(raw: &str) -> String {
+  export::html_escape(raw)
+    .replace(HIT_START, "<mark>")
+    .replace(HIT_END, "</mark>")
+}
+
+/// Turns free-form user input into an FTS5 query. Every whitespace separated
+/// word becomes a quoted string so characters such as `-`, `:` or `"` are
+/// matched literally instead of being parsed as FTS5 operators.
+fn to_fts_query(input: &str) -> String {
+  input
+    .split_whitespace()
+    .map(|term| format!("\"{}\"", term.replace('"', "\"\"")))
+    .collect::<Vec<_>>()
+    .join(" ")
+}
+
+fn search(
+  conn: &Connection,
+  query: &str,
+  pdf_id: Option<i64>,
+) -> rusqlite::Result<Vec<HighlightMatch>> {
+  let fts_query = to_fts_query(query);
+  if fts_query.is_empty() {
+    return Ok(Vec::new());
+  }
+
+  let mut stmt = conn.prepare(&format!(
+    "SELECT h.highlight_id, h.pdf_id, h.page_number, {}
+     FROM highlights_fts
+     JOIN highlights h ON h.id = highlights_fts.rowid
+     WHERE highlights_fts MATCH ?1
+       AND h.deleted_at IS NULL
+       AND (?2 IS NULL OR h.pdf_id = ?2)
+     ORDER BY rank",
+    FTS_SNIPPET
+  ))?;
+
+  let rows = stmt.query_map(params![fts_query, pdf_id], |row| {
+    Ok(HighlightMatch {
+      highlight_id: row.get(0)?,
+      pdf_id: row.get(1)?,
+      page_number: row.get(2)?,
+      snippet: marked_snippet(&row.get::<_, String>(3)?),
+    })
+  })?;
+  rows.collect()
+}
+
+#[tauri::command]
+pub async fn search_highlights(
+  app: AppHandle,
+  query: String,
+  pdf_id: Option<i64>,
+) -> Result<Vec<HighlightMatch>, AppError> {
+  let conn = db::open(&app)?;
+  Ok(search(&conn, &query, pdf_id)?)
+}
+
+/// Text shown for a match when there is no full-text query to build a
+/// snippet from.
+const PLAIN_SNIPPET: &str =
+  "substr(COALESCE(NULLIF(h.content_text, ''), h.comment_text, ''), 1, 200)";
+
+/// Combines the full-text query, tag intersection, PDF and page range filters
+/// into one statement; `None` and empty filters are left out.
+///
+/// With a query the FTS index drives the lookup and the other filters only
+/// narrow its hits, so cost grows with the number of matches. Without one the
+/// FTS table isn't joined at all: tag-only searches go through the
+/// `highlight_tags` primary key and PDF-scoped ones scan that PDF's
+/// highlights, which stays cheap at library sizes.
+fn advanced(
+  conn: &Connection,
+  query: Option<&str>,
+  tag_ids: &[i64],
+  pdf_id: Option<i64>,
+  page_range: Option<(i64, i64)>,
+) -> rusqlite::Result<Vec<HighlightMatch>> {
+  let fts_query = query.map(to_fts_query).filter(|q| !q.is_empty());
+
+  let mut values: Vec<Value> = Vec::new();
+  let mut filters = vec!["h.deleted_at IS NULL".to_string()];
+  let (from, snippet, order_by) = match &fts_query {
+    Some(fts_query) => {
+      values.push(fts_query.clone().into());
+      filters.push(format!("highlights_fts MATCH ?{}", values.len()));
+      (
+        "highlights_fts JOIN highlights h ON h.id = highlights_fts.rowid",
+        FTS_SNIPPET,
+        "rank",
+      )
+    }
+    None => (
+      "highlights h",
+      PLAIN_SNIPPET,
+      "h.pdf_id, h.page_number, h.id",
+    ),
+  };
+
+  if let Some(pdf_id) = pdf_id {
+    values.push(pdf_id.into());
+    filters.push(format!("h.pdf_id = ?{}", values.len()));
+  }
+  if let Some((first, last)) = page_range {
+    values.push(first.into());
+    values.push(last.into());
+    filters.push(format!(
+      "h.page_number BETWEEN ?{} AND ?{}",
+      values.len() - 1,
+      values.len()
+    ));
+  }
+  if !tag_ids.is_empty() {
+    let first = values.len() + 1;
+    values.extend(tag_ids.iter().map(|&id| Value::from(id)));
+    let placeholders = (first..=values.len())
+      .map(|i| format!("?{}", i))
+      .collect::<Vec<_>>()
+      .join(", ");
+    filters.push(format!(
+      "h.highlight_id IN (
+         SELECT highlight_id FROM highlight_tags
+         WHERE tag_id IN ({})
+         GROUP BY highlight_id
+         HAVING COUNT(DISTINCT tag_id) = {}
+       )",
+      placeholders,
+      tag_ids.len()
+    ));
+  }
+
+  let mut stmt = conn.prepare(&format!(
+    "SELECT h.highlight_id, h.pdf_id, h.page_number, {}
+     FROM {}
+     WHERE {}
+     ORDER BY {}",
+    snippet,
+    from,
+    filters.join(" AND "),
+    order_by
+  ))?;
+  let rows = stmt.query_map(params_from_iter(values), |row| {
+    Ok(HighlightMatch {
+      highlight_id: row.get(0)?,
+      pdf_id: row.get(1)?,
+      page_number: row.get(2)?,
+      snippet: marked_snippet(&row.get::<_, String>(3)?),
+    })
+  })?;
+  rows.collect()
+}
+
+/// `search_highlights` combined with the tag (all of `tag_ids`), PDF and
+/// inclusive page range filters. Without a query the snippet is the start of
+/// the highlighted text, or of the comment for image highlights.
+#[tauri::command]
+pub async fn advanced_search(
+  app: AppHandle,
+  query: Option<String>,
+  tag_ids: Vec<i64>,
+  pdf_id: Option<i64>,
+  page_range: Option<(i64, i64)>,
+) -> Result<Vec<HighlightMatch>, AppError> {
+  if let Some((first, last)) = page_range {
+    if first > last {
+      return Err(AppError::InvalidInput(format!(
+        "Invalid page range {}-{}",
+        first, last
+      )));
+    }
+  }
+  let mut tag_ids = tag_ids;
+  tag_ids.sort_unstable();
+  tag_ids.dedup();
+
+  let conn = db::open(&app)?;
+  Ok(advanced(
+    &conn,
+    query.as_deref(),
+    &tag_ids,
+    pdf_id,
+    page_range,
+  )?)
+}
+
+/// Longest pattern `search_highlights_regex` accepts.
+const MAX_PATTERN_LEN: usize = 1000;
+
+/// Compiled size limit for regex searches, so a pattern like `\w{1000}{1000}`
+/// is refused instead of taking all memory. Matching itself is linear in the
+/// text with the regex crate, so no pattern can backtrack catastrophically.
+const REGEX_SIZE_LIMIT: usize = 1 << 20;
+
+/// Most matches a regex search returns.
+const MAX_REGEX_MATCHES: usize = 500;
+
+/// `text` HTML-escaped with every non-empty match wrapped in `<mark>` tags,
+/// or `None` if nothing matches.
+fn mark_matches(regex: &Regex, text: &str) -> Option<String> {
+  let mut marked = String::with_capacity(text.len() + 16);
+  let mut last = 0;
+  for m in regex.find_iter(text).filter(|m| !m.is_empty()) {
+    marked.push_str(&export::html_escape(&text[last..m.start()]));
+    marked.push_str("<mark>");
+    marked.push_str(&export::html_escape(m.as_str()));
+    marked.push_str("</mark>");
+    last = m.end();
+  }
+  if last == 0 {
+    return None;
+  }
+  marked.push_str(&export::html_escape(&text[last..]));
+  Some(marked)
+}
+
+fn search_regex(
+  conn: &Connection,
+  regex: &Regex,
+  pdf_id: Option<i64>,
+) -> rusqlite::Result<Vec<HighlightMatch>> {
+  let mut stmt = conn.prepare(
+    "SELECT highlight_id, pdf_id, page_number, content_text, comment_text
+     FROM highlights
+     WHERE deleted_at IS NULL
+       AND (?1 IS NULL OR pdf_id = ?1)
+       AND (content_text IS NOT NULL OR comment_text IS NOT NULL)
+     ORDER BY pdf_id, page_number, id",
+  )?;
+  let mut rows = stmt.query(params![pdf_id])?;
+  let mut matches = Vec::new();
+  while let Some(row) = rows.next()? {
+    let texts: [Option<String>; 2] = [row.get(3)?, row.get(4)?];
+    let Some(snippet) = texts
+      .iter()
+      .flatten()
+      .find_map(|text| mark_matches(regex, text))
+    else {
+      continue;
+    };
+    matches.push(HighlightMatch {
+      highlight_id: row.get(0)?,
+      pdf_id: row.get(1)?,
+      page_number: row.get(2)?,
+      snippet,
+    });
+    if matches.len() == MAX_REGEX_MATCHES {
+      break;
+    }
+  }
+  Ok(matches)
+}
+
+/// Highlights whose text, or else comment, matches the regular expression
+/// `pattern`, optionally within one PDF. The snippet is the whole matching
+/// text with each match wrapped in `<mark>` tags, like `search_highlights`
+/// marks its hits. At most `MAX_REGEX_MATCHES` highlights are returned.
+#[tauri::command]
+pub async fn search_highlights_regex(
+  app: AppHandle,
+  pattern: String,
+  pdf_id: Option<i64>,
+) -> Result<Vec<HighlightMatch>, AppError> {
+  if pattern.len() > MAX_PATTERN_LEN {
+    return Err(AppError::InvalidInput(format!(
+      "Pattern is longer than {} characters",
+      MAX_PATTERN_LEN
+    )));
+  }
+  let regex = RegexBuilder::new(&pattern)
+    .size_limit(REGEX_SIZE_LIMIT)
+    .build()
+    .map_err(|e| AppError::InvalidInput(format!("Invalid pattern: {}", e)))?;
+
+  let conn = db::open(&app)?;
+  Ok(search_regex(&conn, &regex, pdf_id)?)
+}
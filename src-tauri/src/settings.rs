@@ -0,0 +1,52 @@
+use rusqlite::{params, Connection, OptionalExtension};
+use std::collections::HashMap;
+use tauri::AppHandle;
+
+use crate::db;
+use crate::error::AppError;
+
+pub fn get(conn: &Connection, key: &str) -> rusqlite::Result<Option<String>> {
+  conn
+    .query_row(
+      "SELECT value FROM settings WHERE key = ?1",
+      params![key],
+      |row| row.get::<_, Option<String>>(0),
+    )
+    .optional()
+    .map(Option::flatten)
+}
+
+pub fn set(conn: &Connection, key: &str, value: &str) -> rusqlite::Result<()> {
+  conn.execute(
+    "INSERT INTO settings (key, value) VALUES (?1, ?2)
+     ON CONFLICT(key) DO UPDATE SET value = excluded.value",
+    params![key, value],
+  )?;
+  Ok(())
+}
+
+#[tauri::command]
+pub async fn get_setting(app: AppHandle, key: String) -> Result<Option<String>, AppError> {
+  let conn = db::open(&app)?;
+  Ok(get(&conn, &key)?)
+}
+
+#[tauri::command]
+pub async fn set_setting(app: AppHandle, key: String, value: String) -> Result<(), AppError> {
+  if key.is_empty() {
+    return Err(AppError::InvalidInput(
+      "Setting key must not be empty".to_string(),
+    ));
+  }
+  let conn = db::open(&app)?;
+  Ok(set(&conn, &key, &value)?)
+}
+
+/// Every stored setting, for the frontend to load once at launch.
+#[tauri::command]
+pub async fn get_all_settings(app: AppHandle) -> Result<HashMap<String, String>, AppError> {
+  let conn = db::open(&app)?;
+  let mut stmt = conn.prepare("SELECT key, value FROM settings WHERE value IS NOT NULL")?;
+  let rows = stmt.query_map([], |row| Ok((row.get(0)?, row.get(1)?)))?;
+  Ok(rows.collect::<rusqlite::Result<HashMap<_, _>>>()?)
+}
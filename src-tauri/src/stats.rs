@@ -0,0 +1,282 @@
+use chrono::{Local, NaiveDate};
+use rusqlite::{params, Connection, OptionalExtension};
+use serde::Serialize;
+use tauri::{AppHandle, Runtime};
+
+use crate::db;
+use crate::error::AppError;
+
+#[derive(Debug, Serialize)]
+pub struct PdfHighlightCount {
+  pub pdf_id: i64,
+  pub name: String,
+  pub highlight_count: i64,
+}
+
+#[derive(Debug, Serialize)]
+pub struct TagCount {
+  pub tag_id: i64,
+  pub name: String,
+  pub highlight_count: i64,
+}
+
+#[derive(Debug, Serialize)]
+pub struct LibraryStats {
+  pub pdf_count: i64,
+  pub highlight_count: i64,
+  pub tag_count: i64,
+  /// Every PDF with its number of highlights, busiest first.
+  pub highlights_per_pdf: Vec<PdfHighlightCount>,
+  /// The ten most used tags.
+  pub top_tags: Vec<TagCount>,
+  /// Number of distinct (PDF, page) pairs with at least one highlight.
+  pub annotated_pages: i64,
+  /// Whether the database uses WAL journaling.
+  pub wal_enabled: bool,
+}
+
+const PDF_COUNT: &str = "SELECT COUNT(*) FROM pdfs";
+const HIGHLIGHT_COUNT: &str = "SELECT COUNT(*) FROM highlights WHERE deleted_at IS NULL";
+const TAG_COUNT: &str = "SELECT COUNT(*) FROM tags";
+
+fn count(conn: &Connection, sql: &str) -> rusqlite::Result<i64> {
+  conn.query_row(sql, [], |row| row.get(0))
+}
+
+fn library_stats(conn: &Connection) -> rusqlite::Result<LibraryStats> {
+  let highlights_per_pdf = conn
+    .prepare(
+      "SELECT p.id, p.name, COUNT(h.id) AS highlight_count
+       FROM pdfs p
+       LEFT JOIN highlights h ON h.pdf_id = p.id AND h.deleted_at IS NULL
+       GROUP BY p.id, p.name
+       ORDER BY highlight_count DESC, p.name ASC",
+    )?
+    .query_map([], |row| {
+      Ok(PdfHighlightCount {
+        pdf_id: row.get(0)?,
+        name: row.get(1)?,
+        highlight_count: row.get(2)?,
+      })
+    })?
+    .collect::<rusqlite::Result<Vec<_>>>()?;
+
+  let top_tags = conn
+    .prepare(
+      "SELECT t.id, t.name, COUNT(h.id) AS highlight_count
+       FROM tags t
+       JOIN highlight_tags ht ON ht.tag_id = t.id
+       JOIN highlights h ON h.highlight_id = ht.highlight_id AND h.deleted_at IS NULL
+       GROUP BY t.id, t.name
+       ORDER BY highlight_count DESC, t.name ASC
+       LIMIT 10",
+    )?
+    .query_map([], |row| {
+      Ok(TagCount {
+        tag_id: row.get(0)?,
+        name: row.get(1)?,
+        highlight_count: row.get(2)?,
+      })
+    })?
+    .collect::<rusqlite::Result<Vec<_>>>()?;
+
+  Ok(LibraryStats {
+    pdf_count: count(conn, PDF_COUNT)?,
+    highlight_count: count(conn, HIGHLIGHT_COUNT)?,
+    tag_count: count(conn, TAG_COUNT)?,
+    highlights_per_pdf,
+    top_tags,
+    annotated_pages: count(
+      conn,
+      "SELECT COUNT(*) FROM (
+         SELECT DISTINCT pdf_id, page_number FROM highlights WHERE deleted_at IS NULL
+       )",
+    )?,
+    wal_enabled: db::wal_enabled(conn)?,
+  })
+}
+
+#[tauri::command]
+pub async fn get_library_statistics(app: AppHandle) -> Result<LibraryStats, AppError> {
+  let conn = db::open(&app)?;
+  Ok(library_stats(&conn)?)
+}
+
+#[derive(Debug, Default, Serialize)]
+pub struct StreakInfo {
+  /// Consecutive days with highlights up to today. A streak that reached
+  /// yesterday still counts until today is over.
+  pub current_streak: i64,
+  pub longest_streak: i64,
+  /// Most recent day with a highlight, as `YYYY-MM-DD`.
+  pub last_active_date: Option<String>,
+}
+
+/// Walks the distinct, ascending days with highlights.
+fn streaks(days: &[NaiveDate], today: NaiveDate) -> StreakInfo {
+  let Some(&last) = days.last() else {
+    return StreakInfo::default();
+  };
+
+  let mut longest = 0;
+  let mut run = 0;
+  let mut previous: Option<NaiveDate> = None;
+  for &day in days {
+    run = match previous {
+      Some(previous) if day.signed_duration_since(previous).num_days() == 1 => run + 1,
+      _ => 1,
+    };
+    longest = longest.max(run);
+    previous = Some(day);
+  }
+
+  let alive = today.signed_duration_since(last).num_days() <= 1;
+  StreakInfo {
+    current_streak: if alive { run } else { 0 },
+    longest_streak: longest,
+    last_active_date: Some(last.format("%Y-%m-%d").to_string()),
+  }
+}
+
+fn active_days(conn: &Connection) -> rusqlite::Result<Vec<NaiveDate>> {
+  // created_at is UTC; bucket by the user's local day
+  let days = conn
+    .prepare(
+      "SELECT DISTINCT DATE(created_at, 'localtime') AS day FROM highlights
+       WHERE created_at IS NOT NULL
+       ORDER BY day",
+    )?
+    .query_map([], |row| row.get::<_, String>(0))?
+    .collect::<rusqlite::Result<Vec<_>>>()?;
+  Ok(
+    days
+      .iter()
+      .filter_map(|day| NaiveDate::parse_from_str(day, "%Y-%m-%d").ok())
+      .collect(),
+  )
+}
+
+/// Current and longest runs of consecutive days on which highlights were
+/// made. Highlights in the trash still count; the work was done.
+#[tauri::command]
+pub async fn annotation_streak(app: AppHandle) -> Result<StreakInfo, AppError> {
+  let conn = db::open(&app)?;
+  let days = active_days(&conn)?;
+  Ok(streaks(&days, Local::now().date_naive()))
+}
+
+/// Library size at one point in time, a row of `stats_snapshots`.
+#[derive(Debug, Serialize)]
+pub struct StatsSnapshot {
+  pub id: i64,
+  pub taken_at: String,
+  pub pdf_count: i64,
+  pub highlight_count: i64,
+  pub tag_count: i64,
+  /// Highlights gained (or lost, when negative) since the previous snapshot;
+  /// `None` for the first one ever taken.
+  pub highlight_change: Option<i64>,
+}
+
+/// Age in hours past which `snapshot_if_stale` takes a new snapshot.
+const SNAPSHOT_INTERVAL_HOURS: i64 = 24;
+
+fn take_snapshot(conn: &Connection) -> rusqlite::Result<()> {
+  conn.execute(
+    &format!(
+      "INSERT INTO stats_snapshots (pdf_count, highlight_count, tag_count)
+       VALUES (({}), ({}), ({}))",
+      PDF_COUNT, HIGHLIGHT_COUNT, TAG_COUNT
+    ),
+    [],
+  )?;
+  Ok(())
+}
+
+/// Records the current number of PDFs, highlights and tags for
+/// `stats_history`.
+#[tauri::command]
+pub async fn take_stats_snapshot(app: AppHandle) -> Result<(), AppError> {
+  let conn = db::open(&app)?;
+  Ok(take_snapshot(&conn)?)
+}
+
+/// Takes a snapshot when none was taken in the last day, so history builds up
+/// from launches alone. A library whose schema predates snapshots is left
+/// alone until the frontend has migrated it.
+pub fn snapshot_if_stale<R: Runtime>(app: &AppHandle<R>) -> Result<(), String> {
+  if !db::db_path(app)?.exists() {
+    return Ok(());
+  }
+  let conn = db::open(app)?;
+  let has_table = conn
+    .query_row(
+      "SELECT 1 FROM sqlite_master WHERE type = 'table' AND name = 'stats_snapshots'",
+      [],
+      |_| Ok(()),
+    )
+    .optional()
+    .map_err(|e| e.to_string())?
+    .is_some();
+  if !has_table {
+    return Ok(());
+  }
+  let recent = conn
+    .query_row(
+      "SELECT 1 FROM stats_snapshots
+       WHERE taken_at > DATETIME('now', ?1)",
+      params![format!("-{} hours", SNAPSHOT_INTERVAL_HOURS)],
+      |_| Ok(()),
+    )
+    .optional()
+    .map_err(|e| e.to_string())?
+    .is_some();
+  if !recent {
+    take_snapshot(&conn).map_err(|e| e.to_string())?;
+    log::info!("Took a library statistics snapshot");
+  }
+  Ok(())
+}
+
+fn history(conn: &Connection, limit: i64) -> rusqlite::Result<Vec<StatsSnapshot>> {
+  // One row past the limit gives the oldest returned snapshot its change
+  let mut snapshots = conn
+    .prepare(
+      "SELECT id, taken_at, pdf_count, highlight_count, tag_count FROM stats_snapshots
+       ORDER BY taken_at DESC, id DESC
+       LIMIT ?1",
+    )?
+    .query_map(params![limit.saturating_add(1)], |row| {
+      Ok(StatsSnapshot {
+        id: row.get(0)?,
+        taken_at: row.get(1)?,
+        pdf_count: row.get(2)?,
+        highlight_count: row.get(3)?,
+        tag_count: row.get(4)?,
+        highlight_change: None,
+      })
+    })?
+    .collect::<rusqlite::Result<Vec<_>>>()?;
+  snapshots.reverse();
+
+  for i in 1..snapshots.len() {
+    snapshots[i].highlight_change =
+      Some(snapshots[i].highlight_count - snapshots[i - 1].highlight_count);
+  }
+  if snapshots.len() as i64 > limit {
+    snapshots.remove(0);
+  }
+  Ok(snapshots)
+}
+
+/// The `limit` most recent snapshots, oldest first, ready to chart.
+#[tauri::command]
+pub async fn stats_history(app: AppHandle, limit: i64) -> Result<Vec<StatsSnapshot>, AppError> {
+  if limit < 1 {
+    return Err(AppError::InvalidInput(
+      "limit must be 1 or greater".to_string(),
+    ));
+  }
+  let conn = db::open(&app)?;
+  Ok(history(&conn, limit)?)
+}
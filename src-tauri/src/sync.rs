@@ -0,0 +1,155 @@
+//! Syncing the library between devices through a WebDAV server, using the
+//! bundle format of `export_library`.
+//!
+//! The remote side is a single bundle file. Pulling merges it into the local
+//! library, keeping whichever copy of a highlight was edited last. Pushing
+//! replaces the file, so it is refused when another device pushed since this
+//! one last synced; the UI should pull and push again.
+//!
+//! Only edits sync, deletions don't. Moving a highlight to the trash is an
+//! edit and reaches other devices like one, but rows that are gone for good
+//! (an emptied trash, a deleted PDF) are simply missing from a bundle, which
+//! a merge can't tell apart from rows the bundle never had. A pull therefore
+//! brings back rows deleted here that the server still has, and keeps local
+//! rows the server lacks, so a row deleted on one device returns with the
+//! next push from any device that still has it.
+
+use reqwest::{Client, Response, StatusCode};
+use serde::Deserialize;
+use std::time::Duration;
+use tauri::AppHandle;
+
+use crate::error::AppError;
+use crate::library::{self, ImportReport, ImportStrategy, LibraryBundle, Progress};
+use crate::{db, settings};
+
+/// Setting holding the `exported_at` of the remote bundle as of the last
+/// push or pull. It only guards pushes; merging doesn't consult it.
+const LAST_SYNC_KEY: &str = "sync_last_sync_at";
+
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(60);
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct WebdavConfig {
+  /// URL of the bundle file on the server, e.g.
+  /// `https://dav.example.com/highlights/library.json`.
+  pub url: String,
+  pub username: String,
+  pub password: String,
+}
+
+fn client() -> Result<Client, AppError> {
+  Ok(Client::builder().timeout(REQUEST_TIMEOUT).build()?)
+}
+
+/// Maps an error status so the UI can offer a retry for `Network` and re-ask
+/// for credentials on `Unauthorized`.
+fn check_status(response: Response) -> Result<Response, AppError> {
+  let status = response.status();
+  if status.is_success() {
+    return Ok(response);
+  }
+  let message = format!("Server responded with {}", status);
+  Err(match status {
+    StatusCode::UNAUTHORIZED | StatusCode::FORBIDDEN => AppError::Unauthorized(message),
+    StatusCode::NOT_FOUND => AppError::NotFound(message),
+    status if status.is_server_error() => AppError::Network(message),
+    _ => AppError::Failed(message),
+  })
+}
+
+/// The bundle on the server, or `None` if nothing was pushed yet.
+async fn download(
+  client: &Client,
+  config: &WebdavConfig,
+) -> Result<Option<LibraryBundle>, AppError> {
+  let response = client
+    .get(&config.url)
+    .basic_auth(&config.username, Some(&config.password))
+    .send()
+    .await?;
+  if response.status() == StatusCode::NOT_FOUND {
+    return Ok(None);
+  }
+  let body = check_status(response)?.bytes().await?;
+  let bundle: LibraryBundle = serde_json::from_slice(&body).map_err(|e| {
+    AppError::InvalidInput(format!("Remote file is not a valid library bundle: {}", e))
+  })?;
+  library::check_bundle(&bundle).map_err(AppError::InvalidInput)?;
+  Ok(Some(bundle))
+}
+
+fn last_sync_at(app: &AppHandle) -> Result<Option<String>, AppError> {
+  let conn = db::open(app)?;
+  Ok(settings::get(&conn, LAST_SYNC_KEY)?)
+}
+
+fn export(app: &AppHandle) -> Result<(Vec<u8>, String), AppError> {
+  let mut conn = db::open(app)?;
+  let tx = conn.transaction()?;
+  let mut body = Vec::new();
+  let exported_at = library::write_bundle_to(&tx, &mut body)?;
+  tx.commit()?;
+  Ok((body, exported_at))
+}
+
+/// Uploads the local library to the server, replacing the bundle there. Fails
+/// with `AppError::Conflict` when another device pushed since the last sync,
+/// or when the bundle there has no `exported_at` to tell, as with hand-made
+/// or legacy ones. `force` replaces it anyway.
+#[tauri::command]
+pub async fn sync_push(
+  app: AppHandle,
+  config: WebdavConfig,
+  force: Option<bool>,
+) -> Result<(), AppError> {
+  let client = client()?;
+  if !force.unwrap_or(false) {
+    if let Some(remote) = download(&client, &config).await? {
+      // A bundle this device hasn't seen would be lost by overwriting it
+      if remote.exported_at.is_none() || remote.exported_at != last_sync_at(&app)? {
+        return Err(AppError::Conflict(
+          "Another device pushed since the last sync, pull first".to_string(),
+        ));
+      }
+    }
+  }
+
+  let (body, exported_at) = export(&app)?;
+  let response = client
+    .put(&config.url)
+    .basic_auth(&config.username, Some(&config.password))
+    .header(reqwest::header::CONTENT_TYPE, "application/json")
+    .body(body)
+    .send()
+    .await?;
+  check_status(response)?;
+
+  let conn = db::open(&app)?;
+  settings::set(&conn, LAST_SYNC_KEY, &exported_at)?;
+  log::info!("Pushed library to {}", config.url);
+  Ok(())
+}
+
+/// Merges the bundle on the server into the local library. Highlights on
+/// both sides keep the copy with the later `updated_at`; ones missing on
+/// either side are kept, see the module docs on deletions.
+#[tauri::command]
+pub async fn sync_pull(app: AppHandle, config: WebdavConfig) -> Result<ImportReport, AppError> {
+  let client = client()?;
+  let bundle = download(&client, &config)
+    .await?
+    .ok_or_else(|| AppError::NotFound(format!("There is no library bundle at {}", config.url)))?;
+
+  let mut progress = Progress::new(&app, &bundle);
+  let mut conn = db::open(&app)?;
+  let tx = conn.transaction()?;
+  let report = library::import(&tx, &bundle, ImportStrategy::Newer, &mut progress)?;
+  if let Some(exported_at) = &bundle.exported_at {
+    settings::set(&tx, LAST_SYNC_KEY, exported_at)?;
+  }
+  tx.commit()?;
+
+  log::info!("Pulled library from {}: {:?}", config.url, report);
+  Ok(report)
+}
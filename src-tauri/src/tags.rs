@@ -0,0 +1,576 @@
+use rusqlite::{params, params_from_iter, Connection, OptionalExtension, Row};
+use serde::Serialize;
+use std::collections::{BTreeMap, HashMap};
+use tauri::AppHandle;
+
+use crate::actions::{self, Action};
+use crate::error::AppError;
+use crate::{color, db, pdfs};
+
+/// A row of the `tags` table, mirroring `Tag` in
+/// example/src/services/database.ts.
+#[derive(Debug, Clone, Serialize)]
+pub struct TagRecord {
+  pub id: i64,
+  pub name: String,
+  pub created_at: String,
+  pub color: Option<String>,
+}
+
+/// Column list matching `TagRecord::from_row`, prefixed with `t.` so it can be
+/// used in joins.
+pub const COLUMNS: &str = "t.id, t.name, t.created_at, t.color";
+
+impl TagRecord {
+  pub fn from_row(row: &Row) -> rusqlite::Result<Self> {
+    Ok(TagRecord {
+      id: row.get(0)?,
+      name: row.get(1)?,
+      created_at: row.get(2)?,
+      color: row.get(3)?,
+    })
+  }
+}
+
+#[derive(Debug, Serialize)]
+pub struct TagMergeResult {
+  pub merged: bool,
+  pub affected_highlights: usize,
+}
+
+fn tag_exists(conn: &Connection, tag_id: i64) -> rusqlite::Result<bool> {
+  conn
+    .query_row("SELECT 1 FROM tags WHERE id = ?1", params![tag_id], |_| {
+      Ok(())
+    })
+    .optional()
+    .map(|row| row.is_some())
+}
+
+fn link_count(conn: &Connection, tag_id: i64) -> rusqlite::Result<usize> {
+  conn.query_row(
+    "SELECT COUNT(*) FROM highlight_tags WHERE tag_id = ?1",
+    params![tag_id],
+    |row| row.get(0),
+  )
+}
+
+/// Re-points every highlight of `source` to `target` and deletes `source`.
+/// Highlights that already carry both tags keep a single link. Returns the
+/// number of highlight associations `source` had. Must run inside a
+/// transaction.
+fn merge_into(conn: &Connection, source: i64, target: i64) -> rusqlite::Result<usize> {
+  let moved = link_count(conn, source)?;
+  conn.execute(
+    "INSERT OR IGNORE INTO highlight_tags (highlight_id, tag_id)
+     SELECT highlight_id, ?2 FROM highlight_tags WHERE tag_id = ?1",
+    params![source, target],
+  )?;
+  conn.execute(
+    "UPDATE tag_usage_history SET tag_id = ?2 WHERE tag_id = ?1",
+    params![source, target],
+  )?;
+  conn.execute(
+    "DELETE FROM highlight_tags WHERE tag_id = ?1",
+    params![source],
+  )?;
+  conn.execute("DELETE FROM tags WHERE id = ?1", params![source])?;
+  Ok(moved)
+}
+
+/// Renames a tag. If another tag already has `new_name`, the two are merged
+/// into that existing tag instead of failing on the UNIQUE constraint.
+#[tauri::command]
+pub async fn rename_tag(
+  app: AppHandle,
+  tag_id: i64,
+  new_name: String,
+) -> Result<TagMergeResult, AppError> {
+  let new_name = new_name.trim();
+  if new_name.is_empty() {
+    return Err(AppError::InvalidInput(
+      "Tag name must not be empty".to_string(),
+    ));
+  }
+
+  let mut conn = db::open(&app)?;
+  let tx = conn.transaction()?;
+  if !tag_exists(&tx, tag_id)? {
+    return Err(AppError::NotFound(format!("Tag {} not found", tag_id)));
+  }
+
+  let existing: Option<i64> = tx
+    .query_row(
+      "SELECT id FROM tags WHERE name = ?1 AND id != ?2",
+      params![new_name, tag_id],
+      |row| row.get(0),
+    )
+    .optional()?;
+
+  let result = match existing {
+    Some(target) => {
+      let moved = merge_into(&tx, tag_id, target)?;
+      actions::record(
+        &tx,
+        &Action::MergeTags {
+          source_tag_ids: vec![tag_id],
+          target_tag_id: target,
+          moved,
+        },
+      )?;
+      TagMergeResult {
+        merged: true,
+        affected_highlights: moved,
+      }
+    }
+    None => {
+      tx.execute(
+        "UPDATE tags SET name = ?1 WHERE id = ?2",
+        params![new_name, tag_id],
+      )?;
+      TagMergeResult {
+        merged: false,
+        affected_highlights: link_count(&tx, tag_id)?,
+      }
+    }
+  };
+
+  tx.commit()?;
+  Ok(result)
+}
+
+/// Folds several tags into `target_tag_id` and returns how many highlight
+/// associations were moved onto it.
+#[tauri::command]
+pub async fn merge_tags(
+  app: AppHandle,
+  source_tag_ids: Vec<i64>,
+  target_tag_id: i64,
+) -> Result<usize, AppError> {
+  if source_tag_ids.contains(&target_tag_id) {
+    return Err(AppError::InvalidInput(
+      "The target tag cannot also be a source tag".to_string(),
+    ));
+  }
+  let mut source_tag_ids = source_tag_ids;
+  source_tag_ids.sort_unstable();
+  source_tag_ids.dedup();
+
+  let mut conn = db::open(&app)?;
+  let tx = conn.transaction()?;
+  for id in source_tag_ids.iter().chain(std::iter::once(&target_tag_id)) {
+    if !tag_exists(&tx, *id)? {
+      return Err(AppError::NotFound(format!("Tag {} not found", id)));
+    }
+  }
+
+  let mut moved = 0;
+  for source in &source_tag_ids {
+    moved += merge_into(&tx, *source, target_tag_id)?;
+  }
+  actions::record(
+    &tx,
+    &Action::MergeTags {
+      source_tag_ids,
+      target_tag_id,
+      moved,
+    },
+  )?;
+  tx.commit()?;
+  Ok(moved)
+}
+
+/// Id of the tag named `name`, creating it with the default color if there
+/// is none.
+pub fn get_or_create(conn: &Connection, name: &str) -> rusqlite::Result<i64> {
+  conn.execute(
+    "INSERT OR IGNORE INTO tags (name) VALUES (?1)",
+    params![name],
+  )?;
+  conn.query_row(
+    "SELECT id FROM tags WHERE name = ?1",
+    params![name],
+    |row| row.get(0),
+  )
+}
+
+/// Tags a highlight and records the use in the tag history. Returns false if
+/// the highlight already had the tag or isn't there to tag, being in the
+/// trash or nonexistent.
+pub fn link(conn: &Connection, tag_id: i64, highlight_id: &str) -> rusqlite::Result<bool> {
+  let linked = conn
+    .prepare_cached(
+      "INSERT OR IGNORE INTO highlight_tags (highlight_id, tag_id)
+         SELECT highlight_id, ?2 FROM highlights
+         WHERE highlight_id = ?1 AND deleted_at IS NULL",
+    )?
+    .execute(params![highlight_id, tag_id])?
+    == 1;
+  if linked {
+    conn
+      .prepare_cached("INSERT INTO tag_usage_history (tag_id, highlight_id) VALUES (?1, ?2)")?
+      .execute(params![tag_id, highlight_id])?;
+  }
+  Ok(linked)
+}
+
+fn get(conn: &Connection, tag_id: i64) -> rusqlite::Result<TagRecord> {
+  conn.query_row(
+    &format!("SELECT {} FROM tags t WHERE t.id = ?1", COLUMNS),
+    params![tag_id],
+    TagRecord::from_row,
+  )
+}
+
+/// Creates a tag, with the default color unless `color` is given. A name
+/// that is already taken yields `AppError::Conflict`.
+#[tauri::command]
+pub async fn create_tag(
+  app: AppHandle,
+  name: String,
+  color: Option<String>,
+) -> Result<TagRecord, AppError> {
+  let name = name.trim();
+  if name.is_empty() {
+    return Err(AppError::InvalidInput(
+      "Tag name must not be empty".to_string(),
+    ));
+  }
+  if let Some(color) = &color {
+    color::validate_hex(color).map_err(AppError::InvalidInput)?;
+  }
+
+  let conn = db::open(&app)?;
+  // The UNIQUE constraint on the name is what reports duplicates
+  let inserted = match &color {
+    Some(color) => conn.execute(
+      "INSERT INTO tags (name, color) VALUES (?1, ?2)",
+      params![name, color],
+    ),
+    None => conn.execute("INSERT INTO tags (name) VALUES (?1)", params![name]),
+  };
+  inserted.map_err(|e| match AppError::from(e) {
+    AppError::Conflict(_) => AppError::Conflict(format!("Tag \"{}\" already exists", name)),
+    e => e,
+  })?;
+  Ok(get(&conn, conn.last_insert_rowid())?)
+}
+
+#[tauri::command]
+pub async fn set_tag_color(app: AppHandle, tag_id: i64, color: String) -> Result<(), AppError> {
+  color::validate_hex(&color).map_err(AppError::InvalidInput)?;
+
+  let conn = db::open(&app)?;
+  let updated = conn.execute(
+    "UPDATE tags SET color = ?1 WHERE id = ?2",
+    params![color, tag_id],
+  )?;
+  if updated == 0 {
+    return Err(AppError::NotFound(format!("Tag {} not found", tag_id)));
+  }
+  Ok(())
+}
+
+/// Form tag names are normalized to: trimmed, lowercased and with runs of
+/// whitespace collapsed to one space.
+fn canonical_name(name: &str) -> String {
+  name
+    .split_whitespace()
+    .collect::<Vec<_>>()
+    .join(" ")
+    .to_lowercase()
+}
+
+#[derive(Debug, Default, Serialize)]
+pub struct NormalizeReport {
+  /// Tags folded into another tag and deleted.
+  pub merged_tags: usize,
+  /// Tags whose name was rewritten to its canonical form.
+  pub renamed_tags: usize,
+  /// Highlight associations moved from merged tags.
+  pub affected_highlights: usize,
+}
+
+fn normalize(conn: &Connection) -> rusqlite::Result<NormalizeReport> {
+  let tags = conn
+    .prepare("SELECT id, name FROM tags ORDER BY id")?
+    .query_map([], |row| {
+      Ok((row.get::<_, i64>(0)?, row.get::<_, String>(1)?))
+    })?
+    .collect::<rusqlite::Result<Vec<_>>>()?;
+
+  let mut groups: BTreeMap<String, Vec<(i64, String)>> = BTreeMap::new();
+  for (id, name) in tags {
+    groups
+      .entry(canonical_name(&name))
+      .or_default()
+      .push((id, name));
+  }
+
+  let mut report = NormalizeReport::default();
+  for (canonical, tags) in groups {
+    if canonical.is_empty() {
+      continue;
+    }
+    // Keep the tag already spelled canonically, otherwise the oldest
+    let target = tags
+      .iter()
+      .find(|(_, name)| *name == canonical)
+      .unwrap_or(&tags[0])
+      .0;
+    for &(id, _) in tags.iter().filter(|(id, _)| *id != target) {
+      report.affected_highlights += merge_into(conn, id, target)?;
+      report.merged_tags += 1;
+    }
+    // Only now that the other spellings are gone can the name be taken
+    // without hitting the UNIQUE constraint.
+    let renamed = conn.execute(
+      "UPDATE tags SET name = ?1 WHERE id = ?2 AND name != ?1",
+      params![canonical, target],
+    )?;
+    report.renamed_tags += renamed;
+  }
+  Ok(report)
+}
+
+/// Merges tags whose names differ only in case or whitespace, e.g. "Rust",
+/// "rust" and " RUST", into one tag with the canonical name.
+#[tauri::command]
+pub async fn normalize_tag_names(app: AppHandle) -> Result<NormalizeReport, AppError> {
+  let mut conn = db::open(&app)?;
+  let tx = conn.transaction()?;
+  let report = normalize(&tx)?;
+  tx.commit()?;
+  log::info!("Normalized tag names: {:?}", report);
+  Ok(report)
+}
+
+/// Tags every highlight in `highlight_ids` with `tag_id` and returns how many
+/// new links were created. Highlights that already carry the tag, are in the
+/// trash or don't exist are skipped.
+#[tauri::command]
+pub async fn add_tag_to_highlights(
+  app: AppHandle,
+  tag_id: i64,
+  highlight_ids: Vec<String>,
+) -> Result<usize, AppError> {
+  let mut conn = db::open(&app)?;
+  let tx = conn.transaction()?;
+  if !tag_exists(&tx, tag_id)? {
+    return Err(AppError::NotFound(format!("Tag {} not found", tag_id)));
+  }
+
+  let mut linked = 0;
+  for highlight_id in &highlight_ids {
+    if link(&tx, tag_id, highlight_id)? {
+      linked += 1;
+    }
+  }
+  tx.commit()?;
+
+  Ok(linked)
+}
+
+/// Removes `tag_id` from every highlight in `highlight_ids` and returns how
+/// many links were deleted.
+#[tauri::command]
+pub async fn remove_tag_from_highlights(
+  app: AppHandle,
+  tag_id: i64,
+  highlight_ids: Vec<String>,
+) -> Result<usize, AppError> {
+  let mut conn = db::open(&app)?;
+  let tx = conn.transaction()?;
+  if !tag_exists(&tx, tag_id)? {
+    return Err(AppError::NotFound(format!("Tag {} not found", tag_id)));
+  }
+
+  let mut unlinked = 0;
+  {
+    let mut unlink =
+      tx.prepare("DELETE FROM highlight_tags WHERE highlight_id = ?1 AND tag_id = ?2")?;
+    for highlight_id in &highlight_ids {
+      unlinked += unlink.execute(params![highlight_id, tag_id])?;
+    }
+  }
+  tx.commit()?;
+  Ok(unlinked)
+}
+
+/// Sets the color of every highlight carrying `tag_id`, trashed ones aside,
+/// and returns how many changed color.
+#[tauri::command]
+pub async fn recolor_by_tag(app: AppHandle, tag_id: i64, color: String) -> Result<usize, AppError> {
+  color::validate_hex(&color).map_err(AppError::InvalidInput)?;
+  let conn = db::open(&app)?;
+  if !tag_exists(&conn, tag_id)? {
+    return Err(AppError::NotFound(format!("Tag {} not found", tag_id)));
+  }
+  let recolored = conn.execute(
+    "UPDATE highlights SET color = ?1
+     WHERE deleted_at IS NULL AND color IS NOT ?1
+       AND highlight_id IN (SELECT highlight_id FROM highlight_tags WHERE tag_id = ?2)",
+    params![color, tag_id],
+  )?;
+  log::info!("Recolored {} highlights tagged {}", recolored, tag_id);
+  Ok(recolored)
+}
+
+/// Tags of one highlight, sorted by name.
+pub fn for_highlight(conn: &Connection, highlight_id: &str) -> rusqlite::Result<Vec<TagRecord>> {
+  let mut stmt = conn.prepare(&format!(
+    "SELECT {} FROM highlight_tags ht
+     JOIN tags t ON t.id = ht.tag_id
+     WHERE ht.highlight_id = ?1
+     ORDER BY t.name",
+    COLUMNS
+  ))?;
+  let rows = stmt.query_map(params![highlight_id], TagRecord::from_row)?;
+  rows.collect()
+}
+
+/// Highlight ids bound per query by `tags_for_highlights`, well under
+/// SQLite's limit on bound parameters.
+const IDS_PER_QUERY: usize = 500;
+
+fn for_highlights(
+  conn: &Connection,
+  highlight_ids: &[String],
+) -> rusqlite::Result<HashMap<String, Vec<TagRecord>>> {
+  let mut tags: HashMap<String, Vec<TagRecord>> = highlight_ids
+    .iter()
+    .map(|id| (id.clone(), Vec::new()))
+    .collect();
+  for batch in highlight_ids.chunks(IDS_PER_QUERY) {
+    let placeholders = vec!["?"; batch.len()].join(", ");
+    let mut stmt = conn.prepare(&format!(
+      "SELECT ht.highlight_id, {} FROM highlight_tags ht
+       JOIN tags t ON t.id = ht.tag_id
+       WHERE ht.highlight_id IN ({})
+       ORDER BY t.name",
+      COLUMNS, placeholders
+    ))?;
+    let mut rows = stmt.query(params_from_iter(batch))?;
+    while let Some(row) = rows.next()? {
+      let highlight_id: String = row.get(0)?;
+      let tag = TagRecord {
+        id: row.get(1)?,
+        name: row.get(2)?,
+        created_at: row.get(3)?,
+        color: row.get(4)?,
+      };
+      tags.entry(highlight_id).or_default().push(tag);
+    }
+  }
+  Ok(tags)
+}
+
+/// Tags of each highlight in `highlight_ids`, sorted by name, so a list of
+/// highlights can show its tags without a query per highlight. Every
+/// requested id is present in the result, with no tags if it has none.
+#[tauri::command]
+pub async fn tags_for_highlights(
+  app: AppHandle,
+  highlight_ids: Vec<String>,
+) -> Result<HashMap<String, Vec<TagRecord>>, AppError> {
+  let conn = db::open(&app)?;
+  Ok(for_highlights(&conn, &highlight_ids)?)
+}
+
+#[derive(Debug, Serialize)]
+pub struct TagCount {
+  #[serde(flatten)]
+  pub tag: TagRecord,
+  /// Highlights carrying the tag, not counting the trash.
+  pub highlight_count: i64,
+}
+
+fn usage_counts(conn: &Connection) -> rusqlite::Result<Vec<TagCount>> {
+  let mut stmt = conn.prepare(&format!(
+    "SELECT {}, COUNT(h.id)
+     FROM tags t
+     LEFT JOIN highlight_tags ht ON ht.tag_id = t.id
+     LEFT JOIN highlights h ON h.highlight_id = ht.highlight_id AND h.deleted_at IS NULL
+     GROUP BY t.id
+     ORDER BY COUNT(h.id) DESC, t.name",
+    COLUMNS
+  ))?;
+  let rows = stmt.query_map([], |row| {
+    Ok(TagCount {
+      tag: TagRecord::from_row(row)?,
+      highlight_count: row.get(4)?,
+    })
+  })?;
+  rows.collect()
+}
+
+/// Every tag with the number of highlights carrying it, unused tags
+/// included, most used first.
+#[tauri::command]
+pub async fn tag_usage_counts(app: AppHandle) -> Result<Vec<TagCount>, AppError> {
+  let conn = db::open(&app)?;
+  Ok(usage_counts(&conn)?)
+}
+
+#[derive(Debug, Serialize)]
+pub struct TagPage {
+  pub tags: Vec<TagRecord>,
+  /// Number of tags matching the query across all pages.
+  pub total: i64,
+}
+
+fn list_page(
+  conn: &Connection,
+  query: Option<&str>,
+  limit: i64,
+  offset: i64,
+) -> rusqlite::Result<TagPage> {
+  // A plain LIKE on a bound pattern, without an OR or an appended wildcard,
+  // is what lets the prefix seek the NOCASE name index.
+  let pattern = query.map(|query| format!("{}%", pdfs::escape_like(query)));
+  let filter = match pattern {
+    Some(_) => "t.name LIKE ?1 ESCAPE '\\'",
+    None => "?1 IS NULL",
+  };
+  let total = conn.query_row(
+    &format!("SELECT COUNT(*) FROM tags t WHERE {}", filter),
+    params![pattern],
+    |row| row.get(0),
+  )?;
+  let mut stmt = conn.prepare(&format!(
+    "SELECT {} FROM tags t
+     WHERE {}
+     ORDER BY t.name COLLATE NOCASE, t.id
+     LIMIT ?2 OFFSET ?3",
+    COLUMNS, filter
+  ))?;
+  let tags = stmt
+    .query_map(params![pattern, limit, offset], TagRecord::from_row)?
+    .collect::<rusqlite::Result<Vec<_>>>()?;
+  Ok(TagPage { tags, total })
+}
+
+/// One page of tags in alphabetical order, optionally narrowed to names
+/// starting with `query` (case-insensitive), for the tag picker's
+/// autocomplete.
+#[tauri::command]
+pub async fn list_tags(
+  app: AppHandle,
+  query: Option<String>,
+  limit: i64,
+  offset: i64,
+) -> Result<TagPage, AppError> {
+  if limit < 1 {
+    return Err(AppError::InvalidInput(
+      "limit must be 1 or greater".to_string(),
+    ));
+  }
+  if offset < 0 {
+    return Err(AppError::InvalidInput(
+      "offset must not be negative".to_string(),
+    ));
+  }
+  let query = query.as_deref().map(str::trim).filter(|q| !q.is_empty());
+  let conn = db::open(&app)?;
+  Ok(list_page(&conn, query, limit, offset)?)
+}
@@ -0,0 +1,153 @@
+use notify_debouncer_mini::notify::{RecommendedWatcher, RecursiveMode};
+use notify_debouncer_mini::{new_debouncer, DebounceEventResult, DebouncedEventKind, Debouncer};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::thread;
+use std::time::Duration;
+use tauri::{AppHandle, Emitter, Manager, Runtime};
+
+use crate::error::AppError;
+use crate::{db, pdfs, settings};
+
+/// Event emitted with the new `PdfRecord` whenever the watcher imports a file.
+pub const PDF_ADDED_EVENT: &str = "pdf-added";
+
+/// Setting holding the directory new PDFs are auto-imported from.
+const WATCH_DIRECTORY_KEY: &str = "watch_directory";
+
+/// Events for the same file closer together than this are reported once.
+const DEBOUNCE: Duration = Duration::from_secs(2);
+
+/// A file whose size still changes across this interval is assumed to be in
+/// the middle of a copy and is left for a later event.
+const SETTLE: Duration = Duration::from_millis(500);
+
+/// The running watcher, if a directory is configured. Dropping it stops the
+/// background thread.
+#[derive(Default)]
+pub struct WatcherState(Mutex<Option<Debouncer<RecommendedWatcher>>>);
+
+/// The watched directory, read in `setup()` before the frontend has loaded
+/// the database. A library that doesn't exist yet or predates the settings
+/// table has none.
+fn saved_directory<R: Runtime>(app: &AppHandle<R>) -> Option<PathBuf> {
+  if !db::db_path(app).ok()?.exists() {
+    return None;
+  }
+  let conn = db::open(app).ok()?;
+  let saved = settings::get(&conn, WATCH_DIRECTORY_KEY).ok()??;
+  let saved = saved.trim();
+  (!saved.is_empty()).then(|| PathBuf::from(saved))
+}
+
+fn is_pdf(path: &Path) -> bool {
+  path
+    .extension()
+    .is_some_and(|ext| ext.eq_ignore_ascii_case("pdf"))
+}
+
+/// True once the file exists and its size stopped changing.
+fn is_settled(path: &Path) -> bool {
+  let Ok(before) = fs::metadata(path) else {
+    return false;
+  };
+  thread::sleep(SETTLE);
+  match fs::metadata(path) {
+    Ok(after) => after.is_file() && after.len() == before.len(),
+    Err(_) => false,
+  }
+}
+
+/// Adds `path` to the library unless the same document is already there.
+fn import<R: Runtime>(app: &AppHandle<R>, path: &Path) -> Result<(), AppError> {
+  let path_str = path.to_string_lossy();
+  let sha256 = pdfs::sha256_file(path)
+    .map_err(|e| AppError::Io(format!("Cannot read PDF {}: {}", path_str, e)))?;
+
+  let conn = db::open(app)?;
+  if pdfs::find_existing(&conn, &path_str, &sha256)?.is_some() {
+    return Ok(());
+  }
+  let info = pdfs::document_info(path).unwrap_or_else(|e| {
+    log::warn!("Cannot read the metadata of {}: {}", path_str, e);
+    Default::default()
+  });
+  let id = pdfs::register(&conn, &path_str, &sha256, &info)?;
+
+  let record =
+    pdfs::get(&conn, id)?.ok_or_else(|| AppError::NotFound(format!("PDF {} not found", id)))?;
+
+  log::info!("Imported {} from the watched directory", path_str);
+  app
+    .emit(PDF_ADDED_EVENT, &record)
+    .map_err(|e| AppError::Failed(e.to_string()))
+}
+
+fn handle_events<R: Runtime>(app: &AppHandle<R>, result: DebounceEventResult) {
+  let events = match result {
+    Ok(events) => events,
+    Err(e) => {
+      log::warn!("PDF watcher error: {}", e);
+      return;
+    }
+  };
+  for event in events {
+    // `AnyContinuous` means the file kept changing for the whole debounce
+    // window; a final `Any` follows once it goes quiet.
+    if event.kind != DebouncedEventKind::Any || !is_pdf(&event.path) {
+      continue;
+    }
+    if !is_settled(&event.path) {
+      continue;
+    }
+    if let Err(e) = import(app, &event.path) {
+      log::warn!("Cannot import {}: {}", event.path.display(), e);
+    }
+  }
+}
+
+fn watch<R: Runtime>(app: &AppHandle<R>, dir: &Path) -> Result<(), AppError> {
+  let handle = app.clone();
+  let mut debouncer = new_debouncer(DEBOUNCE, move |result| handle_events(&handle, result))
+    .map_err(|e| AppError::Failed(format!("Cannot start the PDF watcher: {}", e)))?;
+  debouncer
+    .watcher()
+    .watch(dir, RecursiveMode::NonRecursive)
+    .map_err(|e| AppError::Io(format!("Cannot watch {}: {}", dir.display(), e)))?;
+
+  let state = app.state::<WatcherState>();
+  *state.0.lock().unwrap() = Some(debouncer);
+  Ok(())
+}
+
+/// Starts watching the directory saved by `set_watch_directory`, if any.
+/// Called from `setup()`; failures are logged rather than aborting startup.
+pub fn start<R: Runtime>(app: &AppHandle<R>) {
+  app.manage(WatcherState::default());
+  if let Some(dir) = saved_directory(app) {
+    if let Err(e) = watch(app, &dir) {
+      log::warn!("PDF watcher not started: {}", e);
+    }
+  }
+}
+
+/// Sets the folder new PDFs are auto-imported from and restarts the watcher
+/// on it. Files already in the folder are not imported.
+#[tauri::command]
+pub async fn set_watch_directory(app: AppHandle, path: String) -> Result<(), AppError> {
+  let dir = PathBuf::from(&path);
+  if !dir.is_dir() {
+    return Err(AppError::InvalidInput(format!(
+      "{} is not a directory",
+      path
+    )));
+  }
+
+  // Stop the old watcher first so a failure below doesn't leave both running.
+  app.state::<WatcherState>().0.lock().unwrap().take();
+  watch(&app, &dir)?;
+
+  let conn = db::open(&app)?;
+  Ok(settings::set(&conn, WATCH_DIRECTORY_KEY, &path)?)
+}
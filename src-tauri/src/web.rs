@@ -0,0 +1,150 @@
+//! Interop with the web version of react-pdf-highlighter, whose highlights
+//! are shared as a JSON `IHighlight[]` array (see src/types.ts).
+
+use rusqlite::params;
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+use tauri::AppHandle;
+
+use crate::error::AppError;
+use crate::highlights::HighlightRecord;
+use crate::position::ScaledPosition;
+use crate::{db, highlights, images, pdfs, position, render};
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct WebContent {
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub text: Option<String>,
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub image: Option<String>,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct WebComment {
+  #[serde(default)]
+  pub text: String,
+  #[serde(default)]
+  pub emoji: String,
+}
+
+/// Mirror of `IHighlight`. `id` may be missing or empty in files written by
+/// hand or by other tools.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct WebHighlight {
+  #[serde(default)]
+  pub id: String,
+  #[serde(default)]
+  pub content: WebContent,
+  pub position: ScaledPosition,
+  #[serde(default)]
+  pub comment: WebComment,
+}
+
+fn non_empty(value: String) -> Option<String> {
+  if value.is_empty() {
+    None
+  } else {
+    Some(value)
+  }
+}
+
+/// Adds highlights exported from the web version to a PDF and returns how
+/// many were imported. Highlights without an `id` get a fresh one; those
+/// whose `id` is already in the library were imported before and are
+/// skipped. Nothing is imported if any highlight is invalid.
+#[tauri::command]
+pub async fn import_web_highlights(
+  app: AppHandle,
+  pdf_id: i64,
+  json: String,
+) -> Result<usize, AppError> {
+  let web: Vec<WebHighlight> = serde_json::from_str(&json)
+    .map_err(|e| AppError::InvalidInput(format!("Not an IHighlight[] array: {}", e)))?;
+
+  let mut conn = db::open(&app)?;
+  let pdf = pdfs::get(&conn, pdf_id)?
+    .ok_or_else(|| AppError::NotFound(format!("PDF {} not found", pdf_id)))?;
+  // A document that can't be read right now only loses the page range check
+  let page_count = render::page_count(&app, &pdf.path).ok();
+
+  let tx = conn.transaction()?;
+  let mut imported = 0;
+  for (i, highlight) in web.into_iter().enumerate() {
+    let position = serde_json::to_string(&highlight.position)?;
+    let position = position::validate(&position, page_count)
+      .map_err(|e| AppError::InvalidInput(format!("Highlight {}: {}", i, e)))?;
+    let highlight_id = match non_empty(highlight.id) {
+      Some(id) => id,
+      None => highlights::new_highlight_id(),
+    };
+
+    let inserted = tx.execute(
+      "INSERT INTO highlights (pdf_id, highlight_id, content_text, content_image,
+         comment_text, comment_emoji, position_data, page_number)
+       VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8)
+       ON CONFLICT(highlight_id) DO NOTHING",
+      params![
+        pdf_id,
+        highlight_id,
+        highlight.content.text,
+        highlight.content.image,
+        non_empty(highlight.comment.text),
+        non_empty(highlight.comment.emoji),
+        serde_json::to_string(&position)?,
+        position.page_number,
+      ],
+    )?;
+    imported += inserted;
+  }
+  tx.commit()?;
+  log::info!("Imported {} web highlights into PDF {}", imported, pdf_id);
+  Ok(imported)
+}
+
+impl WebHighlight {
+  fn from_record(record: HighlightRecord, images_dir: Option<&Path>) -> Result<Self, AppError> {
+    let position = position::parse(&record.position_data).map_err(|e| {
+      AppError::Corrupt(format!(
+        "Highlight {} has an unreadable position: {}",
+        record.highlight_id, e
+      ))
+    })?;
+    // The web version only knows inline images
+    let image = match &record.content_image_path {
+      Some(file) if record.content_image.is_none() => Some(
+        images::inline(images_dir, file)
+          .map_err(|e| AppError::Corrupt(format!("Highlight {}: {}", record.highlight_id, e)))?,
+      ),
+      _ => record.content_image,
+    };
+    Ok(WebHighlight {
+      id: record.highlight_id,
+      content: WebContent {
+        text: record.content_text,
+        image,
+      },
+      position,
+      comment: WebComment {
+        text: record.comment_text.unwrap_or_default(),
+        emoji: record.comment_emoji.unwrap_or_default(),
+      },
+    })
+  }
+}
+
+/// Writes a PDF's highlights as the `IHighlight[]` JSON the web version
+/// loads, the reverse of `import_web_highlights`. Trashed highlights are
+/// left out, as are colors and tags, which the web version doesn't have.
+#[tauri::command]
+pub async fn export_web_highlights(app: AppHandle, pdf_id: i64) -> Result<String, AppError> {
+  let conn = db::open(&app)?;
+  if pdfs::get(&conn, pdf_id)?.is_none() {
+    return Err(AppError::NotFound(format!("PDF {} not found", pdf_id)));
+  }
+  let images_dir = images::images_dir(&conn)?;
+  let web = highlights::list_for_pdf(&conn, pdf_id)?
+    .into_iter()
+    .map(|record| WebHighlight::from_record(record, images_dir.as_deref()))
+    .collect::<Result<Vec<_>, _>>()?;
+  Ok(serde_json::to_string(&web)?)
+}